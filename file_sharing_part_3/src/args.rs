@@ -1,11 +1,21 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, num::NonZeroUsize, path::PathBuf};
 
-use clap::Parser;
-use libp2p::Multiaddr;
+use clap::{ArgGroup, Parser};
+use libp2p::{Multiaddr, PeerId};
+
+use crate::output::OutputFormat;
 
 #[derive(Debug, Parser)]
 #[clap(name = "P2P File Sharing")]
 pub struct Opt {
+    // 配置文件路径，命令行参数覆盖配置文件中的值
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    // 输出格式，json格式下每行输出一个JSON对象；默认为text
+    #[clap(long, value_enum)]
+    pub output: Option<OutputFormat>,
+
     // 生成密钥对的种子
     #[clap(long)]
     pub secret_key_seed: Option<u8>,
@@ -18,6 +28,86 @@ pub struct Opt {
     #[clap(long)]
     pub listen_address: Option<Multiaddr>,
 
+    // 作为中继服务器，为无法直接连接的节点转发连接
+    #[clap(long)]
+    pub relay_server: bool,
+
+    // 中继节点地址，需包含节点ID；在中继上预留位置，通过中继接受连接
+    #[clap(long)]
+    pub relay: Option<Multiaddr>,
+
+    // 私有网络的预共享密钥文件，只与持有相同密钥的节点建立连接
+    #[clap(long)]
+    pub swarm_key: Option<PathBuf>,
+
+    // 始终保持连接的节点地址，需包含节点ID，可以指定多次；断开后自动重连
+    #[clap(long)]
+    pub sticky_peer: Vec<Multiaddr>,
+
+    // 全局上传速率上限(字节/秒)
+    #[clap(long)]
+    pub upload_rate: Option<u64>,
+
+    // 每个节点的上传速率上限(字节/秒)
+    #[clap(long)]
+    pub peer_upload_rate: Option<u64>,
+
+    // 同时进行的上传数量上限，默认为8
+    #[clap(long)]
+    pub max_concurrent_uploads: Option<usize>,
+
+    // 已建立的连接总数上限，默认为256
+    #[clap(long)]
+    pub max_connections: Option<u32>,
+
+    // 与单个节点的连接数上限，默认为4
+    #[clap(long)]
+    pub max_connections_per_peer: Option<u32>,
+
+    // 正在建立的入站连接数上限，默认为64
+    #[clap(long)]
+    pub max_pending_incoming: Option<u32>,
+
+    // 正在建立的出站连接数上限，默认为64
+    #[clap(long)]
+    pub max_pending_outgoing: Option<u32>,
+
+    // 连接空闲多少秒后关闭，默认为10
+    #[clap(long)]
+    pub idle_timeout: Option<u64>,
+
+    // 保存已知节点地址的文件，重启后未指定 --peer 时仍可重新加入网络
+    #[clap(long)]
+    pub address_book: Option<PathBuf>,
+
+    // 保存节点评分的文件，评分在多次运行之间保留
+    #[clap(long)]
+    pub reputation_file: Option<PathBuf>,
+
+    // 评分低于该值的节点被封禁，默认为-50
+    #[clap(long, allow_hyphen_values = true)]
+    pub ban_threshold: Option<f64>,
+
+    // 提供文件时启动本地HTTP网关，默认只监听本机地址，指定地址时使用 --gateway=<地址>
+    #[clap(long, min_values = 0, max_values = 1, require_equals = true, default_missing_value = "127.0.0.1:8080")]
+    pub gateway: Option<SocketAddr>,
+
+    // 网关的POST /share只能共享该目录中的文件，可以指定多次；共享的文件和监视的目录总是可以共享
+    #[clap(long)]
+    pub share_root: Vec<PathBuf>,
+
+    // 在本地地址上导出Prometheus格式的运行指标，默认监听127.0.0.1:9090，指定地址时使用 --metrics=<地址>
+    #[clap(long, min_values = 0, max_values = 1, require_equals = true, default_missing_value = "127.0.0.1:9090")]
+    pub metrics: Option<SocketAddr>,
+
+    // 日志级别或过滤规则，如debug、file_sharing=debug,libp2p_kad=trace；未指定时使用RUST_LOG环境变量，默认为info
+    #[clap(long)]
+    pub log_level: Option<String>,
+
+    // 同时以JSON格式将日志追加写入该文件，每行一个事件
+    #[clap(long)]
+    pub log_file: Option<PathBuf>,
+
     // 子命令
     #[clap(subcommand)]
     pub argument: CliArgument,
@@ -25,16 +115,70 @@ pub struct Opt {
 
 #[derive(Debug, Parser)]
 pub enum CliArgument {
-    // 提供文件子命令
+    // 提供文件子命令，未指定文件或目录时共享配置文件中的文件
+    #[clap(alias = "share", group(ArgGroup::new("source").args(&["path", "watch"])))]
     Provide {
         #[clap(long)]
-        path: PathBuf, // 文件全路径
+        path: Option<PathBuf>, // 文件全路径
         #[clap(long)]
-        name: String, // 文件名称
+        watch: Option<PathBuf>, // 监视的目录，共享其中出现和修改的文件，撤回已删除的文件
+        #[clap(long, requires = "source")]
+        name: Option<String>, // 文件名称，默认使用文件名；监视目录时作为文件名称的前缀
+        #[clap(long, requires = "source", conflicts_with = "require-token")]
+        allow: Vec<PeerId>, // 允许获取文件的节点，未指定时任何节点都可以获取
+        #[clap(long, requires = "source")]
+        require_token: bool, // 只允许持有本节点签发的访问令牌的节点获取
+        #[clap(long, requires = "source")]
+        recipient: Vec<PeerId>, // 为这些节点加密文件内容，只有接收者可以解密
     },
-    // 获取文件内容子命令
+    // 获取文件内容子命令，可以同时获取多个文件
     Get {
+        #[clap(long, required_unless_present = "list")]
+        name: Vec<String>, // 文件名称，可以指定多次
+        #[clap(long)]
+        list: Option<PathBuf>, // 文件名称列表，每行一个，忽略空行和以#开头的行
+        #[clap(long, default_value = "4")]
+        jobs: NonZeroUsize, // 同时下载的文件数量上限
+        #[clap(long)]
+        token: Option<String>, // 十六进制编码的访问令牌
+        #[clap(long)]
+        output: Option<PathBuf>, // 保存文件的路径，获取多个文件时必须是已有目录；`-` 表示原样写到标准输出；未指定时输出文件内容
+        #[clap(long, requires = "output")]
+        force: bool, // 覆盖已存在的文件
+    },
+    // 只根据节点ID连接节点子命令，地址通过地址簿和DHT查找
+    Connect {
+        #[clap(long)]
+        peer: PeerId, // 节点ID
+    },
+    // 签发访问令牌子命令
+    Grant {
         #[clap(long)]
         name: String, // 文件名称
+        #[clap(long)]
+        peer: PeerId, // 被授权的节点ID
+        #[clap(long)]
+        valid_for: Option<u64>, // 令牌的有效期(秒)，未指定时长期有效
+    },
+    // 发布名称记录子命令
+    Publish {
+        #[clap(long)]
+        target: String, // 名称指向的文件名称
+    },
+    // 解析名称记录子命令
+    Resolve {
+        #[clap(long)]
+        publisher: PeerId, // 发布者节点ID
+    },
+    // 配置文件子命令
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
     },
 }
+
+#[derive(Debug, Parser)]
+pub enum ConfigAction {
+    // 检查配置文件，输出合并命令行参数后的设置
+    Check,
+}
@@ -1,9 +1,14 @@
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, ops::Range};
 
-use libp2p::{request_response::ResponseChannel, Multiaddr, PeerId};
+use libp2p::{
+    autonat::NatStatus,
+    request_response::{RequestId, ResponseChannel},
+    Multiaddr, PeerId,
+};
 use tokio::sync::oneshot;
+use tracing::Span;
 
-use crate::network::FileResponse;
+use crate::network::{access::AccessPolicy, transfer::{DownloadedFile, Manifest}, FileResponse};
 
 #[derive(Debug)]
 pub enum Command {
@@ -14,42 +19,98 @@ pub enum Command {
         // 用于发送命令执行状态的通道
         sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
     },
-    // 链接给定节点命令
+    // 链接给定节点命令，同时尝试给定地址和已知地址
     Dial {
         // 节点ID
         peer_id: PeerId,
-        // 节点地址
-        peer_addr: Multiaddr,
-        // 用于发送命令执行状态的通道
-        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+        // 节点地址，可以为空
+        peer_addrs: Vec<Multiaddr>,
+        // 用于发送连接成功的地址的通道
+        sender: oneshot::Sender<Result<Multiaddr, Box<dyn Error + Send>>>,
+        // 发出命令时所在的span，事件循环处理该命令的日志记录在其下
+        span: Span,
+    },
+    // 通过DHT查找距离给定节点最近的节点命令，目标节点可达时查找过程中会与其建立连接
+    GetClosestPeers {
+        // 节点ID
+        peer_id: PeerId,
+        // 用于发送找到的节点的通道
+        sender: oneshot::Sender<Vec<PeerId>>,
+        // 发出命令时所在的span，事件循环处理该命令的日志记录在其下
+        span: Span,
     },
     // 宣称本节点提供共享文件命令
     StartProviding {
+        // 文件名称
+        file_name: String,
+        // 用于发送命令执行状态的通道，无法保存提供者记录时为错误
+        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+    },
+    // 停止提供共享文件命令
+    StopProviding {
         // 文件名称
         file_name: String,
         // 用于发送命令执行状态的通道
         sender: oneshot::Sender<()>,
     },
+    // 设置共享文件访问策略命令
+    SetAccessPolicy {
+        // 文件名称
+        file_name: String,
+        // 访问策略
+        policy: AccessPolicy,
+    },
     // 获取提供共享文件的节点命令
     GetProviders {
         // 文件名称
         file_name: String,
         // 用于发送命令执行状态的通道
         sender: oneshot::Sender<HashSet<PeerId>>,
+        // 发出命令时所在的span，事件循环处理该命令的日志记录在其下
+        span: Span,
     },
-    // 请求共享文件命令
-    RequestFile {
+    // 下载共享文件命令
+    DownloadFile {
         // 文件名称
         file_name: String,
-        // 节点ID
-        peer: PeerId,
-        // 用于发送命令执行状态的通道
+        // 访问令牌
+        token: Option<Vec<u8>>,
+        // 提供共享文件的节点
+        providers: HashSet<PeerId>,
+        // 已知的文件清单，为空时先向提供者请求
+        manifest: Option<Manifest>,
+        // 只下载该范围内的分块，为空时下载整个文件
+        chunks: Option<Range<u64>>,
+        // 用于发送下载完成的文件的通道
+        sender: oneshot::Sender<Result<DownloadedFile, Box<dyn Error + Send>>>,
+        // 发出命令时所在的span，事件循环处理该命令的日志记录在其下
+        span: Span,
+    },
+    // 发布名称记录命令，名称指向给定的共享文件
+    PublishName {
+        // 指向的共享文件键
+        target: String,
+        // 用于发送记录序列号的通道
+        sender: oneshot::Sender<Result<u64, Box<dyn Error + Send>>>,
+    },
+    // 解析节点发布的名称记录命令
+    ResolveName {
+        // 发布者节点ID
+        publisher: PeerId,
+        // 用于发送指向的共享文件键的通道
         sender: oneshot::Sender<Result<String, Box<dyn Error + Send>>>,
     },
+    // 查询本节点能否被直接连接命令
+    Reachability {
+        // 用于发送AutoNAT判断结果的通道
+        sender: oneshot::Sender<NatStatus>,
+    },
     // 返回共享文件内容命令
     RespondFile {
-        // 文件名称
-        file: String,
+        // 请求ID
+        request_id: RequestId,
+        // 响应内容
+        response: FileResponse,
         // 返回文件内容
         channel: ResponseChannel<FileResponse>,
     },
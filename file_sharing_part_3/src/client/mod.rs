@@ -1,14 +1,29 @@
 pub mod command;
 
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, io, ops::Range};
 
-use libp2p::{request_response::ResponseChannel, Multiaddr, PeerId};
-use tokio::sync::{
-    mpsc::{self, Sender},
-    oneshot,
+use libp2p::{
+    autonat::NatStatus,
+    request_response::{RequestId, ResponseChannel},
+    Multiaddr, PeerId,
 };
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, Sender},
+        oneshot,
+    },
+    time,
+};
+use tracing::{info, Span};
 
-use crate::network::FileResponse;
+use crate::network::{
+    access::AccessPolicy,
+    event::Event,
+    subscription::{EventFilter, Subscription},
+    transfer::{DownloadedFile, Manifest},
+    FileResponse, CONNECT_ATTEMPTS, CONNECT_BACKOFF,
+};
 
 pub use self::command::Command;
 
@@ -17,11 +32,18 @@ pub use self::command::Command;
 pub struct Client {
     // 将命令发送到mpsc通道
     sender: mpsc::Sender<Command>,
+    // 用于创建事件订阅
+    events: broadcast::Sender<Event>,
 }
 
 impl Client {
-    pub fn new(sender: Sender<Command>) -> Client {
-        Client { sender }
+    pub fn new(sender: Sender<Command>, events: broadcast::Sender<Event>) -> Client {
+        Client { sender, events }
+    }
+
+    // 订阅符合过滤条件的网络事件
+    pub fn subscribe(&self, filter: EventFilter) -> Subscription {
+        Subscription::new(self.events.subscribe(), filter)
     }
 
     pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error + Send>> {
@@ -38,57 +60,199 @@ impl Client {
         peer_id: PeerId,
         peer_addr: Multiaddr,
     ) -> Result<(), Box<dyn Error + Send>> {
+        self.dial_addresses(peer_id, vec![peer_addr]).await.map(|_| ())
+    }
+
+    // 只根据节点ID连接节点，先尝试已知地址，失败后通过DHT查找节点并重试，返回连接成功的地址
+    pub async fn connect(&mut self, peer_id: PeerId) -> Result<Multiaddr, Box<dyn Error + Send>> {
+        let mut backoff = CONNECT_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.dial_addresses(peer_id, Vec::new()).await {
+                Ok(addr) => return Ok(addr),
+                Err(e) if attempt >= CONNECT_ATTEMPTS => return Err(e),
+                Err(e) => {
+                    info!(peer = %peer_id, attempt, error = %e, "Connection attempt failed, looking up peer");
+                }
+            }
+            // 等待期间查找节点，查找时可能直接与其建立连接
+            let (_, closest) = tokio::join!(time::sleep(backoff), self.get_closest_peers(peer_id));
+            if closest.is_empty() {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No peers to look up {} through.", peer_id),
+                )));
+            }
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    // 同时尝试给定地址和已知地址，返回连接成功的地址
+    async fn dial_addresses(
+        &mut self,
+        peer_id: PeerId,
+        peer_addrs: Vec<Multiaddr>,
+    ) -> Result<Multiaddr, Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
         self.sender
             .send(Command::Dial {
                 peer_id,
-                peer_addr,
+                peer_addrs,
                 sender,
+                span: Span::current(),
             })
             .await
             .expect("Command receiver not to be dropped.");
         receiver.await.expect("Sender not to be dropped.")
     }
 
-    pub async fn start_providing(&mut self, file_name: String) {
+    // 通过DHT查找距离给定节点最近的节点
+    pub async fn get_closest_peers(&mut self, peer_id: PeerId) -> Vec<PeerId> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::GetClosestPeers {
+                peer_id,
+                sender,
+                span: Span::current(),
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    // 保存并发布提供者记录，本地存储中的记录数量达到上限时返回错误
+    pub async fn start_providing(&mut self, file_name: String) -> Result<(), Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
         self.sender
             .send(Command::StartProviding { file_name, sender })
             .await
             .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    // 撤回提供者记录，并删除文件的访问策略。Kademlia不支持删除其他节点保存的记录，
+    // 撤回只删除本地记录并停止重新发布，其他节点上的记录在过期(PROVIDER_RECORD_TTL)后删除
+    pub async fn stop_providing(&mut self, file_name: String) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::StopProviding { file_name, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
         receiver.await.expect("Sender not to be dropped.");
     }
 
+    pub async fn set_access_policy(&mut self, file_name: String, policy: AccessPolicy) {
+        self.sender
+            .send(Command::SetAccessPolicy { file_name, policy })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
     pub async fn get_providers(&mut self, file_name: String) -> HashSet<PeerId> {
         let (sender, receiver) = oneshot::channel();
         self.sender
-            .send(Command::GetProviders { file_name, sender })
+            .send(Command::GetProviders {
+                file_name,
+                sender,
+                span: Span::current(),
+            })
             .await
             .expect("Command receiver not to be dropped.");
         receiver.await.expect("Sender not to be dropped.")
     }
 
-    pub async fn request_file(
+    // 从提供者并行下载文件分块，返回通过校验的文件内容及其清单
+    pub async fn download_file(
         &mut self,
-        peer: PeerId,
         file_name: String,
-    ) -> Result<String, Box<dyn Error + Send>> {
+        token: Option<Vec<u8>>,
+        providers: HashSet<PeerId>,
+    ) -> Result<DownloadedFile, Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
         self.sender
-            .send(Command::RequestFile {
+            .send(Command::DownloadFile {
                 file_name,
-                peer,
+                token,
+                providers,
+                manifest: None,
+                chunks: None,
                 sender,
+                span: Span::current(),
             })
             .await
             .expect("Command receiver not to be dropped.");
         receiver.await.expect("Sender not be dropped.")
     }
 
-    #[allow(dead_code)]
-    pub async fn respond_file(&mut self, file: String, channel: ResponseChannel<FileResponse>) {
+    // 只下载指定范围内的分块，分块按清单校验，不校验整个文件的摘要；
+    // 未给出清单时先获取清单，范围为空时只获取清单
+    pub async fn download_chunks(
+        &mut self,
+        file_name: String,
+        token: Option<Vec<u8>>,
+        providers: HashSet<PeerId>,
+        manifest: Option<Manifest>,
+        chunks: Range<u64>,
+    ) -> Result<DownloadedFile, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::DownloadFile {
+                file_name,
+                token,
+                providers,
+                manifest,
+                chunks: Some(chunks),
+                sender,
+                span: Span::current(),
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    // 发布名称记录，返回记录的序列号
+    pub async fn publish_name(&mut self, target: String) -> Result<u64, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
         self.sender
-            .send(Command::RespondFile { file, channel })
+            .send(Command::PublishName { target, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    // 解析名称记录，返回序列号最大的有效记录指向的共享文件键
+    pub async fn resolve_name(&mut self, publisher: PeerId) -> Result<String, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::ResolveName { publisher, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    // 本节点能否被直接连接，AutoNAT尚未得出结论时为Unknown
+    pub async fn reachability(&mut self) -> NatStatus {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::Reachability { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    pub async fn respond_file(
+        &mut self,
+        request_id: RequestId,
+        response: FileResponse,
+        channel: ResponseChannel<FileResponse>,
+    ) {
+        self.sender
+            .send(Command::RespondFile {
+                request_id,
+                response,
+                channel,
+            })
             .await
             .expect("Command receiver not to be dropped.");
     }
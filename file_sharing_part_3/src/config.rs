@@ -0,0 +1,674 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use file_sharing::network::{
+    access::AccessPolicy, limits::ConnectionLimits, reputation::ReputationConfig, throttle::UploadLimits,
+};
+use libp2p::{multiaddr::Protocol, pnet::PreSharedKey, Multiaddr, PeerId};
+use serde::Deserialize;
+
+use crate::{
+    args::{CliArgument, Opt},
+    output::OutputFormat,
+};
+
+// 未配置监听地址时使用的默认地址
+const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/0";
+
+// 配置文件内容，所有项均可省略
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    // 生成密钥对的种子
+    pub secret_key_seed: Option<u8>,
+    // 监听地址
+    pub listen_addresses: Vec<String>,
+    // 引导节点地址，需包含节点ID
+    pub bootstrap_peers: Vec<String>,
+    // 是否作为中继服务器
+    pub relay_server: Option<bool>,
+    // 中继节点地址，需包含节点ID
+    pub relays: Vec<String>,
+    // 始终保持连接的节点地址，需包含节点ID
+    pub sticky_peers: Vec<String>,
+    // 私有网络的预共享密钥文件，相对于配置文件所在目录
+    pub swarm_key: Option<PathBuf>,
+    // 全局上传速率上限(字节/秒)
+    pub upload_rate: Option<u64>,
+    // 每个节点的上传速率上限(字节/秒)
+    pub peer_upload_rate: Option<u64>,
+    // 同时进行的上传数量上限
+    pub max_concurrent_uploads: Option<usize>,
+    // 已建立的连接总数上限
+    pub max_connections: Option<u32>,
+    // 与单个节点的连接数上限
+    pub max_connections_per_peer: Option<u32>,
+    // 正在建立的入站和出站连接数上限
+    pub max_pending_incoming: Option<u32>,
+    pub max_pending_outgoing: Option<u32>,
+    // 连接空闲多少秒后关闭
+    pub idle_timeout: Option<u64>,
+    // HTTP网关监听地址，省略时不启动网关
+    pub gateway: Option<String>,
+    // 网关的POST /share只能共享这些目录中的文件，相对于配置文件所在目录
+    pub share_roots: Vec<PathBuf>,
+    // 导出运行指标的地址，省略时不导出
+    pub metrics: Option<String>,
+    // 输出格式，text或json
+    pub output: Option<OutputFormat>,
+    // 日志级别或过滤规则，格式与RUST_LOG相同
+    pub log_level: Option<String>,
+    // 以JSON格式追加写入日志的文件，相对于配置文件所在目录
+    pub log_file: Option<PathBuf>,
+    // 保存已知节点地址的文件，相对于配置文件所在目录
+    pub address_book: Option<PathBuf>,
+    // 保存节点评分的文件，相对于配置文件所在目录
+    pub reputation_file: Option<PathBuf>,
+    // 评分低于该值的节点被封禁
+    pub ban_threshold: Option<f64>,
+    // 共享的文件和目录
+    #[serde(rename = "share")]
+    pub shares: Vec<ShareConfig>,
+}
+
+// 共享的文件或目录
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShareConfig {
+    pub path: PathBuf,
+    // 文件名称，默认使用文件名；共享目录时作为目录中文件名称的前缀
+    pub name: Option<String>,
+    // 允许获取文件的节点
+    #[serde(default)]
+    pub allow: Vec<String>,
+    // 只允许持有访问令牌的节点获取
+    #[serde(default)]
+    pub require_token: bool,
+    // 为这些节点加密文件内容
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+// 合并配置文件和命令行参数后的节点设置
+#[derive(Debug)]
+pub struct Settings {
+    pub secret_key_seed: Option<u8>,
+    pub listen_addresses: Vec<Multiaddr>,
+    pub bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    pub relay_server: bool,
+    pub relays: Vec<(PeerId, Multiaddr)>,
+    pub sticky_peers: Vec<(PeerId, Multiaddr)>,
+    pub swarm_key: Option<PreSharedKey>,
+    pub upload_limits: UploadLimits,
+    pub connection_limits: ConnectionLimits,
+    pub reputation: ReputationConfig,
+    pub address_book: Option<PathBuf>,
+    pub gateway: Option<SocketAddr>,
+    // 网关可以共享的目录或文件，包括配置的共享路径和监视的目录
+    pub share_roots: Vec<PathBuf>,
+    pub metrics: Option<SocketAddr>,
+    pub output: OutputFormat,
+    pub log_level: Option<String>,
+    pub log_file: Option<PathBuf>,
+    pub shares: Vec<Share>,
+    pub watch: Option<WatchDir>,
+}
+
+// 要共享的单个文件
+#[derive(Debug)]
+pub struct Share {
+    pub path: PathBuf,
+    pub name: String,
+    pub policy: AccessPolicy,
+    pub recipients: Vec<PeerId>,
+}
+
+// 监视的目录，其中的文件使用相同的访问策略
+#[derive(Debug)]
+pub struct WatchDir {
+    pub path: PathBuf,
+    // 文件名称的前缀
+    pub prefix: Option<String>,
+    pub policy: AccessPolicy,
+    pub recipients: Vec<PeerId>,
+}
+
+// 配置错误，包含出错的配置项
+#[derive(Debug)]
+pub struct ConfigError {
+    field: String,
+    message: String,
+}
+
+impl ConfigError {
+    fn new(field: impl Into<String>, message: impl fmt::Display) -> Self {
+        ConfigError {
+            field: field.into(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid `{}`: {}", self.field, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        let mut config: ConfigFile = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e))?;
+        // 共享路径、密钥文件、地址簿、评分文件和日志文件路径相对于配置文件所在目录
+        if let Some(dir) = path.parent() {
+            for share in &mut config.shares {
+                share.path = dir.join(&share.path);
+            }
+            if let Some(file) = &mut config.swarm_key {
+                *file = dir.join(&file);
+            }
+            if let Some(file) = &mut config.address_book {
+                *file = dir.join(&file);
+            }
+            if let Some(file) = &mut config.reputation_file {
+                *file = dir.join(&file);
+            }
+            if let Some(file) = &mut config.log_file {
+                *file = dir.join(&file);
+            }
+            for root in &mut config.share_roots {
+                *root = dir.join(&root);
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Settings {
+    // 读取配置文件，并使用命令行参数覆盖其中的值
+    pub fn load(opt: &Opt) -> Result<Self, Box<dyn Error>> {
+        let config = match &opt.config {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+        let settings = Settings::merge(opt, config).map_err(|e| match &opt.config {
+            Some(path) => format!("Config file {}: {}", path.display(), e),
+            None => e.to_string(),
+        })?;
+        Ok(settings)
+    }
+
+    fn merge(opt: &Opt, config: ConfigFile) -> Result<Self, ConfigError> {
+        let listen_addresses = match &opt.listen_address {
+            Some(addr) => vec![addr.clone()],
+            None if config.listen_addresses.is_empty() => {
+                vec![DEFAULT_LISTEN_ADDRESS.parse().expect("Valid default address.")]
+            }
+            None => config
+                .listen_addresses
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| parse_multiaddr(&format!("listen_addresses[{}]", i), addr))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let bootstrap_peers = match &opt.peer {
+            Some(addr) => vec![peer_address("--peer", addr.clone())?],
+            None => config
+                .bootstrap_peers
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| {
+                    let field = format!("bootstrap_peers[{}]", i);
+                    peer_address(&field, parse_multiaddr(&field, addr)?)
+                })
+                .collect::<Result<_, _>>()?,
+        };
+
+        let relays = match &opt.relay {
+            Some(addr) => vec![peer_address("--relay", addr.clone())?],
+            None => config
+                .relays
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| {
+                    let field = format!("relays[{}]", i);
+                    peer_address(&field, parse_multiaddr(&field, addr)?)
+                })
+                .collect::<Result<_, _>>()?,
+        };
+
+        let sticky_peers = if opt.sticky_peer.is_empty() {
+            config
+                .sticky_peers
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| {
+                    let field = format!("sticky_peers[{}]", i);
+                    peer_address(&field, parse_multiaddr(&field, addr)?)
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            opt.sticky_peer
+                .iter()
+                .map(|addr| peer_address("--sticky-peer", addr.clone()))
+                .collect::<Result<_, _>>()?
+        };
+
+        let swarm_key = match (&opt.swarm_key, &config.swarm_key) {
+            (Some(path), _) => Some(swarm_key("--swarm-key", path)?),
+            (None, Some(path)) => Some(swarm_key("swarm_key", path)?),
+            (None, None) => None,
+        };
+
+        let max_concurrent = opt
+            .max_concurrent_uploads
+            .or(config.max_concurrent_uploads)
+            .unwrap_or(UploadLimits::default().max_concurrent);
+        if max_concurrent == 0 {
+            return Err(ConfigError::new("max_concurrent_uploads", "must be at least 1"));
+        }
+        let upload_limits = UploadLimits {
+            global_rate: opt.upload_rate.or(config.upload_rate),
+            peer_rate: opt.peer_upload_rate.or(config.peer_upload_rate),
+            max_concurrent,
+        };
+        if upload_limits.global_rate == Some(0) {
+            return Err(ConfigError::new("upload_rate", "must be greater than 0"));
+        }
+        if upload_limits.peer_rate == Some(0) {
+            return Err(ConfigError::new("peer_upload_rate", "must be greater than 0"));
+        }
+
+        let defaults = ConnectionLimits::default();
+        let limit = |field: &str, value: Option<u32>, default: Option<u32>| match value {
+            Some(0) => Err(ConfigError::new(field, "must be at least 1")),
+            Some(value) => Ok(Some(value)),
+            None => Ok(default),
+        };
+        let idle_timeout = opt.idle_timeout.or(config.idle_timeout);
+        if idle_timeout == Some(0) {
+            return Err(ConfigError::new("idle_timeout", "must be greater than 0"));
+        }
+        let connection_limits = ConnectionLimits {
+            max_established: limit(
+                "max_connections",
+                opt.max_connections.or(config.max_connections),
+                defaults.max_established,
+            )?,
+            max_established_per_peer: limit(
+                "max_connections_per_peer",
+                opt.max_connections_per_peer.or(config.max_connections_per_peer),
+                defaults.max_established_per_peer,
+            )?,
+            max_pending_incoming: limit(
+                "max_pending_incoming",
+                opt.max_pending_incoming.or(config.max_pending_incoming),
+                defaults.max_pending_incoming,
+            )?,
+            max_pending_outgoing: limit(
+                "max_pending_outgoing",
+                opt.max_pending_outgoing.or(config.max_pending_outgoing),
+                defaults.max_pending_outgoing,
+            )?,
+            idle_timeout: idle_timeout.map_or(defaults.idle_timeout, Duration::from_secs),
+            ..defaults
+        };
+
+        let ban_threshold = opt.ban_threshold.or(config.ban_threshold);
+        if ban_threshold.is_some_and(|threshold| !threshold.is_finite() || threshold >= 0.0) {
+            return Err(ConfigError::new("ban_threshold", "must be a negative number"));
+        }
+        let reputation = ReputationConfig {
+            ban_threshold: ban_threshold.unwrap_or(ReputationConfig::default().ban_threshold),
+            path: opt.reputation_file.clone().or(config.reputation_file),
+            ..Default::default()
+        };
+
+        let gateway = match (opt.gateway, &config.gateway) {
+            (Some(addr), _) => Some(addr),
+            (None, Some(addr)) => Some(addr.parse().map_err(|e| {
+                ConfigError::new("gateway", format!("{:?} is not a valid socket address: {}", addr, e))
+            })?),
+            (None, None) => None,
+        };
+
+        let metrics = match (opt.metrics, &config.metrics) {
+            (Some(addr), _) => Some(addr),
+            (None, Some(addr)) => Some(addr.parse().map_err(|e| {
+                ConfigError::new("metrics", format!("{:?} is not a valid socket address: {}", addr, e))
+            })?),
+            (None, None) => None,
+        };
+
+        // 只有共享文件的子命令需要展开共享配置，其他子命令不访问共享的路径
+        let sharing = matches!(opt.argument, CliArgument::Provide { .. } | CliArgument::Config { .. });
+        let mut share_roots = if !sharing {
+            Vec::new()
+        } else if opt.share_root.is_empty() {
+            config.share_roots
+        } else {
+            opt.share_root.clone()
+        };
+
+        // 命令行指定共享文件或监视目录时不再共享配置文件中的文件
+        let (shares, watch) = match &opt.argument {
+            CliArgument::Provide {
+                watch: Some(path),
+                name,
+                allow,
+                require_token,
+                recipient,
+                ..
+            } => {
+                if !fs::metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+                    return Err(ConfigError::new(
+                        "--watch",
+                        format!("{} is not a directory", path.display()),
+                    ));
+                }
+                share_roots.push(path.clone());
+                let watch = WatchDir {
+                    path: path.clone(),
+                    prefix: name.clone(),
+                    policy: access_policy(allow.clone(), *require_token),
+                    recipients: recipient.clone(),
+                };
+                (Vec::new(), Some(watch))
+            }
+            CliArgument::Provide {
+                path: Some(path),
+                name,
+                allow,
+                require_token,
+                recipient,
+                ..
+            } => {
+                let name = match name {
+                    Some(name) => name.clone(),
+                    None => file_name("--path", path)?,
+                };
+                share_roots.push(path.clone());
+                let share = Share {
+                    path: path.clone(),
+                    name,
+                    policy: access_policy(allow.clone(), *require_token),
+                    recipients: recipient.clone(),
+                };
+                (vec![share], None)
+            }
+            _ if sharing => {
+                let mut shares = Vec::new();
+                for (i, share) in config.shares.into_iter().enumerate() {
+                    share_roots.push(share.path.clone());
+                    shares.extend(expand_share(&format!("share[{}]", i), share)?);
+                }
+                (shares, None)
+            }
+            _ => (Vec::new(), None),
+        };
+
+        let mut names = HashSet::new();
+        if let Some(share) = shares.iter().find(|share| !names.insert(&share.name)) {
+            return Err(ConfigError::new(
+                "share",
+                format!("file name {:?} is shared more than once", share.name),
+            ));
+        }
+
+        Ok(Settings {
+            secret_key_seed: opt.secret_key_seed.or(config.secret_key_seed),
+            listen_addresses,
+            bootstrap_peers,
+            relay_server: opt.relay_server || config.relay_server.unwrap_or(false),
+            relays,
+            sticky_peers,
+            swarm_key,
+            upload_limits,
+            connection_limits,
+            reputation,
+            address_book: opt.address_book.clone().or(config.address_book),
+            gateway,
+            share_roots,
+            metrics,
+            output: opt.output.or(config.output).unwrap_or(OutputFormat::Text),
+            log_level: opt.log_level.clone().or(config.log_level),
+            log_file: opt.log_file.clone().or(config.log_file),
+            shares,
+            watch,
+        })
+    }
+}
+
+fn parse_multiaddr(field: &str, addr: &str) -> Result<Multiaddr, ConfigError> {
+    addr.parse()
+        .map_err(|e| ConfigError::new(field, format!("{:?} is not a valid multiaddr: {}", addr, e)))
+}
+
+fn parse_peer_id(field: &str, peer: &str) -> Result<PeerId, ConfigError> {
+    peer.parse()
+        .map_err(|e| ConfigError::new(field, format!("{:?} is not a valid peer ID: {}", peer, e)))
+}
+
+// 从节点地址中取出节点ID
+fn peer_address(field: &str, addr: Multiaddr) -> Result<(PeerId, Multiaddr), ConfigError> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(hash)) => match PeerId::from_multihash(hash) {
+            Ok(peer_id) => Ok((peer_id, addr)),
+            Err(_) => Err(ConfigError::new(field, format!("{} has an invalid peer ID", addr))),
+        },
+        _ => Err(ConfigError::new(
+            field,
+            format!("{} must end with /p2p/<peer ID>", addr),
+        )),
+    }
+}
+
+// 读取预共享密钥文件，格式与go-ipfs的swarm.key相同
+fn swarm_key(field: &str, path: &Path) -> Result<PreSharedKey, ConfigError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ConfigError::new(field, format!("{}: {}", path.display(), e)))?;
+    content
+        .parse()
+        .map_err(|e| ConfigError::new(field, format!("{} is not a valid swarm key: {}", path.display(), e)))
+}
+
+fn access_policy(allow: Vec<PeerId>, require_token: bool) -> AccessPolicy {
+    if require_token {
+        AccessPolicy::Capability
+    } else if !allow.is_empty() {
+        AccessPolicy::Allowlist(allow.into_iter().collect())
+    } else {
+        AccessPolicy::Public
+    }
+}
+
+fn file_name(field: &str, path: &Path) -> Result<String, ConfigError> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| ConfigError::new(field, format!("{} has no file name", path.display())))
+}
+
+// 检查共享配置，目录展开为其中的所有文件
+pub fn expand_share(field: &str, share: ShareConfig) -> Result<Vec<Share>, ConfigError> {
+    if share.require_token && !share.allow.is_empty() {
+        return Err(ConfigError::new(
+            field,
+            "`allow` and `require_token` cannot be used together",
+        ));
+    }
+    let allow = share
+        .allow
+        .iter()
+        .enumerate()
+        .map(|(i, peer)| parse_peer_id(&format!("{}.allow[{}]", field, i), peer))
+        .collect::<Result<Vec<_>, _>>()?;
+    let recipients = share
+        .recipients
+        .iter()
+        .enumerate()
+        .map(|(i, peer)| parse_peer_id(&format!("{}.recipients[{}]", field, i), peer))
+        .collect::<Result<Vec<_>, _>>()?;
+    let policy = access_policy(allow, share.require_token);
+
+    let path_field = format!("{}.path", field);
+    let metadata = fs::metadata(&share.path).map_err(|e| {
+        ConfigError::new(&path_field, format!("{}: {}", share.path.display(), e))
+    })?;
+    if metadata.is_file() {
+        let name = match share.name {
+            Some(name) => name,
+            None => file_name(&path_field, &share.path)?,
+        };
+        return Ok(vec![Share {
+            path: share.path,
+            name,
+            policy,
+            recipients,
+        }]);
+    }
+
+    // 目录中文件的名称为相对路径，指定名称时加上前缀
+    let mut files = Vec::new();
+    collect_files(&share.path, &mut files)
+        .map_err(|e| ConfigError::new(&path_field, format!("{}: {}", share.path.display(), e)))?;
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let relative = path
+                .strip_prefix(&share.path)
+                .expect("File to be inside the shared directory.")
+                .to_string_lossy()
+                .into_owned();
+            let name = match &share.name {
+                Some(prefix) => format!("{}/{}", prefix, relative),
+                None => relative,
+            };
+            Share {
+                path,
+                name,
+                policy: policy.clone(),
+                recipients: recipients.clone(),
+            }
+        })
+        .collect())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const GET: [&str; 3] = ["get", "--name", "file"];
+
+    fn merge(args: &[&str], config: &str) -> Result<Settings, ConfigError> {
+        let opt = Opt::try_parse_from(std::iter::once("file_sharing").chain(args.iter().copied())).unwrap();
+        Settings::merge(&opt, toml::from_str(config).unwrap())
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let cases: [(&[&str], &str, usize, Option<u64>); 4] = [
+            (&GET, "", 8, None),
+            (&GET, "max_concurrent_uploads = 3\nupload_rate = 100", 3, Some(100)),
+            (
+                &["--max-concurrent-uploads", "5", "get", "--name", "file"],
+                "max_concurrent_uploads = 3\nupload_rate = 100",
+                5,
+                Some(100),
+            ),
+            (&["--upload-rate", "200", "get", "--name", "file"], "upload_rate = 100", 8, Some(200)),
+        ];
+        for (args, config, max_concurrent, global_rate) in cases {
+            let settings = merge(args, config).unwrap();
+            assert_eq!(settings.upload_limits.max_concurrent, max_concurrent, "{:?} {:?}", args, config);
+            assert_eq!(settings.upload_limits.global_rate, global_rate, "{:?} {:?}", args, config);
+        }
+
+        let settings = merge(&["--ban-threshold", "-1", "get", "--name", "file"], "ban_threshold = -3.0").unwrap();
+        assert_eq!(settings.reputation.ban_threshold, -1.0);
+        let settings = merge(&GET, "ban_threshold = -3.0").unwrap();
+        assert_eq!(settings.reputation.ban_threshold, -3.0);
+        let settings = merge(&GET, "").unwrap();
+        assert_eq!(settings.reputation.ban_threshold, ReputationConfig::default().ban_threshold);
+        assert_eq!(settings.listen_addresses, [DEFAULT_LISTEN_ADDRESS.parse::<Multiaddr>().unwrap()]);
+        assert_eq!(settings.output, OutputFormat::Text);
+        assert_eq!(settings.log_level, None);
+        assert_eq!(settings.log_file, None);
+
+        let config = "output = \"json\"\nlog_level = \"debug\"\nlog_file = \"node.log\"";
+        let settings = merge(&GET, config).unwrap();
+        assert_eq!(settings.output, OutputFormat::Json);
+        assert_eq!(settings.log_level.as_deref(), Some("debug"));
+        assert_eq!(settings.log_file, Some(PathBuf::from("node.log")));
+        let args = ["--output", "text", "--log-level", "warn", "--log-file", "cli.log", "get", "--name", "file"];
+        let settings = merge(&args, config).unwrap();
+        assert_eq!(settings.output, OutputFormat::Text);
+        assert_eq!(settings.log_level.as_deref(), Some("warn"));
+        assert_eq!(settings.log_file, Some(PathBuf::from("cli.log")));
+    }
+
+    #[test]
+    fn invalid_values_name_the_field() {
+        let bad_key = std::env::temp_dir().join(format!("config-test-swarm-key-{}", std::process::id()));
+        fs::write(&bad_key, "not a swarm key").unwrap();
+        let bad_key_config = format!("swarm_key = {:?}", bad_key.display().to_string());
+        let bad_key_arg = bad_key.display().to_string();
+
+        let cases: [(&[&str], &str, &str); 11] = [
+            (&GET, "max_concurrent_uploads = 0", "max_concurrent_uploads"),
+            (&["--upload-rate", "0", "get", "--name", "file"], "", "upload_rate"),
+            (&GET, "peer_upload_rate = 0", "peer_upload_rate"),
+            (&GET, "max_connections = 0", "max_connections"),
+            (&["--max-pending-incoming", "0", "get", "--name", "file"], "", "max_pending_incoming"),
+            (&["--idle-timeout", "0", "get", "--name", "file"], "", "idle_timeout"),
+            (&GET, "ban_threshold = 1.0", "ban_threshold"),
+            (&["--ban-threshold", "0", "get", "--name", "file"], "", "ban_threshold"),
+            (&GET, &bad_key_config, "swarm_key"),
+            (&["--swarm-key", &bad_key_arg, "get", "--name", "file"], "", "--swarm-key"),
+            (&GET, "bootstrap_peers = [\"/ip4/127.0.0.1/tcp/4001\"]", "bootstrap_peers[0]"),
+        ];
+        for (args, config, field) in cases {
+            let error = merge(args, config).unwrap_err();
+            assert_eq!(error.field, field, "{:?} {:?}", args, config);
+        }
+        fs::remove_file(&bad_key).unwrap();
+    }
+
+    #[test]
+    fn shares_are_only_expanded_when_sharing() {
+        let config = "share_roots = [\"/srv\"]\n[[share]]\npath = \"/nonexistent/file\"";
+        let settings = merge(&GET, config).unwrap();
+        assert!(settings.shares.is_empty());
+        assert!(settings.share_roots.is_empty());
+
+        for args in [&["provide"][..], &["config", "check"]] {
+            let error = merge(args, config).unwrap_err();
+            assert_eq!(error.field, "share[0].path");
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{error::Error, fmt};
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use libp2p::{
+    identity::{self, Keypair},
+    multihash::Code,
+    PeerId,
+};
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{x25519, X25519_BASEPOINT_BYTES};
+
+// 加密文件的标识前缀
+const MAGIC: &[u8] = b"FSENC1";
+// 派生密钥时使用的前缀
+const KEY_DOMAIN: &[u8] = b"file-sharing-recipient-key:";
+// 每次加密都生成新的内容密钥和临时密钥，包装密钥由临时密钥和接收者公钥派生，
+// 因此每个密钥只用于加密一次，可以使用固定的nonce；修改密钥生成方式时必须保持这一点
+const NONCE: [u8; 12] = [0u8; 12];
+// 每个接收者条目的长度：X25519公钥(32) + 加密后的内容密钥(32 + 16)
+const RECIPIENT_LEN: usize = 32 + 48;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    // 只支持ed25519密钥
+    UnsupportedKey,
+    // 本节点不在接收者列表中
+    NotRecipient,
+    // 加密数据格式错误或校验失败
+    Malformed,
+    // 接收者数量超过格式能表示的上限
+    TooManyRecipients,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::UnsupportedKey => write!(f, "only ed25519 keys are supported"),
+            CryptoError::NotRecipient => write!(f, "local node is not a recipient of the file"),
+            CryptoError::Malformed => write!(f, "encrypted file is malformed"),
+            CryptoError::TooManyRecipients => write!(f, "at most {} recipients are supported", u16::MAX),
+        }
+    }
+}
+
+impl Error for CryptoError {}
+
+// 为接收者加密文件内容
+// 格式：前缀 | 临时公钥(32) | 接收者数量(2) | 接收者条目 | 加密后的文件内容
+pub fn encrypt(plaintext: &[u8], recipients: &[PeerId]) -> Result<Vec<u8>, CryptoError> {
+    let count = u16::try_from(recipients.len()).map_err(|_| CryptoError::TooManyRecipients)?;
+    let content_key: [u8; 32] = rand::random();
+    let ephemeral_secret: [u8; 32] = rand::random();
+    let ephemeral_public = x25519(ephemeral_secret, X25519_BASEPOINT_BYTES);
+
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&ephemeral_public);
+    data.extend_from_slice(&count.to_be_bytes());
+
+    // 使用与每个接收者协商的密钥加密内容密钥
+    for recipient in recipients {
+        let recipient_public = x25519_public_key(recipient)?;
+        let shared = x25519(ephemeral_secret, recipient_public);
+        let wrapping_key = derive_key(&shared, &ephemeral_public, &recipient_public);
+        let wrapped = seal(&wrapping_key, &content_key)?;
+        data.extend_from_slice(&recipient_public);
+        data.extend_from_slice(&wrapped);
+    }
+
+    data.extend_from_slice(&seal(&content_key, plaintext)?);
+    Ok(data)
+}
+
+// 使用本节点密钥对解密文件内容
+pub fn decrypt(data: &[u8], keypair: &Keypair) -> Result<Vec<u8>, CryptoError> {
+    let secret = x25519_secret_key(keypair)?;
+    let public = x25519(secret, X25519_BASEPOINT_BYTES);
+
+    let data = data.strip_prefix(MAGIC).ok_or(CryptoError::Malformed)?;
+    if data.len() < 34 {
+        return Err(CryptoError::Malformed);
+    }
+    let (ephemeral_public, data) = data.split_at(32);
+    let ephemeral_public: [u8; 32] = ephemeral_public.try_into().expect("Length to be 32.");
+    let (count, data) = data.split_at(2);
+    let count = u16::from_be_bytes([count[0], count[1]]) as usize;
+    if data.len() < count * RECIPIENT_LEN {
+        return Err(CryptoError::Malformed);
+    }
+    let (entries, ciphertext) = data.split_at(count * RECIPIENT_LEN);
+
+    // 找到本节点的接收者条目，解密出内容密钥
+    let wrapped = entries
+        .chunks(RECIPIENT_LEN)
+        .find(|entry| entry[..32] == public)
+        .map(|entry| &entry[32..])
+        .ok_or(CryptoError::NotRecipient)?;
+    let shared = x25519(secret, ephemeral_public);
+    let wrapping_key = derive_key(&shared, &ephemeral_public, &public);
+    let content_key = open(&wrapping_key, wrapped)?;
+
+    open(&content_key, ciphertext)
+}
+
+// 将ed25519私钥转换为X25519私钥
+fn x25519_secret_key(keypair: &Keypair) -> Result<[u8; 32], CryptoError> {
+    let keypair = match keypair {
+        Keypair::Ed25519(keypair) => keypair,
+        #[allow(unreachable_patterns)]
+        _ => return Err(CryptoError::UnsupportedKey),
+    };
+    let hash = Sha512::digest(keypair.secret().as_ref());
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&hash[..32]);
+    secret[0] &= 248;
+    secret[31] &= 127;
+    secret[31] |= 64;
+    Ok(secret)
+}
+
+// 从节点ID中取出ed25519公钥，并转换为X25519公钥
+fn x25519_public_key(peer_id: &PeerId) -> Result<[u8; 32], CryptoError> {
+    let multihash = peer_id.as_ref();
+    if multihash.code() != u64::from(Code::Identity) {
+        return Err(CryptoError::UnsupportedKey);
+    }
+    let public_key = match identity::PublicKey::from_protobuf_encoding(multihash.digest()) {
+        Ok(identity::PublicKey::Ed25519(public_key)) => public_key,
+        _ => return Err(CryptoError::UnsupportedKey),
+    };
+    let point = CompressedEdwardsY(public_key.encode())
+        .decompress()
+        .ok_or(CryptoError::UnsupportedKey)?;
+    Ok(point.to_montgomery().to_bytes())
+}
+
+fn derive_key(shared: &[u8; 32], ephemeral_public: &[u8; 32], recipient_public: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_DOMAIN);
+    hasher.update(shared);
+    hasher.update(ephemeral_public);
+    hasher.update(recipient_public);
+    hasher.finalize().into()
+}
+
+fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&NONCE), plaintext)
+        .map_err(|_| CryptoError::Malformed)
+}
+
+fn open(key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if key.len() != 32 {
+        return Err(CryptoError::Malformed);
+    }
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&NONCE), ciphertext)
+        .map_err(|_| CryptoError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::keypair;
+
+    // 加密数据中接收者条目之后的偏移
+    fn entries_end(count: usize) -> usize {
+        MAGIC.len() + 32 + 2 + count * RECIPIENT_LEN
+    }
+
+    #[test]
+    fn round_trip_for_every_recipient() {
+        let alice = keypair(Some(1));
+        let bob = keypair(Some(2));
+        let recipients = [alice.public().to_peer_id(), bob.public().to_peer_id()];
+        let data = encrypt(b"secret content", &recipients).unwrap();
+
+        assert!(data.starts_with(MAGIC));
+        assert_eq!(decrypt(&data, &alice).unwrap(), b"secret content");
+        assert_eq!(decrypt(&data, &bob).unwrap(), b"secret content");
+    }
+
+    #[test]
+    fn empty_plaintext_round_trips() {
+        let alice = keypair(Some(1));
+        let data = encrypt(b"", &[alice.public().to_peer_id()]).unwrap();
+        assert_eq!(decrypt(&data, &alice).unwrap(), b"");
+    }
+
+    #[test]
+    fn recipient_count_must_fit_the_header() {
+        let alice = keypair(Some(1)).public().to_peer_id();
+        let recipients = vec![alice; u16::MAX as usize + 1];
+        assert!(matches!(encrypt(b"", &recipients), Err(CryptoError::TooManyRecipients)));
+    }
+
+    #[test]
+    fn other_peers_are_not_recipients() {
+        let alice = keypair(Some(1));
+        let mallory = keypair(Some(3));
+        let data = encrypt(b"secret content", &[alice.public().to_peer_id()]).unwrap();
+
+        assert!(matches!(decrypt(&data, &mallory), Err(CryptoError::NotRecipient)));
+    }
+
+    #[test]
+    fn flipped_bits_are_rejected() {
+        let alice = keypair(Some(1));
+        let data = encrypt(b"secret content", &[alice.public().to_peer_id()]).unwrap();
+
+        // 临时公钥、包装后的内容密钥和加密内容中任一位被修改都无法解密
+        let wrapped = entries_end(1) - 1;
+        for index in [MAGIC.len(), wrapped, entries_end(1), data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[index] ^= 1;
+            assert!(
+                matches!(decrypt(&tampered, &alice), Err(CryptoError::Malformed)),
+                "byte {} was not authenticated",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_data_is_malformed() {
+        let alice = keypair(Some(1));
+        let data = encrypt(b"secret content", &[alice.public().to_peer_id()]).unwrap();
+
+        for len in [0, MAGIC.len(), entries_end(0), entries_end(1) - 1, entries_end(1)] {
+            assert!(matches!(decrypt(&data[..len], &alice), Err(CryptoError::Malformed)));
+        }
+    }
+
+    // 固定nonce的前提：同一内容再次加密时使用不同的临时密钥和内容密钥
+    #[test]
+    fn keys_are_never_reused() {
+        let alice = keypair(Some(1));
+        let recipients = [alice.public().to_peer_id()];
+        let first = encrypt(b"secret content", &recipients).unwrap();
+        let second = encrypt(b"secret content", &recipients).unwrap();
+
+        let ephemeral = MAGIC.len()..MAGIC.len() + 32;
+        assert_ne!(first[ephemeral.clone()], second[ephemeral]);
+        let wrapped = entries_end(1) - 48..entries_end(1);
+        assert_ne!(first[wrapped.clone()], second[wrapped]);
+        assert_ne!(first[entries_end(1)..], second[entries_end(1)..]);
+    }
+
+    // 同一次加密中，每个接收者的包装密钥不同
+    #[test]
+    fn wrapping_keys_differ_per_recipient() {
+        let shared = [7u8; 32];
+        let ephemeral_public = [1u8; 32];
+        assert_ne!(
+            derive_key(&shared, &ephemeral_public, &[2u8; 32]),
+            derive_key(&shared, &ephemeral_public, &[3u8; 32])
+        );
+    }
+}
@@ -0,0 +1,797 @@
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    error::Error,
+    fs,
+    net::{SocketAddr, TcpListener},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use file_sharing::{
+    crypto,
+    crypto::CryptoError,
+    network::{
+        access::{AccessPolicy, CapabilityToken},
+        transfer::{Manifest, SharedFile, CHUNK_SIZE},
+    },
+    Client,
+};
+use hyper::{
+    body::{self, Bytes, HttpBody},
+    header::{self, HeaderValue},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use libp2p::{identity::Keypair, PeerId};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tracing::{error, info_span, warn, Instrument, Span};
+
+use crate::{
+    config::{self, ShareConfig},
+    library::Library,
+    output::{Output, Record},
+};
+
+// POST /share 请求体的最大长度
+const MAX_BODY_SIZE: usize = 64 * 1024;
+// 从提供者获取文件时每批下载的分块数量，客户端读取完一批后再下载下一批
+const STREAM_BATCH_CHUNKS: u64 = 8;
+
+// 本地HTTP网关，通过HTTP获取和共享文件
+#[derive(Clone)]
+pub struct Gateway {
+    client: Client,
+    library: Library,
+    keypair: Keypair,
+    // POST /share只能共享这些路径之下的文件，启动时转换为规范路径
+    share_roots: Vec<PathBuf>,
+    // 实际监听的地址，启动后设置，用于检查请求的Host和Origin
+    address: Option<SocketAddr>,
+    output: Output,
+}
+
+// 运行中的网关
+pub struct GatewayHandle {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl GatewayHandle {
+    // 停止接受新连接，等待进行中的请求结束
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+// 请求处理失败时返回的状态码和原因
+type HttpError = (StatusCode, String);
+
+// 要返回的文件内容
+enum Content {
+    // 本节点共享的文件
+    Shared(Arc<SharedFile>),
+    // 已下载并解密的文件
+    Decrypted(Vec<u8>),
+    // 按需从提供者下载分块
+    Remote {
+        file_name: String,
+        token: Option<Vec<u8>>,
+        providers: HashSet<PeerId>,
+        manifest: Manifest,
+        span: Span,
+    },
+}
+
+impl Content {
+    fn len(&self) -> u64 {
+        match self {
+            Content::Shared(file) => file.manifest().size,
+            Content::Decrypted(content) => content.len() as u64,
+            Content::Remote { manifest, .. } => manifest.size,
+        }
+    }
+}
+
+impl Gateway {
+    pub fn new(
+        client: Client,
+        library: Library,
+        keypair: Keypair,
+        share_roots: Vec<PathBuf>,
+        output: Output,
+    ) -> Self {
+        Gateway {
+            client,
+            library,
+            keypair,
+            share_roots,
+            address: None,
+            output,
+        }
+    }
+
+    pub fn spawn(mut self, addr: SocketAddr) -> Result<GatewayHandle, Box<dyn Error>> {
+        // 不存在的路径中没有可以共享的文件
+        self.share_roots = self
+            .share_roots
+            .iter()
+            .filter_map(|root| match fs::canonicalize(root) {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    warn!(path = %root.display(), error = %e, "Ignoring gateway share root");
+                    None
+                }
+            })
+            .collect();
+        // 先绑定端口，以便使用实际监听的地址检查请求
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        self.address = Some(local_addr);
+
+        let output = self.output;
+        let make_service = make_service_fn(move |_| {
+            let gateway = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let gateway = gateway.clone();
+                    async move { Ok::<_, Infallible>(gateway.handle(request).await) }
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener)?.serve(make_service);
+        output.print(Record::Gateway {
+            address: format!("http://{}", local_addr),
+        });
+
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let server = server.with_graceful_shutdown(async {
+            let _ = shutdown_receiver.await;
+        });
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!(error = %e, "HTTP gateway failed");
+            }
+        });
+        Ok(GatewayHandle { shutdown, task })
+    }
+
+    // 所有请求都先检查Host和Origin，避免DNS重绑定后网页读取网关上的文件
+    async fn handle(self, request: Request<Body>) -> Response<Body> {
+        let path = request.uri().path().to_string();
+        let result = match self.check_origin(&request) {
+            Err(e) => Err(e),
+            Ok(()) => self.route(&path, request).await,
+        };
+        result.unwrap_or_else(|(status, message)| {
+            json_response(status, json!({ "error": message }).to_string())
+        })
+    }
+
+    async fn route(self, path: &str, request: Request<Body>) -> Result<Response<Body>, HttpError> {
+        match (request.method(), path.strip_prefix("/files/")) {
+            (&Method::GET | &Method::HEAD, Some(key)) => match percent_decode(key) {
+                Some(key) => self.get_file(key, &request).await,
+                None => Err((StatusCode::BAD_REQUEST, "Invalid file key.".to_string())),
+            },
+            (_, Some(_)) => Err(method_not_allowed()),
+            (&Method::POST, None) if path == "/share" => self.share(request).await,
+            (_, None) if path == "/share" => Err(method_not_allowed()),
+            _ => Err((StatusCode::NOT_FOUND, "Not found.".to_string())),
+        }
+    }
+
+    // GET /files/<key>，支持Range和ETag
+    // 访问令牌通过 ?token=<十六进制> 或 Authorization: Bearer <十六进制> 传递
+    async fn get_file(
+        mut self,
+        key: String,
+        request: &Request<Body>,
+    ) -> Result<Response<Body>, HttpError> {
+        let token = request_token(request)?;
+        let (etag, content) = self.open(key, token).await?;
+        let etag = format!("\"{}\"", hex::encode(etag));
+        let headers = request.headers();
+
+        // 内容未变化时不再返回内容
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| etag_matches(value, &etag));
+        if not_modified {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, &etag)
+                .body(Body::empty())
+                .expect("Response to be valid."));
+        }
+
+        // If-Range与ETag不一致时忽略Range，返回完整内容
+        let range = headers
+            .get(header::RANGE)
+            .filter(|_| {
+                headers
+                    .get(header::IF_RANGE)
+                    .is_none_or(|value| value.as_bytes() == etag.as_bytes())
+            })
+            .and_then(|value| value.to_str().ok());
+        let len = content.len();
+        let response = Response::builder()
+            .header(header::ETAG, &etag)
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::CONTENT_TYPE, "application/octet-stream");
+        let (response, range) = match range.map(|range| parse_range(range, len)) {
+            Some(Some(Ok((start, end)))) => (
+                response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+                start..end + 1,
+            ),
+            Some(Some(Err(()))) => {
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                    .body(Body::empty())
+                    .expect("Response to be valid."))
+            }
+            // 没有Range或无法识别的Range返回完整内容
+            _ => (response.status(StatusCode::OK), 0..len),
+        };
+
+        let response = response.header(header::CONTENT_LENGTH, range.end - range.start);
+        let body = if request.method() == Method::HEAD {
+            Body::empty()
+        } else {
+            self.body(content, range)
+        };
+        Ok(response.body(body).expect("Response to be valid."))
+    }
+
+    // 检查本地共享文件的访问策略，或获取远程文件的清单；加密的文件需完整下载后解密
+    // 返回用作ETag的摘要和文件内容
+    async fn open(&mut self, key: String, token: Option<Vec<u8>>) -> Result<([u8; 32], Content), HttpError> {
+        if let Some((file, policy)) = self.library.get_with_policy(&key) {
+            if !self.permits(&policy, &key, token.as_deref()) {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!("Access to file {} requires a valid token.", key),
+                ));
+            }
+            let digest = file.manifest().digest;
+            if !file.manifest().encrypted {
+                return Ok((digest, Content::Shared(file)));
+            }
+            return Ok((digest, Content::Decrypted(self.decrypt(file.content())?)));
+        }
+
+        // 查找提供者和下载的日志记录在同一个span下，提供者按其访问策略检查令牌
+        let span = info_span!("get", file = %key);
+        let providers = self
+            .client
+            .get_providers(key.clone())
+            .instrument(span.clone())
+            .await;
+        if providers.is_empty() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Could not find provider for file {}.", key),
+            ));
+        }
+        let manifest = self
+            .client
+            .download_chunks(key.clone(), token.clone(), providers.clone(), None, 0..0)
+            .instrument(span.clone())
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?
+            .manifest;
+        let digest = manifest.digest;
+        if !manifest.encrypted {
+            return Ok((
+                digest,
+                Content::Remote {
+                    file_name: key,
+                    token,
+                    providers,
+                    manifest,
+                    span,
+                },
+            ));
+        }
+
+        let chunks = 0..manifest.chunk_digests.len() as u64;
+        let file = self
+            .client
+            .download_chunks(key.clone(), token, providers, Some(manifest), chunks)
+            .instrument(span)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+        if !file.manifest.verify(&file.content) {
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!("File {} failed verification.", key),
+            ));
+        }
+        Ok((digest, Content::Decrypted(self.decrypt(&file.content)?)))
+    }
+
+    // HTTP客户端没有节点身份，以访问令牌中被授权的节点作为请求节点，
+    // 名单和令牌策略都需要本节点签发的有效令牌
+    fn permits(&self, policy: &AccessPolicy, file_name: &str, token: Option<&[u8]>) -> bool {
+        if *policy == AccessPolicy::Public {
+            return true;
+        }
+        let issuer = self.keypair.public();
+        token
+            .and_then(CapabilityToken::decode)
+            .is_some_and(|grant| {
+                grant.verify(&issuer, file_name, &grant.grantee)
+                    && policy.permits(&issuer, file_name, &grant.grantee, token)
+            })
+    }
+
+    // 使用本节点密钥解密
+    fn decrypt(&self, content: &[u8]) -> Result<Vec<u8>, HttpError> {
+        crypto::decrypt(content, &self.keypair).map_err(|e| match e {
+            CryptoError::NotRecipient => (StatusCode::FORBIDDEN, e.to_string()),
+            _ => (StatusCode::BAD_GATEWAY, e.to_string()),
+        })
+    }
+
+    // 生成响应体，远程文件边下载边发送
+    fn body(&self, content: Content, range: Range<u64>) -> Body {
+        let range = range.start as usize..range.end as usize;
+        match content {
+            Content::Shared(file) => Body::from(file.content()[range].to_vec()),
+            Content::Decrypted(content) => Body::from(content[range].to_vec()),
+            Content::Remote {
+                file_name,
+                token,
+                providers,
+                manifest,
+                span,
+            } => {
+                let (sender, body) = Body::channel();
+                let remote = RemoteFile {
+                    client: self.client.clone(),
+                    file_name,
+                    token,
+                    providers,
+                    manifest,
+                };
+                tokio::spawn(remote.send(range.start as u64..range.end as u64, sender).instrument(span));
+                body
+            }
+        }
+    }
+
+    // POST /share，请求体格式与配置文件中的[[share]]相同，例如 {"path": "/tmp/a.txt", "name": "a"}
+    // 只接受JSON请求，避免网页通过跨站表单共享本地文件
+    async fn share(mut self, request: Request<Body>) -> Result<Response<Body>, HttpError> {
+        if !is_json(&request) {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Content-Type must be application/json.".to_string(),
+            ));
+        }
+
+        let mut body = request.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "Request body is too large.".to_string(),
+                ));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let share: ShareConfig = serde_json::from_slice(&bytes)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request body: {}", e)))?;
+        self.check_path(&share.path)?;
+        let shares = config::expand_share("body", share)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        // 目录中的符号链接可能指向共享路径之外
+        for share in &shares {
+            self.check_path(&share.path)?;
+        }
+
+        let mut shared = Vec::new();
+        for share in shares {
+            let file_name = share.name.clone();
+            let file = self
+                .library
+                .share(&mut self.client, share, self.output)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            shared.push(json!({
+                "file_name": file_name,
+                "size": file.manifest().size,
+                "sha256": hex::encode(file.manifest().digest),
+            }));
+        }
+        Ok(json_response(
+            StatusCode::CREATED,
+            json!({ "shared": shared }).to_string(),
+        ))
+    }
+
+    // Host必须是网关的监听地址，Origin存在时必须是网关自身
+    fn check_origin(&self, request: &Request<Body>) -> Result<(), HttpError> {
+        let address = self.address.expect("Gateway to be listening.");
+        let forbidden = |message: &str| Err((StatusCode::FORBIDDEN, message.to_string()));
+        let host = request
+            .headers()
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok());
+        if !host.is_some_and(|host| is_own_host(host, address)) {
+            return forbidden("Host does not match the gateway address.");
+        }
+        if let Some(origin) = request.headers().get(header::ORIGIN) {
+            let own = origin
+                .to_str()
+                .ok()
+                .and_then(|origin| origin.strip_prefix("http://"))
+                .is_some_and(|host| is_own_host(host, address));
+            if !own {
+                return forbidden("Cross-origin requests are not allowed.");
+            }
+        }
+        Ok(())
+    }
+
+    // 路径必须位于共享路径之下，符号链接按其指向的位置判断
+    fn check_path(&self, path: &Path) -> Result<(), HttpError> {
+        let path = fs::canonicalize(path)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("{}: {}", path.display(), e)))?;
+        if self.share_roots.iter().any(|root| path.starts_with(root)) {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("{} is outside the gateway share roots.", path.display()),
+            ))
+        }
+    }
+}
+
+// 从提供者下载远程文件的分块
+struct RemoteFile {
+    client: Client,
+    file_name: String,
+    token: Option<Vec<u8>>,
+    providers: HashSet<PeerId>,
+    manifest: Manifest,
+}
+
+impl RemoteFile {
+    // 按批下载覆盖该字节范围的分块，分块通过校验后立即发送，发送完一批再下载下一批
+    // 请求完整文件时同时计算整个文件的摘要，最后一批在摘要一致后才发送
+    // 下载失败或摘要不一致时中断响应，客户端会收到不完整的响应体
+    async fn send(mut self, range: Range<u64>, mut sender: body::Sender) {
+        let chunk_size = CHUNK_SIZE as u64;
+        let chunks = chunk_range(&range);
+        let mut hasher = (range == (0..self.manifest.size)).then(Sha256::new);
+        let mut next = chunks.start;
+        while next < chunks.end {
+            let batch = next..(next + STREAM_BATCH_CHUNKS).min(chunks.end);
+            let file = match self
+                .client
+                .download_chunks(
+                    self.file_name.clone(),
+                    self.token.clone(),
+                    self.providers.clone(),
+                    Some(self.manifest.clone()),
+                    batch.clone(),
+                )
+                .await
+            {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!(error = %e, "Failed to stream file");
+                    sender.abort();
+                    return;
+                }
+            };
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&file.content);
+            }
+            if batch.end == chunks.end {
+                let digest = hasher.take().map(|hasher| hasher.finalize());
+                if digest.is_some_and(|digest| digest.as_slice() != self.manifest.digest) {
+                    warn!(file = %self.file_name, "Streamed file failed verification");
+                    sender.abort();
+                    return;
+                }
+            }
+            // 只发送请求范围内的部分
+            let offset = batch.start * chunk_size;
+            let from = range.start.saturating_sub(offset) as usize;
+            let to = ((range.end - offset) as usize).min(file.content.len());
+            if sender.send_data(Bytes::from(file.content).slice(from..to)).await.is_err() {
+                // 客户端已断开
+                return;
+            }
+            next = batch.end;
+        }
+    }
+}
+
+// 覆盖字节范围的分块
+fn chunk_range(range: &Range<u64>) -> Range<u64> {
+    let chunk_size = CHUNK_SIZE as u64;
+    if range.start >= range.end {
+        return 0..0;
+    }
+    range.start / chunk_size..range.end.div_ceil(chunk_size)
+}
+
+fn request_token(request: &Request<Body>) -> Result<Option<Vec<u8>>, HttpError> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    query_param(request, "token")
+        .or(header)
+        .map(|token| {
+            hex::decode(token.trim())
+                .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid token.".to_string()))
+        })
+        .transpose()
+}
+
+fn is_json(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+// 主机名为网关的监听地址；监听所有地址时接受任意IP地址，监听本机地址时也接受localhost
+fn is_own_host(host: &str, address: SocketAddr) -> bool {
+    if let Ok(host) = host.parse::<SocketAddr>() {
+        return host.port() == address.port()
+            && (host.ip() == address.ip() || address.ip().is_unspecified());
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) => {
+            name.eq_ignore_ascii_case("localhost")
+                && port.parse() == Ok(address.port())
+                && (address.ip().is_loopback() || address.ip().is_unspecified())
+        }
+        None => false,
+    }
+}
+
+fn method_not_allowed() -> HttpError {
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        "Method not allowed.".to_string(),
+    )
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )
+        .body(Body::from(body))
+        .expect("Response to be valid.")
+}
+
+fn query_param(request: &Request<Body>, name: &str) -> Option<String> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| percent_decode(value)).flatten()
+    })
+}
+
+// 解码URL中的%XX转义
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            // from_str_radix接受前导的+号，需先检查两个字符都是十六进制数字
+            let hex = [iter.next()?, iter.next()?];
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+// If-None-Match包含该ETag或为*
+fn etag_matches(value: &str, etag: &str) -> bool {
+    value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// 解析单个字节范围，返回包含两端的范围；无法识别或包含多个范围时返回None，范围超出内容时返回Err
+fn parse_range(range: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len.checked_sub(1))
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, Some(end.min(len.saturating_sub(1))))
+        }
+    };
+    match end {
+        Some(end) if start < len => Some(Ok((start, end))),
+        _ => Some(Err(())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use file_sharing::NodeBuilder;
+
+    use super::*;
+    use crate::output::OutputFormat;
+
+    #[test]
+    fn percent_decode_handles_escapes() {
+        assert_eq!(percent_decode("docs%2Fa%20b.txt").as_deref(), Some("docs/a b.txt"));
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("%E4%BD%A0").as_deref(), Some("你"));
+        // +号不表示空格
+        assert_eq!(percent_decode("a+b").as_deref(), Some("a+b"));
+    }
+
+    #[test]
+    fn percent_decode_rejects_invalid_escapes() {
+        for input in ["%", "a%", "a%2", "%zz", "%+f", "%-1", "%FF"] {
+            assert_eq!(percent_decode(input), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn etag_matches_lists_and_wildcards() {
+        let etag = "\"abc\"";
+        assert!(etag_matches("\"abc\"", etag));
+        assert!(etag_matches("W/\"abc\"", etag));
+        assert!(etag_matches("\"x\", \"abc\"", etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches("\"abcd\"", etag));
+        assert!(!etag_matches("abc", etag));
+        assert!(!etag_matches("", etag));
+    }
+
+    #[test]
+    fn parse_range_cases() {
+        let cases = [
+            ("bytes=0-9", 100, Some(Ok((0, 9)))),
+            ("bytes=90-", 100, Some(Ok((90, 99)))),
+            ("bytes=90-200", 100, Some(Ok((90, 99)))),
+            ("bytes=-10", 100, Some(Ok((90, 99)))),
+            ("bytes=-200", 100, Some(Ok((0, 99)))),
+            ("bytes=5-5", 100, Some(Ok((5, 5)))),
+            (" bytes= 1 - 2 ", 100, Some(Ok((1, 2)))),
+            // 后缀长度为0或起点超出内容时无法满足
+            ("bytes=-0", 100, Some(Err(()))),
+            ("bytes=100-", 100, Some(Err(()))),
+            ("bytes=100-200", 100, Some(Err(()))),
+            ("bytes=0-", 0, Some(Err(()))),
+            ("bytes=-5", 0, Some(Err(()))),
+            // 多个或重叠的范围、倒置的范围和无法识别的格式返回完整内容
+            ("bytes=0-1,5-6", 100, None),
+            ("bytes=0-50,25-75", 100, None),
+            ("bytes=9-0", 100, None),
+            ("bytes=a-b", 100, None),
+            ("bytes=-", 100, None),
+            ("items=0-1", 100, None),
+            ("bytes=0", 100, None),
+        ];
+        for (range, len, expected) in cases {
+            assert_eq!(parse_range(range, len), expected, "{:?} of {}", range, len);
+        }
+    }
+
+    #[test]
+    fn chunk_range_covers_bytes() {
+        let size = CHUNK_SIZE as u64;
+        assert_eq!(chunk_range(&(0..1)), 0..1);
+        assert_eq!(chunk_range(&(0..size)), 0..1);
+        assert_eq!(chunk_range(&(size - 1..size + 1)), 0..2);
+        assert_eq!(chunk_range(&(size..3 * size)), 1..3);
+        assert_eq!(chunk_range(&(5..5)), 0..0);
+    }
+
+    #[test]
+    fn own_host_matches_listen_address() {
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        assert!(is_own_host("127.0.0.1:8080", local));
+        assert!(is_own_host("localhost:8080", local));
+        assert!(is_own_host("LOCALHOST:8080", local));
+        assert!(!is_own_host("127.0.0.1:8081", local));
+        assert!(!is_own_host("127.0.0.2:8080", local));
+        assert!(!is_own_host("evil.example:8080", local));
+        assert!(!is_own_host("localhost", local));
+
+        let any: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        assert!(is_own_host("192.168.1.2:8080", any));
+        assert!(is_own_host("localhost:8080", any));
+        assert!(!is_own_host("evil.example:8080", any));
+
+        let lan: SocketAddr = "192.168.1.2:8080".parse().unwrap();
+        assert!(!is_own_host("localhost:8080", lan));
+    }
+
+    // 网关监听127.0.0.1:8080，节点不需要连接网络
+    async fn gateway() -> Gateway {
+        let node = NodeBuilder::new().build().await.unwrap();
+        let mut gateway = Gateway::new(
+            node.client,
+            Library::new(),
+            Keypair::generate_ed25519(),
+            Vec::new(),
+            Output::new(OutputFormat::Text),
+        );
+        gateway.address = Some("127.0.0.1:8080".parse().unwrap());
+        gateway
+    }
+
+    fn request(method: Method, path: &str, headers: &[(header::HeaderName, &str)]) -> Request<Body> {
+        let mut request = Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_route_checks_host_and_origin() {
+        let gateway = gateway().await;
+        let rejected = [
+            request(Method::GET, "/files/a", &[(header::HOST, "evil.example:8080")]),
+            request(Method::HEAD, "/files/a", &[(header::HOST, "evil.example:8080")]),
+            request(Method::GET, "/files/a", &[]),
+            request(
+                Method::GET,
+                "/files/a",
+                &[(header::HOST, "127.0.0.1:8080"), (header::ORIGIN, "http://evil.example")],
+            ),
+            request(Method::POST, "/share", &[(header::HOST, "evil.example:8080")]),
+        ];
+        for request in rejected {
+            let response = gateway.clone().handle(request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        let own = request(Method::GET, "/other", &[(header::HOST, "localhost:8080")]);
+        let response = gateway.handle(own).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod client;
+pub mod crypto;
+pub mod network;
+
+pub use client::Client;
+pub use network::{
+    builder::{Node, NodeBuilder},
+    event::Event,
+};
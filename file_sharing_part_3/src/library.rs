@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{Arc, RwLock},
+};
+
+use file_sharing::{
+    crypto,
+    network::{access::AccessPolicy, transfer::SharedFile, FileResponse},
+    Client,
+};
+
+use crate::{
+    config::Share,
+    output::{Output, Record},
+};
+
+// 本节点共享的文件，供文件请求处理和HTTP网关共同使用
+#[derive(Clone, Default)]
+pub struct Library {
+    files: Arc<RwLock<HashMap<String, Entry>>>,
+}
+
+// 共享的文件及其访问策略，HTTP网关按相同的策略检查请求
+#[derive(Clone)]
+struct Entry {
+    file: Arc<SharedFile>,
+    policy: AccessPolicy,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<SharedFile>> {
+        self.get_with_policy(name).map(|(file, _)| file)
+    }
+
+    pub fn get_with_policy(&self, name: &str) -> Option<(Arc<SharedFile>, AccessPolicy)> {
+        self.files
+            .read()
+            .expect("Lock not to be poisoned.")
+            .get(name)
+            .map(|entry| (entry.file.clone(), entry.policy.clone()))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.files
+            .read()
+            .expect("Lock not to be poisoned.")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    pub fn remove(&self, name: &str) -> Option<Arc<SharedFile>> {
+        self.files
+            .write()
+            .expect("Lock not to be poisoned.")
+            .remove(name)
+            .map(|entry| entry.file)
+    }
+
+    // 根据文件请求生成响应，没有该文件时返回NotFound
+    pub fn respond(&self, name: &str, chunk: Option<u64>) -> FileResponse {
+        match self.get(name) {
+            Some(file) => file.respond(chunk),
+            None => FileResponse::NotFound,
+        }
+    }
+
+    // 读取文件并开始共享，已共享同名文件时替换其内容
+    pub async fn share(
+        &self,
+        client: &mut Client,
+        share: Share,
+        output: Output,
+    ) -> Result<Arc<SharedFile>, Box<dyn Error + Send + Sync>> {
+        let content = std::fs::read(&share.path)
+            .map_err(|e| format!("Failed to read {}: {}", share.path.display(), e))?;
+        self.share_content(client, share, content, output).await
+    }
+
+    // 共享已读取的文件内容
+    pub async fn share_content(
+        &self,
+        client: &mut Client,
+        share: Share,
+        mut file_content: Vec<u8>,
+        output: Output,
+    ) -> Result<Arc<SharedFile>, Box<dyn Error + Send + Sync>> {
+        // 设置文件的访问策略
+        client
+            .set_access_policy(share.name.clone(), share.policy.clone())
+            .await;
+
+        // 计算文件清单，需要时先为接收者加密
+        let encrypted = !share.recipients.is_empty();
+        if encrypted {
+            file_content = crypto::encrypt(&file_content, &share.recipients)?;
+        }
+        let file = Arc::new(SharedFile::new(file_content, encrypted));
+        output.print(Record::Sharing {
+            file_name: share.name.clone(),
+            size: file.manifest().size,
+            sha256: hex::encode(file.manifest().digest),
+        });
+
+        // Advertise oneself as a provider of the file on the DHT.
+        client
+            .start_providing(share.name.clone())
+            .await
+            .map_err(|e| format!("Failed to provide {}: {}", share.name, e))?;
+        self.files
+            .write()
+            .expect("Lock not to be poisoned.")
+            .insert(
+                share.name,
+                Entry {
+                    file: file.clone(),
+                    policy: share.policy,
+                },
+            );
+        Ok(file)
+    }
+}
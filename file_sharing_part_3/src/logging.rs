@@ -0,0 +1,48 @@
+use std::{
+    error::Error,
+    fs::OpenOptions,
+    io,
+    path::Path,
+    sync::Mutex,
+};
+
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+// 未指定日志级别且未设置RUST_LOG时，本程序输出info及以上的日志，libp2p等依赖只输出警告
+const DEFAULT_FILTER: &str = "warn,file_sharing=info,file_sharing_part_3=info";
+
+// 初始化日志：便于阅读的日志写到标准错误，指定文件时同时以JSON格式追加写入文件
+// level优先于RUST_LOG环境变量，格式与RUST_LOG相同
+pub fn init(level: Option<&str>, file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|e| format!("Invalid log level {:?}: {}", level, e))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER)),
+    };
+
+    let json = match file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))?;
+            // 每行一个事件，包含当前span及其所有上级span的字段
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .with_writer(Mutex::new(file)),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(io::stderr))
+        .with(json)
+        .try_init()?;
+    Ok(())
+}
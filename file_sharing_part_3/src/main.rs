@@ -1,98 +1,573 @@
-use std::error::Error;
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use args::{CliArgument, Opt};
+use args::{CliArgument, ConfigAction, Opt};
 use clap::Parser;
-use client::Client;
-use futures::FutureExt;
-use libp2p::{multiaddr::Protocol, PeerId};
-use network::event::Event;
-use tokio::sync::mpsc::Receiver;
+use config::Settings;
+use gateway::Gateway;
+use library::Library;
+use file_sharing::{
+    crypto,
+    network::{
+        self,
+        access::CapabilityToken,
+        event::InboundRequest,
+        metrics::Metrics,
+        subscription::{EventFilter, EventKind},
+        transfer::DownloadedFile,
+        FileResponse,
+    },
+    Client, Event, Node, NodeBuilder,
+};
+use futures::{stream, StreamExt};
+use libp2p::identity;
+use output::{FailedFile, Output, OutputFormat, Record, SharedFileConfig};
+use progress::Progress;
+use prometheus_client::registry::Registry;
+use save::Destination;
+use tokio::sync::{broadcast::error::RecvError, mpsc::Receiver};
+use tracing::{info_span, warn, Instrument};
+use watch::Watcher;
 
 mod args;
-mod client;
-mod network;
+mod config;
+mod gateway;
+mod library;
+mod logging;
+mod metrics;
+mod output;
+mod progress;
+mod save;
+mod watch;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let opt = Opt::parse();
+    // 合并配置文件和命令行参数，输出格式和日志设置也可以来自配置文件
+    let settings = Settings::load(&opt);
+    let output = Output::new(match &settings {
+        Ok(settings) => settings.output,
+        Err(_) => opt.output.unwrap_or(OutputFormat::Text),
+    });
+    let result = match settings {
+        Ok(settings) => {
+            logging::init(settings.log_level.as_deref(), settings.log_file.as_deref())?;
+            run(opt, settings, output).await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        // json格式下错误也作为记录输出
+        Err(e) if output.is_json() => {
+            output.print(Record::Error {
+                message: e.to_string(),
+            });
+            process::exit(1);
+        }
+        result => result,
+    }
+}
+
+async fn run(opt: Opt, settings: Settings, output: Output) -> Result<(), Box<dyn Error>> {
+    let id_keys = network::keypair(settings.secret_key_seed);
+
+    if let CliArgument::Config {
+        action: ConfigAction::Check,
+    } = opt.argument
+    {
+        output.print(settings_record(&settings, &id_keys));
+        return Ok(());
+    }
 
-    let (network_client, network_events, network_event_loop) =
-        network::new(opt.secret_key_seed).await?;
+    let mut builder = NodeBuilder::new()
+        .keypair(id_keys.clone())
+        .upload_limits(settings.upload_limits.clone())
+        .connection_limits(settings.connection_limits.clone())
+        .reputation(settings.reputation.clone())
+        .relay_server(settings.relay_server);
+    if let Some(path) = &settings.address_book {
+        builder = builder.address_book(path.clone());
+    }
+    if let Some(key) = settings.swarm_key {
+        builder = builder.swarm_key(key);
+    }
+    for addr in &settings.listen_addresses {
+        builder = builder.listen_address(addr.clone());
+    }
+    for (peer_id, addr) in &settings.bootstrap_peers {
+        builder = builder.bootstrap_peer(*peer_id, addr.clone());
+    }
+    for (peer_id, addr) in &settings.relays {
+        builder = builder.relay(*peer_id, addr.clone());
+    }
+    for (peer_id, addr) in &settings.sticky_peers {
+        builder = builder.sticky_peer(*peer_id, addr.clone());
+    }
+    // 导出指标时将节点指标注册到Registry
+    let mut registry = None;
+    if settings.metrics.is_some() {
+        let mut metrics_registry = Registry::default();
+        builder = builder.metrics(Metrics::new(&mut metrics_registry));
+        registry = Some(metrics_registry);
+    }
+    let Node {
+        client: network_client,
+        inbound_requests,
+        event_loop: network_event_loop,
+    } = builder.build().await?;
+    if let (Some(addr), Some(registry)) = (settings.metrics, registry) {
+        metrics::spawn(addr, registry, output)?;
+    }
 
+    // 输出监听地址和可达性变化，json格式下同时输出节点连接事件；下载相关事件由Get子命令输出
+    let kinds = [EventKind::Listener, EventKind::Connection];
+    // 文件内容原样写到标准输出时，监听地址改为输出到标准错误
+    let to_stderr = matches!(
+        &opt.argument,
+        CliArgument::Get { output: Some(path), .. } if path == Path::new("-")
+    );
+    let mut events = network_client.subscribe(EventFilter::kinds(kinds));
     tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(Event::PeerConnected { .. } | Event::PeerDisconnected { .. } | Event::PeerBanned { .. })
+                    if !output.is_json() => {}
+                Ok(event) if to_stderr => eprintln!("{}", Record::from(&event)),
+                Ok(event) => output.print(Record::from(&event)),
+                Err(RecvError::Lagged(count)) => output.print(Record::EventsMissed { count }),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let event_loop = tokio::spawn(async move {
         network_event_loop.run().await;
     });
 
-    process_args(opt, network_client, network_events).await?;
+    process_args(
+        opt.argument,
+        output,
+        settings,
+        id_keys,
+        network_client,
+        inbound_requests,
+    )
+    .await?;
+
+    // Client已全部释放，等待事件循环撤回提供者记录后退出
+    event_loop.await?;
 
     Ok(())
 }
 
-// 解析命令行参数
-async fn process_args(opt: Opt, mut network_client: Client, mut network_events: Receiver<Event>) -> Result<(), Box<dyn Error>> {
-    match opt.listen_address {
-        Some(addr) => network_client
-            .start_listening(addr)
-            .await
-            .expect("Listening not to fail."),
-        None => network_client
-            .start_listening("/ip4/0.0.0.0/tcp/0".parse()?)
-            .await
-            .expect("Listening not to fail."),
+// 合并后的设置
+fn settings_record(settings: &Settings, id_keys: &identity::Keypair) -> Record {
+    Record::Config {
+        peer_id: id_keys.public().to_peer_id().to_string(),
+        listen_addresses: settings
+            .listen_addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        bootstrap_peers: settings
+            .bootstrap_peers
+            .iter()
+            .map(|(_, addr)| addr.to_string())
+            .collect(),
+        relay_server: settings.relay_server,
+        relays: settings
+            .relays
+            .iter()
+            .map(|(_, addr)| addr.to_string())
+            .collect(),
+        sticky_peers: settings
+            .sticky_peers
+            .iter()
+            .map(|(_, addr)| addr.to_string())
+            .collect(),
+        swarm_key: settings
+            .swarm_key
+            .map(|key| key.fingerprint().to_string()),
+        upload_rate: settings.upload_limits.global_rate,
+        peer_upload_rate: settings.upload_limits.peer_rate,
+        max_concurrent_uploads: settings.upload_limits.max_concurrent,
+        max_connections: settings.connection_limits.max_established,
+        max_connections_per_peer: settings.connection_limits.max_established_per_peer,
+        max_pending_incoming: settings.connection_limits.max_pending_incoming,
+        max_pending_outgoing: settings.connection_limits.max_pending_outgoing,
+        idle_timeout: settings.connection_limits.idle_timeout.as_secs(),
+        reputation_file: settings
+            .reputation
+            .path
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        ban_threshold: settings.reputation.ban_threshold,
+        address_book: settings
+            .address_book
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        gateway: settings.gateway.map(|addr| addr.to_string()),
+        share_roots: settings
+            .share_roots
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+        metrics: settings.metrics.map(|addr| addr.to_string()),
+        log_level: settings.log_level.clone(),
+        log_file: settings
+            .log_file
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        shares: settings
+            .shares
+            .iter()
+            .map(|share| SharedFileConfig {
+                file_name: share.name.clone(),
+                path: share.path.display().to_string(),
+                policy: format!("{:?}", share.policy),
+                recipients: share.recipients.len(),
+            })
+            .collect(),
+    }
+}
+
+// 文本格式下更新进度条，json格式下输出事件
+fn show_event(output: Output, progress: &mut Progress, event: &Event) {
+    if output.is_json() {
+        output.print(Record::from(event));
+    } else {
+        progress.handle(event);
+    }
+}
+
+// 解密下载的文件，保存到文件、原样写到标准输出或输出文件内容
+fn deliver(
+    output: Output,
+    id_keys: &identity::Keypair,
+    name: &str,
+    destination: Option<Destination>,
+    to_stdout: bool,
+    file: DownloadedFile,
+) -> Result<(), Box<dyn Error>> {
+    // 清单声明为加密的文件使用本节点密钥解密
+    let file = if file.manifest.encrypted {
+        crypto::decrypt(&file.content, id_keys)?
+    } else {
+        file.content
     };
 
-    if let Some(addr) = opt.peer {
-        let peer_id = match addr.iter().last() {
-            Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash).expect("Valid hash."),
-            _ => return Err("Expect peer multiaddr to contain peer ID.".into()),
-        };
-        network_client
-            .dial(peer_id, addr)
-            .await
-            .expect("Dial to succeed");
+    if let Some(destination) = destination {
+        destination.write(&file)?;
+        output.print(Record::Saved {
+            file_name: name.to_string(),
+            path: destination.path().display().to_string(),
+            size: file.len(),
+        });
+    } else if to_stdout {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&file)?;
+        stdout.flush()?;
+    } else {
+        output.print(Record::FileContent {
+            file_name: name.to_string(),
+            size: file.len(),
+            content: String::from_utf8_lossy(&file).into_owned(),
+        });
     }
+    Ok(())
+}
+
+// 读取文件名称列表，每行一个名称
+fn read_list(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToString::to_string)
+        .collect())
+}
+
+// 解析命令行参数
+async fn process_args(
+    argument: CliArgument,
+    output: Output,
+    settings: Settings,
+    id_keys: identity::Keypair,
+    mut network_client: Client,
+    mut inbound_requests: Receiver<InboundRequest>,
+) -> Result<(), Box<dyn Error>> {
+    match argument {
+        // 共享的文件已在合并设置时确定
+        // 启用网关时可以不指定文件，之后通过 POST /share 共享
+        CliArgument::Provide { .. } => {
+            if settings.shares.is_empty() && settings.watch.is_none() && settings.gateway.is_none() {
+                return Err("No files to share, pass --path or --watch, or add [[share]] entries to the config file.".into());
+            }
 
-    match opt.argument {
-        CliArgument::Provide { path, name } => {
-            // Advertise oneself as a provider of the file on the DHT.
-            network_client.start_providing(name.clone()).await;
+            let library = Library::new();
+            for share in settings.shares {
+                library
+                    .share(&mut network_client, share, output)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            let gateway = match settings.gateway {
+                Some(addr) => Some(
+                    Gateway::new(
+                        network_client.clone(),
+                        library.clone(),
+                        id_keys,
+                        settings.share_roots,
+                        output,
+                    )
+                    .spawn(addr)?,
+                ),
+                None => None,
+            };
+            let mut watcher = match settings.watch {
+                Some(dir) => {
+                    let watcher = Watcher::new(dir, library.clone(), output)?;
+                    Some(tokio::spawn(watcher.run(network_client.clone())))
+                }
+                None => None,
+            };
 
             loop {
-                match network_events.recv().await {
-                    // Reply with the content of the file on incoming requests.
-                    Some(Event::InboundRequest { request, channel }) => {
-                        if request == name {
-                            let file_content = std::fs::read_to_string(&path)?;
-                            network_client.respond_file(file_content, channel).await;
+                tokio::select! {
+                    request = inbound_requests.recv() => match request {
+                        // Reply with the content of the file on incoming requests.
+                        Some(InboundRequest {
+                            peer,
+                            request_id,
+                            request,
+                            channel,
+                        }) => {
+                            let response = library.respond(&request.file_name, request.chunk);
+                            if request.chunk.is_none() && !matches!(response, FileResponse::NotFound) {
+                                output.print(Record::Serving {
+                                    file_name: request.file_name.clone(),
+                                    peer: peer.to_string(),
+                                });
+                            }
+                            network_client
+                                .respond_file(request_id, response, channel)
+                                .await;
+                        }
+                        None => return Err("Network event loop stopped.".into()),
+                    },
+                    result = async { watcher.as_mut().expect("Watcher to be running.").await }, if watcher.is_some() => {
+                        watcher = None;
+                        match result? {
+                            Ok(()) => {}
+                            Err(e) => warn!(error = %e, "Stopped watching"),
                         }
                     }
-                    e => todo!("{:?}", e),
+                    // 退出前撤回提供者记录
+                    _ = tokio::signal::ctrl_c() => {
+                        if let Some(watcher) = watcher {
+                            watcher.abort();
+                            let _ = watcher.await;
+                        }
+                        if let Some(gateway) = gateway {
+                            gateway.shutdown().await;
+                        }
+                        let names = library.names();
+                        let count = names.len();
+                        for name in names {
+                            network_client.stop_providing(name).await;
+                        }
+                        output.print(Record::StoppedProviding { files: count });
+                        return Ok(());
+                    }
                 }
             }
         }
         
-        CliArgument::Get { name } => {
-            // 找到提供该文件的所有节点
-            let providers = network_client.get_providers(name.clone()).await;
-            if providers.is_empty() {
-                return Err(format!("Could not find provider for file {}.", name).into());
+        CliArgument::Get {
+            name,
+            list,
+            jobs,
+            token,
+            output: save_path,
+            force,
+        } => {
+            let token = token.map(hex::decode).transpose()?;
+            let mut names = name;
+            if let Some(list) = list {
+                names.extend(
+                    read_list(&list)
+                        .map_err(|e| format!("Failed to read {}: {}", list.display(), e))?,
+                );
+            }
+            let mut seen = HashSet::new();
+            names.retain(|name| seen.insert(name.clone()));
+            if names.is_empty() {
+                return Err("No file names to get.".into());
+            }
+            // 获取多个文件时，单个文件失败不影响其他文件，最后输出汇总
+            let batch = names.len() > 1;
+
+            // `-` 表示原样写到标准输出，不能与json格式的记录混在一起
+            let to_stdout = save_path.as_deref() == Some(Path::new("-"));
+            if to_stdout && output.is_json() {
+                return Err("Cannot write file content to stdout with --output json.".into());
+            }
+            if batch && (to_stdout || save_path.as_ref().is_some_and(|path| !path.is_dir())) {
+                return Err("--output must be an existing directory when getting several files.".into());
+            }
+
+            // 下载前确定保存位置，无法保存的文件不再下载
+            let mut succeeded = Vec::new();
+            let mut failed = Vec::new();
+            let mut downloads = Vec::new();
+            for name in names {
+                let destination = match &save_path {
+                    Some(path) if !to_stdout => match Destination::new(path, &name, force) {
+                        Ok(destination) => Some(destination),
+                        Err(e) if batch => {
+                            failed.push((name, e));
+                            continue;
+                        }
+                        Err(e) => return Err(e.into()),
+                    },
+                    _ => None,
+                };
+                // 同时查找所有文件的提供者，查找、连接和下载的日志记录在同一个span下
+                let span = info_span!("get", file = %name);
+                let mut client = network_client.clone();
+                let lookup = tokio::spawn({
+                    let name = name.clone();
+                    async move { client.get_providers(name).await }.instrument(span.clone())
+                });
+                downloads.push((name, destination, lookup, span));
+            }
+
+            // 通过同一个节点的连接从提供者并行下载文件分块，同时进行的下载不超过jobs个
+            // 同时根据网络事件显示下载进度，json格式下输出下载事件
+            let mut progress = Progress::new();
+            let filter = if output.is_json() {
+                EventFilter::kinds([EventKind::Discovery, EventKind::Transfer])
+            } else {
+                EventFilter::all()
+            };
+            let mut events = network_client.subscribe(filter);
+            let mut results = stream::iter(downloads)
+                .map(|(name, destination, lookup, span)| {
+                    let mut client = network_client.clone();
+                    let token = token.clone();
+                    async move {
+                        let result = match lookup.await {
+                            Ok(providers) if providers.is_empty() => {
+                                Err(format!("Could not find provider for file {}.", name))
+                            }
+                            Ok(providers) => client
+                                .download_file(name.clone(), token, providers)
+                                .await
+                                .map_err(|e| e.to_string()),
+                            Err(e) => Err(e.to_string()),
+                        };
+                        (name, destination, result)
+                    }
+                    .instrument(span)
+                })
+                .buffer_unordered(jobs.get());
+            loop {
+                tokio::select! {
+                    result = results.next() => match result {
+                        Some((name, destination, result)) => {
+                            let result = result.and_then(|file| {
+                                deliver(output, &id_keys, &name, destination, to_stdout, file)
+                                    .map_err(|e| e.to_string())
+                            });
+                            match result {
+                                Ok(()) => succeeded.push(name),
+                                Err(e) if batch => failed.push((name, e)),
+                                Err(e) => return Err(e.into()),
+                            }
+                        }
+                        None => break,
+                    },
+                    event = events.recv() => match event {
+                        Ok(event) => show_event(output, &mut progress, &event),
+                        Err(RecvError::Lagged(count)) if output.is_json() => {
+                            output.print(Record::EventsMissed { count })
+                        }
+                        Err(RecvError::Lagged(count)) => progress.lagged(count),
+                        Err(RecvError::Closed) => return Err("Network event loop stopped.".into()),
+                    },
+                }
+            }
+            // 显示剩余的事件
+            while let Ok(event) = events.try_recv() {
+                show_event(output, &mut progress, &event);
             }
 
-            // 从每个节点请求文件的内容。
-            let requests = providers.into_iter().map(|p| {
-                let mut network_client = network_client.clone();
-                let name = name.clone();
-                async move { network_client.request_file(p, name).await }.boxed()
+            if batch {
+                let failures = failed.len();
+                let total = failures + succeeded.len();
+                output.print(Record::Summary {
+                    succeeded,
+                    failed: failed
+                        .into_iter()
+                        .map(|(file_name, error)| FailedFile { file_name, error })
+                        .collect(),
+                });
+                if failures > 0 {
+                    return Err(format!("Failed to get {} of {} files.", failures, total).into());
+                }
+            }
+        }
+
+        CliArgument::Connect { peer } => {
+            let address = network_client
+                .connect(peer)
+                .instrument(info_span!("connect", peer = %peer))
+                .await
+                .map_err(|e| format!("Failed to connect to {}: {}", peer, e))?;
+            output.print(Record::Connected {
+                peer: peer.to_string(),
+                address: address.to_string(),
             });
+        }
+
+        CliArgument::Grant { name, peer, valid_for } => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let expires = valid_for.map(|secs| now.saturating_add(secs));
+            let token = CapabilityToken::new(&id_keys, name.clone(), peer, expires)?;
+            output.print(Record::Token {
+                file_name: name,
+                peer: peer.to_string(),
+                token: hex::encode(token.encode()),
+            });
+        }
 
-            // 等待请求，一旦有一个请求成功，就忽略剩下的请求。
-            let file = futures::future::select_ok(requests)
+        CliArgument::Publish { target } => {
+            let sequence = network_client
+                .publish_name(target.clone())
                 .await
-                .map_err(|_| "None of the providers returned file.")?
-                .0;
+                .map_err(|e| e.to_string())?;
+            output.print(Record::Published { target, sequence });
+        }
 
-            println!("Content of file {}: {}", name, file);
+        CliArgument::Resolve { publisher } => {
+            let target = network_client
+                .resolve_name(publisher)
+                .await
+                .map_err(|e| e.to_string())?;
+            output.print(Record::Resolved {
+                publisher: publisher.to_string(),
+                target,
+            });
         }
+
+        CliArgument::Config { .. } => {}
     }
 
     Ok(())
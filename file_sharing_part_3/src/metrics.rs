@@ -0,0 +1,59 @@
+use std::{convert::Infallible, error::Error, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header::{self, HeaderValue},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus_client::{encoding::text::encode, registry::Registry};
+use tracing::error;
+
+use crate::output::{Output, Record};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// 在本地地址上以Prometheus文本格式导出指标，随进程退出而停止
+pub fn spawn(addr: SocketAddr, registry: Registry, output: Output) -> Result<(), Box<dyn Error>> {
+    let registry = Arc::new(registry);
+    let make_service = make_service_fn(move |_| {
+        let registry = registry.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let registry = registry.clone();
+                async move { Ok::<_, Infallible>(handle(&registry, request)) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    output.print(Record::MetricsEndpoint {
+        address: format!("http://{}/metrics", server.local_addr()),
+    });
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!(error = %e, "Metrics endpoint failed");
+        }
+    });
+    Ok(())
+}
+
+fn handle(registry: &Registry, request: Request<Body>) -> Response<Body> {
+    let (status, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            let mut body = Vec::new();
+            match encode(&mut body, registry) {
+                Ok(()) => (StatusCode::OK, body),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string().into_bytes()),
+            }
+        }
+        (_, "/metrics") => (StatusCode::METHOD_NOT_ALLOWED, b"Method not allowed.".to_vec()),
+        _ => (StatusCode::NOT_FOUND, b"Not found.".to_vec()),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    if status == StatusCode::OK {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    }
+    response
+}
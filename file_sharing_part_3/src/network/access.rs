@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+
+use libp2p::{
+    identity::{error::SigningError, Keypair, PublicKey},
+    PeerId,
+};
+
+use super::{
+    encoding::{put_prefixed, split, split_prefixed},
+    persist::unix_time,
+};
+
+// 签名内容的前缀，避免签名被用于其他用途
+const SIGNING_DOMAIN: &[u8] = b"file-sharing-capability:";
+
+// 共享文件的访问策略
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AccessPolicy {
+    // 任何节点都可以获取
+    #[default]
+    Public,
+    // 只有名单中的节点可以获取
+    Allowlist(HashSet<PeerId>),
+    // 只有持有本节点签发的访问令牌的节点可以获取
+    Capability,
+}
+
+impl AccessPolicy {
+    // 检查请求节点是否可以获取文件，issuer为本节点公钥
+    pub fn permits(
+        &self,
+        issuer: &PublicKey,
+        file_name: &str,
+        peer: &PeerId,
+        token: Option<&[u8]>,
+    ) -> bool {
+        match self {
+            AccessPolicy::Public => true,
+            AccessPolicy::Allowlist(peers) => peers.contains(peer),
+            AccessPolicy::Capability => token
+                .and_then(CapabilityToken::decode)
+                .is_some_and(|token| token.verify(issuer, file_name, peer)),
+        }
+    }
+}
+
+// 访问令牌，由文件提供者签发，授权指定节点获取指定文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityToken {
+    // 授权的文件名称
+    pub file_name: String,
+    // 被授权的节点ID
+    pub grantee: PeerId,
+    // 过期时间(Unix时间戳，秒)，None表示长期有效
+    pub expires: Option<u64>,
+    // 签发者公钥
+    pub public_key: PublicKey,
+    // 签发者对文件名称、被授权节点和过期时间的签名
+    pub signature: Vec<u8>,
+}
+
+impl CapabilityToken {
+    // 使用签发者密钥对签发访问令牌
+    pub fn new(
+        keypair: &Keypair,
+        file_name: String,
+        grantee: PeerId,
+        expires: Option<u64>,
+    ) -> Result<Self, SigningError> {
+        let signature = keypair.sign(&signing_bytes(&file_name, &grantee, expires))?;
+        Ok(CapabilityToken {
+            file_name,
+            grantee,
+            expires,
+            public_key: keypair.public(),
+            signature,
+        })
+    }
+
+    // 检查令牌由issuer签发、尚未过期，且授权给请求节点获取该文件
+    pub fn verify(&self, issuer: &PublicKey, file_name: &str, peer: &PeerId) -> bool {
+        self.public_key == *issuer
+            && self.file_name == file_name
+            && self.grantee == *peer
+            && self.expires.is_none_or(|expires| unix_time() < expires)
+            && self.public_key.verify(
+                &signing_bytes(&self.file_name, &self.grantee, self.expires),
+                &self.signature,
+            )
+    }
+
+    // 编码格式：公钥 | 签名 | 被授权节点ID | 过期时间(8，0表示长期有效) | 文件名称，前三项带2字节长度前缀
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put_prefixed(&mut bytes, &self.public_key.to_protobuf_encoding());
+        put_prefixed(&mut bytes, &self.signature);
+        put_prefixed(&mut bytes, &self.grantee.to_bytes());
+        bytes.extend_from_slice(&self.expires.unwrap_or(0).to_be_bytes());
+        bytes.extend_from_slice(self.file_name.as_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (public_key, rest) = split_prefixed(bytes)?;
+        let public_key = PublicKey::from_protobuf_encoding(public_key).ok()?;
+        let (signature, rest) = split_prefixed(rest)?;
+        let (grantee, rest) = split_prefixed(rest)?;
+        let grantee = PeerId::from_bytes(grantee).ok()?;
+        let (expires, rest) = split(rest, 8)?;
+        let expires = Some(u64::from_be_bytes(expires.try_into().ok()?)).filter(|expires| *expires != 0);
+        let file_name = String::from_utf8(rest.to_vec()).ok()?;
+
+        Some(CapabilityToken {
+            file_name,
+            grantee,
+            expires,
+            public_key,
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+fn signing_bytes(file_name: &str, grantee: &PeerId, expires: Option<u64>) -> Vec<u8> {
+    let mut bytes = SIGNING_DOMAIN.to_vec();
+    put_prefixed(&mut bytes, &grantee.to_bytes());
+    bytes.extend_from_slice(&expires.unwrap_or(0).to_be_bytes());
+    bytes.extend_from_slice(file_name.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::ed25519;
+
+    use super::*;
+
+    fn keypair(seed: u8) -> Keypair {
+        let mut bytes = [seed; 32];
+        Keypair::Ed25519(ed25519::SecretKey::from_bytes(&mut bytes).unwrap().into())
+    }
+
+    // 由issuer签发给grantee的令牌
+    fn token(issuer: &Keypair, grantee: PeerId, expires: Option<u64>) -> Vec<u8> {
+        CapabilityToken::new(issuer, "file".to_string(), grantee, expires)
+            .unwrap()
+            .encode()
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let issuer = keypair(1);
+        let grantee = keypair(2).public().to_peer_id();
+        for expires in [None, Some(unix_time() + 60)] {
+            let token = CapabilityToken::new(&issuer, "file".to_string(), grantee, expires).unwrap();
+            assert_eq!(CapabilityToken::decode(&token.encode()), Some(token));
+        }
+    }
+
+    #[test]
+    fn capability_requires_a_valid_token_for_the_peer() {
+        let issuer = keypair(1);
+        let public = issuer.public();
+        let grantee = keypair(2).public().to_peer_id();
+        let other = keypair(3);
+        let policy = AccessPolicy::Capability;
+        let permits = |token: Option<&[u8]>| policy.permits(&public, "file", &grantee, token);
+
+        assert!(permits(Some(&token(&issuer, grantee, None))));
+        assert!(permits(Some(&token(&issuer, grantee, Some(unix_time() + 60)))));
+        // 已过期
+        assert!(!permits(Some(&token(&issuer, grantee, Some(unix_time() - 1)))));
+        // 授权给其他节点
+        assert!(!permits(Some(&token(&issuer, other.public().to_peer_id(), None))));
+        // 由其他节点签发
+        assert!(!permits(Some(&token(&other, grantee, None))));
+        // 其他文件
+        assert!(!policy.permits(&public, "other", &grantee, Some(&token(&issuer, grantee, None))));
+        assert!(!permits(None));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let issuer = keypair(1);
+        let grantee = keypair(2).public().to_peer_id();
+        let valid = token(&issuer, grantee, None);
+        let policy = AccessPolicy::Capability;
+
+        for len in [0, 1, 10, valid.len() - 13] {
+            assert!(!policy.permits(&issuer.public(), "file", &grantee, Some(&valid[..len])));
+        }
+        assert!(!policy.permits(&issuer.public(), "file", &grantee, Some(b"garbage")));
+
+        // 修改过期时间后签名无效
+        let mut extended = CapabilityToken::new(&issuer, "file".to_string(), grantee, Some(1)).unwrap();
+        extended.expires = None;
+        assert!(!policy.permits(&issuer.public(), "file", &grantee, Some(&extended.encode())));
+    }
+
+    #[test]
+    fn public_and_allowlist_policies() {
+        let issuer = keypair(1).public();
+        let allowed = keypair(2).public().to_peer_id();
+        let other = keypair(3).public().to_peer_id();
+
+        assert!(AccessPolicy::Public.permits(&issuer, "file", &other, None));
+        let allowlist = AccessPolicy::Allowlist(HashSet::from([allowed]));
+        assert!(allowlist.permits(&issuer, "file", &allowed, None));
+        assert!(!allowlist.permits(&issuer, "file", &other, None));
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, io, path::PathBuf};
+
+use libp2p::{Multiaddr, PeerId};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use super::persist::{self, unix_time};
+
+// 每个节点保存的地址数量上限，超过后丢弃最早添加的地址
+const MAX_ADDRESSES_PER_PEER: usize = 8;
+// 保存的节点数量上限，超过后优先丢弃从未连接成功的节点
+const MAX_PEERS: usize = 1024;
+// 超过该时长未连接成功的节点在读取时丢弃(秒)
+const MAX_AGE: u64 = 30 * 24 * 60 * 60;
+
+// 单个节点的地址和连接统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerEntry {
+    // 最近添加的地址在前
+    pub addresses: Vec<Multiaddr>,
+    // 最近一次连接成功的时间(Unix时间戳，秒)
+    pub last_seen: Option<u64>,
+    pub successes: u64,
+    pub failures: u64,
+}
+
+impl PeerEntry {
+    // 连接成功率，没有连接记录时为0.5
+    pub fn success_rate(&self) -> f64 {
+        let attempts = self.successes + self.failures;
+        if attempts == 0 {
+            0.5
+        } else {
+            self.successes as f64 / attempts as f64
+        }
+    }
+}
+
+// 已知节点的地址簿，重启后用于重新加入网络
+pub struct AddressBook {
+    // 保存地址簿的文件，None表示不保存
+    path: Option<PathBuf>,
+    peers: HashMap<PeerId, PeerEntry>,
+    // 有未保存的变化
+    dirty: bool,
+}
+
+impl AddressBook {
+    // 从文件读取地址簿，文件不存在时从空白开始
+    pub fn load(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut peers: HashMap<PeerId, PeerEntry> = match &path {
+            Some(path) => persist::load(path)?,
+            None => HashMap::new(),
+        };
+        // 忽略长期未连接的节点
+        let now = unix_time();
+        peers.retain(|_, entry| entry.last_seen.is_none_or(|seen| seen + MAX_AGE >= now));
+        let mut book = AddressBook {
+            path,
+            peers,
+            dirty: false,
+        };
+        while book.peers.len() > MAX_PEERS {
+            book.evict(None);
+        }
+        Ok(book)
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        match &self.path {
+            Some(path) if self.dirty => persist::save(path, &self.peers)?,
+            _ => return Ok(()),
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn get(&self, peer: &PeerId) -> Option<&PeerEntry> {
+        self.peers.get(peer)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &PeerEntry)> {
+        self.peers.iter()
+    }
+
+    // 添加节点地址，已有的地址移到最前；节点数量超过上限时丢弃其他节点
+    pub fn add_address(&mut self, peer: PeerId, addr: Multiaddr) {
+        let entry = self.peers.entry(peer).or_default();
+        if entry.addresses.first() == Some(&addr) {
+            return;
+        }
+        entry.addresses.retain(|a| a != &addr);
+        entry.addresses.insert(0, addr);
+        entry.addresses.truncate(MAX_ADDRESSES_PER_PEER);
+        self.dirty = true;
+        if self.peers.len() > MAX_PEERS {
+            self.evict(Some(&peer));
+        }
+    }
+
+    // 丢弃最没有价值的节点：先是从未连接成功的，其次是成功率低、最久未连接的
+    fn evict(&mut self, keep: Option<&PeerId>) {
+        let worst = self
+            .peers
+            .iter()
+            .filter(|(peer, _)| Some(*peer) != keep)
+            .min_by(|(_, a), (_, b)| {
+                (a.last_seen.is_some(), a.success_rate(), a.last_seen)
+                    .partial_cmp(&(b.last_seen.is_some(), b.success_rate(), b.last_seen))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(peer, _)| *peer);
+        if let Some(peer) = worst {
+            self.peers.remove(&peer);
+            self.dirty = true;
+        }
+    }
+
+    // 记录连接成功，addr为本节点拨号使用的地址
+    pub fn record_connected(&mut self, peer: PeerId, addr: Option<Multiaddr>) {
+        if let Some(addr) = addr {
+            self.add_address(peer, addr);
+        }
+        // 只记录有地址的节点，对方拨入时的地址无法用于连接
+        if let Some(entry) = self.peers.get_mut(&peer) {
+            entry.successes += 1;
+            entry.last_seen = Some(unix_time());
+            self.dirty = true;
+        }
+    }
+
+    pub fn record_dial_failure(&mut self, peer: &PeerId) {
+        if let Some(entry) = self.peers.get_mut(peer) {
+            entry.failures += 1;
+            self.dirty = true;
+        }
+    }
+
+    pub fn remove(&mut self, peer: &PeerId) {
+        if self.peers.remove(peer).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // 从连接成功率最高、最近连接过的节点中随机选择count个，避免所有节点总是连接相同的节点
+    pub fn sample(&self, count: usize) -> Vec<PeerId> {
+        let mut candidates: Vec<_> = self.peers.iter().collect();
+        candidates.sort_by(|(_, a), (_, b)| {
+            (b.success_rate(), b.last_seen)
+                .partial_cmp(&(a.success_rate(), a.last_seen))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        candidates.truncate(count * 2);
+        candidates
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .map(|(peer, _)| **peer)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/10.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn latest_address_comes_first() {
+        let mut book = AddressBook::load(None).unwrap();
+        let peer = PeerId::random();
+        for port in 0..MAX_ADDRESSES_PER_PEER as u16 + 2 {
+            book.add_address(peer, addr(port));
+        }
+        book.add_address(peer, addr(5));
+
+        let addresses = &book.get(&peer).unwrap().addresses;
+        assert_eq!(addresses.len(), MAX_ADDRESSES_PER_PEER);
+        assert_eq!(addresses[0], addr(5));
+        assert_eq!(addresses[1], addr(MAX_ADDRESSES_PER_PEER as u16 + 1));
+        assert_eq!(addresses.iter().filter(|a| **a == addr(5)).count(), 1);
+    }
+
+    #[test]
+    fn inbound_connections_without_address_are_not_recorded() {
+        let mut book = AddressBook::load(None).unwrap();
+        let peer = PeerId::random();
+        book.record_connected(peer, None);
+        assert!(book.get(&peer).is_none());
+
+        book.record_connected(peer, Some(addr(1)));
+        book.record_dial_failure(&peer);
+        let entry = book.get(&peer).unwrap();
+        assert!(entry.last_seen.is_some());
+        assert_eq!((entry.successes, entry.failures), (1, 1));
+        assert_eq!(entry.success_rate(), 0.5);
+    }
+
+    #[test]
+    fn never_connected_peers_are_evicted_first() {
+        let mut book = AddressBook::load(None).unwrap();
+        let connected = PeerId::random();
+        book.record_connected(connected, Some(addr(1)));
+        for _ in 0..MAX_PEERS + 10 {
+            book.add_address(PeerId::random(), addr(2));
+        }
+        assert_eq!(book.peers().count(), MAX_PEERS);
+        assert!(book.get(&connected).is_some());
+
+        // 新添加的节点不会被立即丢弃
+        let newest = PeerId::random();
+        book.add_address(newest, addr(3));
+        assert!(book.get(&newest).is_some());
+    }
+
+    #[test]
+    fn save_and_load_drops_expired_peers() {
+        let path = env::temp_dir().join(format!("address-book-test-{}.json", std::process::id()));
+        let mut book = AddressBook::load(Some(path.clone())).unwrap();
+        let (recent, stale, unknown) = (PeerId::random(), PeerId::random(), PeerId::random());
+        book.record_connected(recent, Some(addr(1)));
+        book.record_connected(stale, Some(addr(2)));
+        book.peers.get_mut(&stale).unwrap().last_seen = Some(unix_time() - MAX_AGE - 1);
+        book.add_address(unknown, addr(3));
+        book.save().unwrap();
+
+        let loaded = AddressBook::load(Some(path.clone())).unwrap();
+        assert_eq!(loaded.get(&recent).unwrap().addresses, vec![addr(1)]);
+        assert!(loaded.get(&stale).is_none());
+        assert!(loaded.get(&unknown).is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sample_prefers_reliable_peers() {
+        let mut book = AddressBook::load(None).unwrap();
+        let reliable: Vec<_> = (0..2).map(|_| PeerId::random()).collect();
+        for peer in &reliable {
+            book.record_connected(*peer, Some(addr(1)));
+        }
+        for _ in 0..10 {
+            let peer = PeerId::random();
+            book.add_address(peer, addr(2));
+            book.record_dial_failure(&peer);
+        }
+        let mut sample = book.sample(1);
+        assert_eq!(sample.len(), 1);
+        assert!(reliable.contains(&sample.pop().unwrap()));
+    }
+}
@@ -1,17 +1,40 @@
+use std::convert::Infallible;
+
 use libp2p::{
-    kad::{store::MemoryStore, Kademlia, KademliaEvent},
+    autonat, dcutr,
+    identify::{Identify, IdentifyEvent},
+    kad::KademliaEvent,
+    relay::v2::{client, relay},
     request_response::{RequestResponse, RequestResponseEvent},
+    swarm::behaviour::toggle::Toggle,
     NetworkBehaviour,
 };
 
-use super::protocol::{FileExchangeCodec, FileRequest, FileResponse};
+use super::{
+    connection::StickyKeepAlive,
+    dht::Dht,
+    protocol::{FileExchangeCodec, FileRequest, FileResponse},
+};
 
-// 组合Kademlia和请求-响应协议
+// 组合Kademlia、请求-响应协议、Identify、中继、打洞和AutoNAT协议
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "ComposedEvent")]
 pub struct ComposedBehaviour {
     pub request_response: RequestResponse<FileExchangeCodec>,
-    pub kademlia: Kademlia<MemoryStore>,
+    // 节点无法被直接连接时切换为客户端模式
+    pub kademlia: Dht,
+    // 交换监听地址和支持的协议
+    pub identify: Identify,
+    // 作为中继服务器，未启用时为空
+    pub relay: Toggle<relay::Relay>,
+    // 通过中继监听和连接节点
+    pub relay_client: client::Client,
+    // 通过中继连接协调打洞，将中继连接升级为直连
+    pub dcutr: dcutr::behaviour::Behaviour,
+    // 请求其他节点回拨，判断本节点能否被直接连接
+    pub autonat: autonat::Behaviour,
+    // 保持与引导节点、中继等节点的连接，打洞成功后关闭中继连接
+    pub keep_alive: StickyKeepAlive,
 }
 
 // 网络行为事件
@@ -19,6 +42,11 @@ pub struct ComposedBehaviour {
 pub enum ComposedEvent {
     RequestResponse(RequestResponseEvent<FileRequest, FileResponse>),
     Kademlia(KademliaEvent),
+    Identify(Box<IdentifyEvent>),
+    Relay(relay::Event),
+    RelayClient(client::Event),
+    Dcutr(dcutr::behaviour::Event),
+    Autonat(autonat::Event),
 }
 
 impl From<RequestResponseEvent<FileRequest, FileResponse>> for ComposedEvent {
@@ -32,3 +60,39 @@ impl From<KademliaEvent> for ComposedEvent {
         ComposedEvent::Kademlia(event)
     }
 }
+
+impl From<IdentifyEvent> for ComposedEvent {
+    fn from(event: IdentifyEvent) -> Self {
+        ComposedEvent::Identify(Box::new(event))
+    }
+}
+
+impl From<relay::Event> for ComposedEvent {
+    fn from(event: relay::Event) -> Self {
+        ComposedEvent::Relay(event)
+    }
+}
+
+impl From<client::Event> for ComposedEvent {
+    fn from(event: client::Event) -> Self {
+        ComposedEvent::RelayClient(event)
+    }
+}
+
+impl From<dcutr::behaviour::Event> for ComposedEvent {
+    fn from(event: dcutr::behaviour::Event) -> Self {
+        ComposedEvent::Dcutr(event)
+    }
+}
+
+impl From<Infallible> for ComposedEvent {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
+
+impl From<autonat::Event> for ComposedEvent {
+    fn from(event: autonat::Event) -> Self {
+        ComposedEvent::Autonat(event)
+    }
+}
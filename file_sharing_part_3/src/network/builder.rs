@@ -0,0 +1,724 @@
+use std::{collections::HashSet, error::Error, iter, path::PathBuf, time::Duration};
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{
+    core::{
+        either::EitherOutput,
+        muxing::StreamMuxerBox,
+        transport::{Boxed, OrTransport},
+        upgrade::{SelectUpgrade, Version},
+    },
+    autonat, dcutr,
+    dns::DnsConfig,
+    identify::{Identify, IdentifyConfig},
+    identity,
+    kad::{
+        store::{MemoryStore, MemoryStoreConfig, RecordStore},
+        Kademlia, KademliaConfig,
+    },
+    mplex::MplexConfig,
+    multiaddr::Protocol,
+    noise,
+    pnet::{PnetConfig, PreSharedKey},
+    relay::v2::{client, relay},
+    request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig},
+    swarm::{dial_opts::DialOpts, SwarmBuilder},
+    tcp::{GenTcpConfig, TcpTransport},
+    websocket::WsConfig,
+    yamux::YamuxConfig,
+    Multiaddr, PeerId, Transport as _,
+};
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, Receiver},
+};
+use tracing::{info, warn};
+
+use crate::client::Client;
+
+use super::{
+    address_book::AddressBook,
+    behaviour::ComposedBehaviour,
+    dht::Dht,
+    connection::{ConnectionManager, StickyKeepAlive},
+    event::{EventLoop, InboundRequest},
+    limits::ConnectionLimits,
+    metrics::Metrics,
+    protocol::{FileExchangeCodec, FileExchangeProtocol, ProtocolLimits},
+    reputation::{Reputation, ReputationConfig},
+    store::BoxedStore,
+    throttle::UploadLimits,
+    transfer::CHUNK_SIZE,
+    ADDRESS_BOOK_DIAL_SAMPLE, EVENT_BUFFER_SIZE, IDENTIFY_PROTOCOL_VERSION, PROVIDER_RECORD_TTL, RELAY_MAX_CIRCUIT_BYTES,
+    RELAY_MAX_CIRCUIT_DURATION, REQUEST_BUFFER_SIZE,
+};
+
+// 节点使用的传输层
+pub type Transport = Boxed<(PeerId, StreamMuxerBox)>;
+
+// 根据本地节点ID创建记录存储
+pub type StoreFactory = Box<dyn FnOnce(PeerId) -> BoxedStore>;
+
+// 未加密的原始连接
+pub trait Socket: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Socket for S {}
+
+// 只建立原始连接的传输层，由构建器完成预共享密钥握手、加密和多路复用
+pub type RawTransport = Boxed<Box<dyn Socket>>;
+
+// 构建完成的节点，需由调用者运行事件循环
+pub struct Node {
+    // 用于向节点发送命令和订阅事件
+    pub client: Client,
+    // 收到的文件请求
+    pub inbound_requests: Receiver<InboundRequest>,
+    pub event_loop: EventLoop,
+}
+
+// 节点构建器，未设置的项使用默认值
+#[derive(Default)]
+pub struct NodeBuilder {
+    // 节点密钥对，默认随机生成
+    keypair: Option<identity::Keypair>,
+    // 传输层，默认使用TCP + Noise + Yamux/Mplex，并始终支持通过中继连接
+    transport: Option<Transport>,
+    // 原始传输层，构建时与默认传输层一样升级
+    raw_transport: Option<RawTransport>,
+    listen_addresses: Vec<Multiaddr>,
+    bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    // 创建Kademlia记录存储，默认使用按store_config限制的内存存储
+    store: Option<StoreFactory>,
+    store_config: MemoryStoreConfig,
+    protocol_limits: ProtocolLimits,
+    upload_limits: UploadLimits,
+    connection_limits: ConnectionLimits,
+    reputation: ReputationConfig,
+    // 保存已知节点地址的文件，未设置时不保存
+    address_book: Option<PathBuf>,
+    // 是否作为中继服务器
+    relay_server: bool,
+    // 在这些中继上预留位置，通过中继接受连接
+    relays: Vec<(PeerId, Multiaddr)>,
+    // 始终保持连接的节点，引导节点和中继也会保持连接
+    sticky_peers: Vec<(PeerId, Multiaddr)>,
+    // 运行指标，默认不注册到任何Registry
+    metrics: Metrics,
+    // 私有网络的预共享密钥，只能与持有相同密钥的节点建立连接
+    swarm_key: Option<PreSharedKey>,
+}
+
+impl NodeBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn keypair(mut self, keypair: identity::Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    // 已完成加密和多路复用的传输层，不能与预共享密钥同时使用
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    // 替换默认的TCP传输层，建立的原始连接在构建时依次加上预共享密钥握手(设置了密钥时)、
+    // Noise加密和Yamux/Mplex多路复用
+    pub fn raw_transport<T>(mut self, transport: T) -> Self
+    where
+        T: libp2p::Transport + Send + Unpin + 'static,
+        T::Output: Socket + 'static,
+        T::Error: Send + Sync + 'static,
+        T::Dial: Send + 'static,
+        T::ListenerUpgrade: Send + 'static,
+    {
+        self.raw_transport = Some(
+            transport
+                .map(|socket, _| Box::new(socket) as Box<dyn Socket>)
+                .boxed(),
+        );
+        self
+    }
+
+    // 添加监听地址，未添加时节点不监听
+    pub fn listen_address(mut self, addr: Multiaddr) -> Self {
+        self.listen_addresses.push(addr);
+        self
+    }
+
+    // 添加引导节点，构建时加入路由表并连接
+    pub fn bootstrap_peer(mut self, peer_id: PeerId, addr: Multiaddr) -> Self {
+        self.bootstrap_peers.push((peer_id, addr));
+        self
+    }
+
+    // 默认内存存储的限制，设置了自定义存储时不使用
+    pub fn store_config(mut self, config: MemoryStoreConfig) -> Self {
+        self.store_config = config;
+        self
+    }
+
+    // 使用自定义的记录存储，例如持久化到磁盘的存储
+    pub fn store<S, F>(mut self, factory: F) -> Self
+    where
+        S: for<'a> RecordStore<'a> + Send + 'static,
+        F: FnOnce(PeerId) -> S + 'static,
+    {
+        self.store = Some(Box::new(move |peer_id| BoxedStore::new(factory(peer_id))));
+        self
+    }
+
+    pub fn protocol_limits(mut self, limits: ProtocolLimits) -> Self {
+        self.protocol_limits = limits;
+        self
+    }
+
+    pub fn upload_limits(mut self, limits: UploadLimits) -> Self {
+        self.upload_limits = limits;
+        self
+    }
+
+    pub fn connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.connection_limits = limits;
+        self
+    }
+
+    pub fn reputation(mut self, config: ReputationConfig) -> Self {
+        self.reputation = config;
+        self
+    }
+
+    // 启动时从地址簿中的节点加入网络，运行时记录新发现的节点
+    pub fn address_book(mut self, path: PathBuf) -> Self {
+        self.address_book = Some(path);
+        self
+    }
+
+    // 作为中继服务器，为其他节点转发连接
+    pub fn relay_server(mut self, enabled: bool) -> Self {
+        self.relay_server = enabled;
+        self
+    }
+
+    // 添加中继，构建时在中继上预留位置并监听其/p2p-circuit地址。
+    // 使用默认传输层时会复用监听端口以便打洞，此时本节点不再为其他节点提供AutoNAT回拨：
+    // 从监听端口回拨同样复用端口的节点时，与已有连接的地址和端口完全相同，回拨必然失败，
+    // 对方会误判为无法被直接连接
+    pub fn relay(mut self, peer_id: PeerId, addr: Multiaddr) -> Self {
+        self.relays.push((peer_id, addr));
+        self
+    }
+
+    // 使用预共享密钥保护默认或原始传输层以及中继连接，不能与已升级的自定义传输层同时使用
+    pub fn swarm_key(mut self, key: PreSharedKey) -> Self {
+        self.swarm_key = Some(key);
+        self
+    }
+
+    // 添加需要始终保持连接的节点，断开后自动重连
+    pub fn sticky_peer(mut self, peer_id: PeerId, addr: Multiaddr) -> Self {
+        self.sticky_peers.push((peer_id, addr));
+        self
+    }
+
+    // 记录运行指标，指标需由调用者注册到Registry后导出
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub async fn build(self) -> Result<Node, Box<dyn Error>> {
+        // 响应需包含状态字节和一个完整的分块
+        if self.protocol_limits.max_message_size <= CHUNK_SIZE {
+            return Err(format!(
+                "Max message size must be larger than the chunk size ({} bytes).",
+                CHUNK_SIZE
+            )
+            .into());
+        }
+
+        // 自定义传输层已完成加密和多路复用，无法在其之前加入预共享密钥握手
+        if self.transport.is_some() && self.swarm_key.is_some() {
+            return Err(
+                "A swarm key cannot be used with an upgraded transport, use a raw transport instead."
+                    .into(),
+            );
+        }
+        if self.transport.is_some() && self.raw_transport.is_some() {
+            return Err("Only one of a transport and a raw transport can be set.".into());
+        }
+
+        let reputation = Reputation::load(self.reputation.clone()).map_err(|e| match &self.reputation.path {
+            Some(path) => format!("Failed to load peer scores from {}: {}", path.display(), e),
+            None => e.to_string(),
+        })?;
+
+        let address_book = AddressBook::load(self.address_book.clone()).map_err(|e| match &self.address_book {
+            Some(path) => format!("Failed to load address book from {}: {}", path.display(), e),
+            None => e.to_string(),
+        })?;
+
+        let id_keys = self
+            .keypair
+            .unwrap_or_else(identity::Keypair::generate_ed25519);
+        // 根据公钥生成节点ID
+        let peer_id = id_keys.public().to_peer_id();
+
+        // 在中继上预留位置的节点通常位于NAT后，打洞时需从监听端口发起连接；
+        // 其他节点不复用端口，否则AutoNAT回拨会与对方已有的连接冲突
+        let port_reuse =
+            self.transport.is_none() && self.raw_transport.is_none() && !self.relays.is_empty();
+        if port_reuse {
+            info!("Reusing the listen port for hole punching, not serving AutoNAT dial-backs");
+        }
+        let transport = match (self.transport, self.raw_transport) {
+            (Some(transport), _) => transport,
+            (None, Some(raw)) => upgrade(raw, &id_keys, self.swarm_key)?,
+            (None, None) => {
+                let tcp = DnsConfig::system(TcpTransport::new(
+                    GenTcpConfig::new().nodelay(true).port_reuse(port_reuse),
+                ))
+                .await?;
+                let ws = WsConfig::new(
+                    DnsConfig::system(TcpTransport::new(GenTcpConfig::new().nodelay(true))).await?,
+                );
+                upgrade(tcp.or_transport(ws), &id_keys, self.swarm_key)?
+            }
+        };
+        // 中继连接同样需要加密和多路复用
+        let (relay_transport, relay_client) = client::Client::new_transport_and_behaviour(peer_id);
+        let transport = OrTransport::new(upgrade(relay_transport, &id_keys, self.swarm_key)?, transport)
+            .map(|output, _| match output {
+                EitherOutput::First(output) | EitherOutput::Second(output) => output,
+            })
+            .boxed();
+
+        // 默认的中继限制只适合建立连接，放宽限制以便通过中继传输文件
+        let relay = self.relay_server.then(|| {
+            relay::Relay::new(
+                peer_id,
+                relay::Config {
+                    max_circuit_duration: RELAY_MAX_CIRCUIT_DURATION,
+                    max_circuit_bytes: RELAY_MAX_CIRCUIT_BYTES,
+                    ..Default::default()
+                },
+            )
+        });
+
+        // 由EventLoop负责重新发布提供者记录，关闭Kademlia自带的发布
+        let mut kademlia_config = KademliaConfig::default();
+        kademlia_config
+            .set_provider_record_ttl(Some(PROVIDER_RECORD_TTL))
+            .set_provider_publication_interval(None)
+            .set_connection_idle_timeout(self.connection_limits.idle_timeout);
+        let store = match self.store {
+            Some(factory) => factory(peer_id),
+            None => BoxedStore::new(MemoryStore::with_config(peer_id, self.store_config)),
+        };
+
+        let mut request_response_config = RequestResponseConfig::default();
+        request_response_config
+            .set_request_timeout(self.protocol_limits.request_timeout)
+            .set_connection_keep_alive(self.connection_limits.idle_timeout);
+
+        // 构建网络层管理组件Swarm
+        let mut swarm = SwarmBuilder::new(
+            transport,
+            ComposedBehaviour {
+                kademlia: Dht::new(Kademlia::with_config(peer_id, store, kademlia_config)),
+                request_response: RequestResponse::new(
+                    FileExchangeCodec::new(self.protocol_limits.max_message_size),
+                    iter::once((FileExchangeProtocol(), ProtocolSupport::Full)),
+                    request_response_config,
+                ),
+                identify: Identify::new(
+                    IdentifyConfig::new(IDENTIFY_PROTOCOL_VERSION.to_string(), id_keys.public())
+                        .with_push_listen_addr_updates(true),
+                ),
+                relay: relay.into(),
+                relay_client,
+                dcutr: dcutr::behaviour::Behaviour::new(),
+                // 允许私有地址，以便在局域网或单机上测试；复用端口时不为其他节点回拨，见relay()
+                autonat: autonat::Behaviour::new(
+                    peer_id,
+                    autonat::Config {
+                        only_global_ips: false,
+                        throttle_clients_global_max: if port_reuse { 0 } else { 30 },
+                        ..Default::default()
+                    },
+                ),
+                keep_alive: StickyKeepAlive::new(
+                    self.bootstrap_peers
+                        .iter()
+                        .chain(&self.relays)
+                        .chain(&self.sticky_peers)
+                        .map(|(peer_id, _)| *peer_id)
+                        .collect(),
+                ),
+            },
+            peer_id,
+        )
+        .connection_limits(self.connection_limits.to_swarm())
+        .max_negotiating_inbound_streams(self.connection_limits.max_negotiating_inbound_streams)
+        .build();
+
+        for addr in self.listen_addresses {
+            swarm.listen_on(addr)?;
+        }
+        // 地址簿中的节点加入路由表，并连接其中一部分，被封禁的节点除外
+        for (peer_id, entry) in address_book.peers() {
+            if reputation.is_banned(peer_id) {
+                continue;
+            }
+            for addr in &entry.addresses {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(peer_id, addr.clone());
+            }
+        }
+        let configured: HashSet<_> = self
+            .bootstrap_peers
+            .iter()
+            .chain(&self.relays)
+            .chain(&self.sticky_peers)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        // 由EventLoop发起拨号，以便将拨号结果对应到正在进行的拨号
+        let mut dials = Vec::new();
+        for peer_id in address_book.sample(ADDRESS_BOOK_DIAL_SAMPLE) {
+            if configured.contains(&peer_id) || reputation.is_banned(&peer_id) {
+                continue;
+            }
+            dials.push(DialOpts::peer_id(peer_id).build());
+        }
+        // 拨号失败时由ConnectionManager稍后重试
+        let mut connections = ConnectionManager::new();
+        for (peer_id, addr) in self.sticky_peers {
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&peer_id, addr.clone());
+            connections.add_sticky(peer_id, addr.clone());
+            dials.push(DialOpts::peer_id(peer_id).addresses(vec![addr]).build());
+        }
+        // 引导节点和中继同时用于AutoNAT探测
+        for (peer_id, addr) in self.bootstrap_peers {
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&peer_id, addr.clone());
+            swarm
+                .behaviour_mut()
+                .autonat
+                .add_server(peer_id, Some(addr.clone()));
+            connections.add_sticky(peer_id, addr.clone());
+            dials.push(DialOpts::peer_id(peer_id).addresses(vec![addr]).build());
+        }
+        // 监听中继地址时会先连接中继并预留位置
+        let mut relays = Vec::new();
+        for (peer_id, addr) in self.relays {
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&peer_id, addr.clone());
+            swarm
+                .behaviour_mut()
+                .autonat
+                .add_server(peer_id, Some(addr.clone()));
+            let relay_addr = match addr.iter().last() {
+                Some(Protocol::P2p(_)) => addr.clone(),
+                _ => addr.clone().with(Protocol::P2p(peer_id.into())),
+            };
+            let circuit_addr = relay_addr.with(Protocol::P2pCircuit);
+            let listener_id = swarm.listen_on(circuit_addr.clone())?;
+            connections.add_sticky(peer_id, addr.clone());
+            relays.push((peer_id, addr, listener_id, circuit_addr));
+        }
+
+        let (command_sender, command_receiver) = mpsc::channel(1);
+        let (event_sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        let (request_sender, request_receiver) = mpsc::channel(REQUEST_BUFFER_SIZE);
+
+        let mut event_loop = EventLoop::new(
+            swarm,
+            id_keys,
+            command_receiver,
+            event_sender.clone(),
+            request_sender,
+            self.upload_limits,
+            reputation,
+            address_book,
+            connections,
+            relays,
+            self.metrics,
+        );
+        for opts in dials {
+            let peer_id = opts.get_peer_id();
+            if let Err(e) = event_loop.dial(opts) {
+                warn!(peer = ?peer_id, error = %e, "Failed to dial");
+            }
+        }
+
+        Ok(Node {
+            client: Client::new(command_sender, event_sender),
+            inbound_requests: request_receiver,
+            event_loop,
+        })
+    }
+}
+
+// 为传输层加上预共享密钥握手(可选)、Noise加密和Yamux/Mplex多路复用
+fn upgrade<T>(
+    transport: T,
+    id_keys: &identity::Keypair,
+    swarm_key: Option<PreSharedKey>,
+) -> Result<Transport, noise::NoiseError>
+where
+    T: libp2p::Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync,
+    T::Dial: Send,
+    T::ListenerUpgrade: Send,
+{
+    match swarm_key {
+        // 没有相同密钥的节点无法完成握手
+        Some(key) => authenticate(
+            transport.and_then(move |socket, _| PnetConfig::new(key).handshake(socket)),
+            id_keys,
+        ),
+        None => authenticate(transport, id_keys),
+    }
+}
+
+fn authenticate<T>(transport: T, id_keys: &identity::Keypair) -> Result<Transport, noise::NoiseError>
+where
+    T: libp2p::Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync,
+    T::Dial: Send,
+    T::ListenerUpgrade: Send,
+{
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(id_keys)?;
+    Ok(transport
+        .upgrade(Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(SelectUpgrade::new(
+            YamuxConfig::default(),
+            MplexConfig::default(),
+        ))
+        .timeout(Duration::from_secs(20))
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        borrow::Cow,
+        sync::{Arc, Mutex},
+    };
+
+    use libp2p::{
+        core::transport::{timeout::TransportTimeout, MemoryTransport},
+        kad::{record::Key, ProviderRecord, Record},
+    };
+
+    use super::*;
+    use crate::network::{
+        event::Event,
+        subscription::{EventFilter, EventKind},
+    };
+
+    // 在内存传输层上监听的节点，返回其节点ID和客户端
+    async fn node(seed: u8, port: u64, swarm_key: Option<PreSharedKey>) -> (PeerId, Client) {
+        let keypair = super::super::keypair(Some(seed));
+        let peer_id = keypair.public().to_peer_id();
+        let mut builder = NodeBuilder::new()
+            .keypair(keypair)
+            .raw_transport(MemoryTransport::default())
+            .listen_address(format!("/memory/{}", port).parse().unwrap());
+        if let Some(key) = swarm_key {
+            builder = builder.swarm_key(key);
+        }
+        let node = builder.build().await.unwrap();
+        tokio::spawn(node.event_loop.run());
+        (peer_id, node.client)
+    }
+
+    // 不监听的节点，握手超时很短，密钥不同时拨号很快失败
+    async fn dialer(seed: u8, swarm_key: Option<PreSharedKey>) -> (PeerId, Client) {
+        let keypair = super::super::keypair(Some(seed));
+        let peer_id = keypair.public().to_peer_id();
+        let transport = upgrade(MemoryTransport::default(), &keypair, swarm_key).unwrap();
+        let transport = TransportTimeout::new(transport, Duration::from_millis(500)).boxed();
+        let node = NodeBuilder::new()
+            .keypair(keypair)
+            .transport(transport)
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn(node.event_loop.run());
+        (peer_id, node.client)
+    }
+
+    #[tokio::test]
+    async fn swarm_key_is_required_to_connect() {
+        let key = PreSharedKey::new([7; 32]);
+        let (peer_id, server) = node(1, 40_001, Some(key)).await;
+        let mut events = server.subscribe(EventFilter::kinds([EventKind::Connection]));
+        let addr: Multiaddr = "/memory/40001".parse().unwrap();
+
+        let (member_id, mut member) = node(2, 40_002, Some(key)).await;
+        member.dial(peer_id, addr.clone()).await.unwrap();
+
+        // 没有密钥或密钥不同时握手得到的数据无法解密，升级超时后拨号失败
+        let (outsider_id, mut outsider) = dialer(3, None).await;
+        let (other_id, mut other) = dialer(4, Some(PreSharedKey::new([8; 32]))).await;
+        let wait = Duration::from_secs(5);
+        let (outsider, other) = tokio::join!(
+            tokio::time::timeout(wait, outsider.dial(peer_id, addr.clone())),
+            tokio::time::timeout(wait, other.dial(peer_id, addr)),
+        );
+        assert!(matches!(outsider, Ok(Err(_))));
+        assert!(matches!(other, Ok(Err(_))));
+
+        // 服务端只与持有相同密钥的节点建立了连接
+        let mut connected = HashSet::new();
+        while let Ok(event) = events.try_recv() {
+            if let Event::PeerConnected { peer } = event {
+                connected.insert(peer);
+            }
+        }
+        assert!(connected.contains(&member_id));
+        assert!(!connected.contains(&outsider_id));
+        assert!(!connected.contains(&other_id));
+    }
+
+    // 与测试共享的内存存储，节点退出后仍可检查其中的记录
+    #[derive(Clone)]
+    struct SharedStore(Arc<Mutex<MemoryStore>>);
+
+    impl<'a> RecordStore<'a> for SharedStore {
+        type RecordsIter = std::vec::IntoIter<Cow<'a, Record>>;
+        type ProvidedIter = std::vec::IntoIter<Cow<'a, ProviderRecord>>;
+
+        fn get(&'a self, k: &Key) -> Option<Cow<'a, Record>> {
+            let store = self.0.lock().unwrap();
+            store.get(k).map(|record| Cow::Owned(record.into_owned()))
+        }
+
+        fn put(&'a mut self, r: Record) -> libp2p::kad::store::Result<()> {
+            self.0.lock().unwrap().put(r)
+        }
+
+        fn remove(&'a mut self, k: &Key) {
+            self.0.lock().unwrap().remove(k)
+        }
+
+        fn records(&'a self) -> Self::RecordsIter {
+            let store = self.0.lock().unwrap();
+            let records: Vec<_> = store.records().map(|r| Cow::Owned(r.into_owned())).collect();
+            records.into_iter()
+        }
+
+        fn add_provider(&'a mut self, record: ProviderRecord) -> libp2p::kad::store::Result<()> {
+            self.0.lock().unwrap().add_provider(record)
+        }
+
+        fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+            self.0.lock().unwrap().providers(key)
+        }
+
+        fn provided(&'a self) -> Self::ProvidedIter {
+            let store = self.0.lock().unwrap();
+            let records: Vec<_> = store.provided().map(|r| Cow::Owned(r.into_owned())).collect();
+            records.into_iter()
+        }
+
+        fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+            self.0.lock().unwrap().remove_provider(k, p)
+        }
+    }
+
+    #[tokio::test]
+    async fn leaving_withdraws_provider_records() {
+        let keypair = super::super::keypair(Some(21));
+        let shared = Arc::new(Mutex::new(MemoryStore::new(keypair.public().to_peer_id())));
+        let store = SharedStore(shared.clone());
+        let node = NodeBuilder::new()
+            .keypair(keypair)
+            .raw_transport(MemoryTransport::default())
+            .store(move |_| store)
+            .build()
+            .await
+            .unwrap();
+        let event_loop = tokio::spawn(node.event_loop.run());
+        let mut client = node.client;
+        client.start_providing("a".to_string()).await.unwrap();
+        client.start_providing("b".to_string()).await.unwrap();
+        assert_eq!(shared.lock().unwrap().provided().count(), 2);
+
+        // 所有Client退出后事件循环撤回记录并结束
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), event_loop)
+            .await
+            .expect("Event loop to exit.")
+            .unwrap();
+        assert_eq!(shared.lock().unwrap().provided().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn withdrawn_records_remain_on_other_nodes_until_expiry() {
+        let (provider_id, mut provider) = node(11, 40_011, None).await;
+
+        // 记录已发布到其他节点时，撤回只删除本地记录，其他节点保存的记录直到过期才删除
+        let (holder_id, mut holder) = node(13, 40_013, None).await;
+        provider.dial(holder_id, "/memory/40013".parse().unwrap()).await.unwrap();
+        provider.start_providing("published".to_string()).await.unwrap();
+        provider.stop_providing("published".to_string()).await;
+        // 发布完成时记录可能尚未到达其他节点，等待其保存
+        let mut found = false;
+        for _ in 0..40 {
+            found = holder.get_providers("published".to_string()).await.contains(&provider_id);
+            if found {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(found);
+    }
+
+    #[tokio::test]
+    async fn custom_store_is_used() {
+        let node = NodeBuilder::new()
+            .raw_transport(MemoryTransport::default())
+            .store(|peer_id| {
+                let config = MemoryStoreConfig {
+                    max_provided_keys: 0,
+                    ..Default::default()
+                };
+                MemoryStore::with_config(peer_id, config)
+            })
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn(node.event_loop.run());
+        let mut client = node.client;
+        assert!(client.start_providing("file".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn swarm_key_requires_a_raw_transport() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let transport = authenticate(MemoryTransport::default(), &keypair).unwrap();
+        let result = NodeBuilder::new()
+            .keypair(keypair)
+            .transport(transport)
+            .swarm_key(PreSharedKey::new([7; 32]))
+            .build()
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    fmt::Debug,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use libp2p::{
+    core::{connection::ConnectionId, upgrade::DeniedUpgrade, ConnectedPoint},
+    swarm::{
+        handler::DummyConnectionHandler, CloseConnection, ConnectionError, ConnectionHandler,
+        IntoConnectionHandler, KeepAlive, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+    },
+    Multiaddr, PeerId,
+};
+use rand::Rng;
+use tokio::time::{Duration, Instant};
+
+use super::{RECONNECT_INITIAL_BACKOFF, RECONNECT_MAX_BACKOFF};
+
+// 需要保持连接的节点的重连状态
+#[derive(Debug)]
+struct StickyPeer {
+    addresses: Vec<Multiaddr>,
+    // 连续失败的次数
+    failures: u32,
+    // 下次尝试连接的时间，None表示已连接或正在拨号
+    next_attempt: Option<Instant>,
+}
+
+// 保持与引导节点、中继等节点的连接，断开或拨号失败后按指数退避重连
+#[derive(Debug, Default)]
+pub struct ConnectionManager {
+    sticky: HashMap<PeerId, StickyPeer>,
+}
+
+impl ConnectionManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_sticky(&mut self, peer: PeerId, addr: Multiaddr) {
+        let entry = self.sticky.entry(peer).or_insert_with(|| StickyPeer {
+            addresses: Vec::new(),
+            failures: 0,
+            next_attempt: None,
+        });
+        if !entry.addresses.contains(&addr) {
+            entry.addresses.push(addr);
+        }
+    }
+
+    pub fn is_sticky(&self, peer: &PeerId) -> bool {
+        self.sticky.contains_key(peer)
+    }
+
+    pub fn connected(&mut self, peer: &PeerId) {
+        if let Some(entry) = self.sticky.get_mut(peer) {
+            entry.failures = 0;
+            entry.next_attempt = None;
+        }
+    }
+
+    // 所有连接均已断开，稍后重连
+    pub fn disconnected(&mut self, peer: &PeerId) {
+        if let Some(entry) = self.sticky.get_mut(peer) {
+            if entry.next_attempt.is_none() {
+                entry.next_attempt = Some(Instant::now() + backoff(entry.failures));
+            }
+        }
+    }
+
+    // 拨号失败，等待时间随连续失败次数加倍
+    pub fn dial_failed(&mut self, peer: &PeerId) {
+        if let Some(entry) = self.sticky.get_mut(peer) {
+            if entry.next_attempt.is_none() {
+                entry.failures += 1;
+                entry.next_attempt = Some(Instant::now() + backoff(entry.failures));
+            }
+        }
+    }
+
+    // 取出到达重连时间的节点及其地址和连续失败次数
+    pub fn due(&mut self, now: Instant) -> Vec<(PeerId, Vec<Multiaddr>, u32)> {
+        self.sticky
+            .iter_mut()
+            .filter(|(_, entry)| entry.next_attempt.is_some_and(|at| at <= now))
+            .map(|(peer, entry)| {
+                entry.next_attempt = None;
+                (*peer, entry.addresses.clone(), entry.failures)
+            })
+            .collect()
+    }
+}
+
+// 指数退避，加上±20%的随机抖动，避免大量节点同时重连
+fn backoff(failures: u32) -> Duration {
+    let base = RECONNECT_INITIAL_BACKOFF
+        .saturating_mul(1 << failures.min(16))
+        .min(RECONNECT_MAX_BACKOFF);
+    base.mul_f64(rand::thread_rng().gen_range(0.8..1.2))
+}
+
+// 阻止与需要保持连接的节点之间的连接因空闲而关闭，其他连接不受影响；
+// 打洞成功后关闭经由中继的连接，之后的请求只经由直连发送
+pub struct StickyKeepAlive {
+    peers: Arc<HashSet<PeerId>>,
+    // 各节点经由中继的连接
+    relayed: HashMap<PeerId, HashSet<ConnectionId>>,
+    // 等待关闭的连接，按请求顺序关闭
+    actions: VecDeque<ConnectionId>,
+    closing: HashMap<ConnectionId, PeerId>,
+}
+
+impl StickyKeepAlive {
+    pub fn new(peers: HashSet<PeerId>) -> Self {
+        StickyKeepAlive {
+            peers: Arc::new(peers),
+            relayed: HashMap::new(),
+            actions: VecDeque::new(),
+            closing: HashMap::new(),
+        }
+    }
+
+    // 关闭与该节点之间经由中继的连接，已有直连时使用
+    pub fn close_relayed(&mut self, peer: &PeerId) {
+        for id in self.relayed.get(peer).into_iter().flatten() {
+            if self.closing.insert(*id, *peer).is_none() {
+                self.actions.push_back(*id);
+            }
+        }
+    }
+
+    // 下一个需要关闭的连接，排队期间已关闭的连接不再处理
+    fn next_close(&mut self) -> Option<(PeerId, ConnectionId)> {
+        while let Some(id) = self.actions.pop_front() {
+            if let Some(peer) = self.closing.get(&id) {
+                return Some((*peer, id));
+            }
+        }
+        None
+    }
+}
+
+// 建立连接后根据对方节点ID决定是否保持连接
+#[derive(Clone)]
+pub struct KeepAliveHandlerProto {
+    peers: Arc<HashSet<PeerId>>,
+}
+
+impl IntoConnectionHandler for KeepAliveHandlerProto {
+    type Handler = DummyConnectionHandler;
+
+    fn into_handler(self, remote_peer_id: &PeerId, _: &ConnectedPoint) -> Self::Handler {
+        DummyConnectionHandler {
+            keep_alive: if self.peers.contains(remote_peer_id) {
+                KeepAlive::Yes
+            } else {
+                KeepAlive::No
+            },
+        }
+    }
+
+    fn inbound_protocol(&self) -> DeniedUpgrade {
+        DeniedUpgrade
+    }
+}
+
+impl NetworkBehaviour for StickyKeepAlive {
+    type ConnectionHandler = KeepAliveHandlerProto;
+    type OutEvent = Infallible;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        KeepAliveHandlerProto {
+            peers: self.peers.clone(),
+        }
+    }
+
+    fn inject_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: <DummyConnectionHandler as ConnectionHandler>::OutEvent,
+    ) {
+        match event {}
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer: &PeerId,
+        id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        _: Option<&Vec<Multiaddr>>,
+        _: usize,
+    ) {
+        if endpoint.is_relayed() {
+            self.relayed.entry(*peer).or_default().insert(*id);
+        }
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer: &PeerId,
+        id: &ConnectionId,
+        _: &ConnectedPoint,
+        _: DummyConnectionHandler,
+        _: usize,
+    ) {
+        if let Some(ids) = self.relayed.get_mut(peer) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.relayed.remove(peer);
+            }
+        }
+        self.closing.remove(id);
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        match self.next_close() {
+            Some((peer_id, id)) => Poll::Ready(NetworkBehaviourAction::CloseConnection {
+                peer_id,
+                connection: CloseConnection::One(id),
+            }),
+            None => Poll::Pending,
+        }
+    }
+}
+
+// 连接关闭的原因
+pub fn close_reason<E: Debug>(cause: &Option<ConnectionError<E>>) -> String {
+    match cause {
+        None => "closed".to_string(),
+        Some(ConnectionError::IO(e)) => format!("I/O error: {}", e),
+        Some(ConnectionError::KeepAliveTimeout) => "idle timeout".to_string(),
+        Some(ConnectionError::Handler(e)) => format!("protocol error: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::core::Endpoint;
+
+    use super::*;
+
+    fn endpoint(address: &str) -> ConnectedPoint {
+        ConnectedPoint::Dialer {
+            address: address.parse().unwrap(),
+            role_override: Endpoint::Dialer,
+        }
+    }
+
+    #[test]
+    fn only_relayed_connections_are_closed() {
+        let relay = PeerId::random();
+        let peer = PeerId::random();
+        let relayed = endpoint(&format!("/ip4/10.0.0.1/tcp/4001/p2p/{}/p2p-circuit", relay));
+        let direct = endpoint("/ip4/10.0.0.2/tcp/4001");
+        let mut keep_alive = StickyKeepAlive::new(HashSet::new());
+        keep_alive.inject_connection_established(&peer, &ConnectionId::new(1), &relayed, None, 0);
+        keep_alive.inject_connection_established(&peer, &ConnectionId::new(2), &direct, None, 1);
+
+        keep_alive.close_relayed(&peer);
+        // 重复调用不会重复关闭
+        keep_alive.close_relayed(&peer);
+        assert_eq!(keep_alive.next_close(), Some((peer, ConnectionId::new(1))));
+        assert_eq!(keep_alive.next_close(), None);
+    }
+
+    #[test]
+    fn connections_closed_while_queued_are_skipped() {
+        let relay = PeerId::random();
+        let peer = PeerId::random();
+        let relayed = endpoint(&format!("/ip4/10.0.0.1/tcp/4001/p2p/{}/p2p-circuit", relay));
+        let mut keep_alive = StickyKeepAlive::new(HashSet::new());
+        keep_alive.inject_connection_established(&peer, &ConnectionId::new(1), &relayed, None, 0);
+
+        keep_alive.close_relayed(&peer);
+        keep_alive.inject_connection_closed(
+            &peer,
+            &ConnectionId::new(1),
+            &relayed,
+            DummyConnectionHandler::default(),
+            0,
+        );
+        assert_eq!(keep_alive.next_close(), None);
+        assert!(keep_alive.relayed.is_empty());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        for failures in [0, 1, 3, 8, 16, 17, u32::MAX] {
+            let base = RECONNECT_INITIAL_BACKOFF
+                .saturating_mul(1 << failures.min(16))
+                .min(RECONNECT_MAX_BACKOFF);
+            let delay = backoff(failures);
+            assert!(delay >= base.mul_f64(0.8) && delay <= base.mul_f64(1.2), "{} {:?}", failures, delay);
+        }
+        assert!(backoff(u32::MAX) <= RECONNECT_MAX_BACKOFF.mul_f64(1.2));
+    }
+
+    #[test]
+    fn failed_dials_are_retried_with_growing_delays() {
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let mut connections = ConnectionManager::new();
+        connections.add_sticky(peer, addr.clone());
+        // 其他节点不重连
+        connections.dial_failed(&PeerId::random());
+
+        let start = Instant::now();
+        connections.dial_failed(&peer);
+        // 等待期间再次失败不会重复计数
+        connections.dial_failed(&peer);
+        assert!(connections.due(start).is_empty());
+        let later = start + RECONNECT_MAX_BACKOFF * 2;
+        assert_eq!(connections.due(later), vec![(peer, vec![addr.clone()], 1)]);
+        // 已取出的节点在下次失败前不再重连
+        assert!(connections.due(later).is_empty());
+
+        connections.dial_failed(&peer);
+        assert_eq!(connections.due(start + RECONNECT_MAX_BACKOFF * 2)[0].2, 2);
+
+        // 连接成功后重置失败次数，断开不计为失败
+        connections.connected(&peer);
+        connections.disconnected(&peer);
+        assert_eq!(connections.due(start + RECONNECT_MAX_BACKOFF * 2)[0].2, 0);
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    error, io,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use libp2p::{
+    core::{
+        connection::ConnectionId,
+        transport::ListenerId,
+        upgrade::{DeniedUpgrade, EitherUpgrade},
+        ConnectedPoint,
+    },
+    kad::{
+        handler::{KademliaHandler, KademliaHandlerProto},
+        Kademlia, KademliaEvent, QueryId,
+    },
+    swarm::{
+        handler::{InboundUpgradeSend, OutboundUpgradeSend},
+        ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr, DialError,
+        IntoConnectionHandler, KeepAlive, NetworkBehaviour, NetworkBehaviourAction,
+        PollParameters, SubstreamProtocol,
+    },
+    Multiaddr, PeerId,
+};
+
+use super::store::BoxedStore;
+
+// 可切换服务端和客户端模式的Kademlia：客户端模式下拒绝其他节点的Kademlia请求，
+// 只发起查询，避免无法被直接连接的节点出现在其他节点的路由表中
+pub struct Dht {
+    kademlia: Kademlia<BoxedStore>,
+    // 所有连接处理器共享的模式
+    server: Arc<AtomicBool>,
+}
+
+impl Dht {
+    pub fn new(kademlia: Kademlia<BoxedStore>) -> Self {
+        Dht {
+            kademlia,
+            server: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn is_server(&self) -> bool {
+        self.server.load(Ordering::Relaxed)
+    }
+
+    // 切换模式，已有连接之后协商的入站子流同样按新模式处理
+    pub fn set_server(&mut self, server: bool) {
+        self.server.store(server, Ordering::Relaxed);
+    }
+
+    // 包装Kademlia创建的处理器，协议配置和空闲超时与Kademlia的配置一致
+    fn wrap(&self, inner: KademliaHandlerProto<QueryId>) -> DhtHandlerProto {
+        DhtHandlerProto {
+            inner,
+            server: self.server.clone(),
+        }
+    }
+}
+
+impl Deref for Dht {
+    type Target = Kademlia<BoxedStore>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.kademlia
+    }
+}
+
+impl DerefMut for Dht {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.kademlia
+    }
+}
+
+type Inner = KademliaHandler<QueryId>;
+type HandlerEvent = <Inner as ConnectionHandler>::OutEvent;
+
+impl NetworkBehaviour for Dht {
+    type ConnectionHandler = DhtHandlerProto;
+    type OutEvent = KademliaEvent;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        let inner = self.kademlia.new_handler();
+        self.wrap(inner)
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.kademlia.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        failed_addresses: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        self.kademlia.inject_connection_established(
+            peer_id,
+            connection_id,
+            endpoint,
+            failed_addresses,
+            other_established,
+        )
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        self.kademlia.inject_connection_closed(
+            peer_id,
+            connection_id,
+            endpoint,
+            handler.inner,
+            remaining_established,
+        )
+    }
+
+    fn inject_address_change(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        old: &ConnectedPoint,
+        new: &ConnectedPoint,
+    ) {
+        self.kademlia
+            .inject_address_change(peer_id, connection_id, old, new)
+    }
+
+    fn inject_event(&mut self, peer_id: PeerId, connection: ConnectionId, event: HandlerEvent) {
+        self.kademlia.inject_event(peer_id, connection, event)
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer_id: Option<PeerId>,
+        handler: Self::ConnectionHandler,
+        error: &DialError,
+    ) {
+        self.kademlia.inject_dial_failure(peer_id, handler.inner, error)
+    }
+
+    fn inject_listen_failure(
+        &mut self,
+        local_addr: &Multiaddr,
+        send_back_addr: &Multiaddr,
+        handler: Self::ConnectionHandler,
+    ) {
+        self.kademlia
+            .inject_listen_failure(local_addr, send_back_addr, handler.inner)
+    }
+
+    fn inject_new_listener(&mut self, id: ListenerId) {
+        self.kademlia.inject_new_listener(id)
+    }
+
+    fn inject_new_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.kademlia.inject_new_listen_addr(id, addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.kademlia.inject_expired_listen_addr(id, addr)
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        self.kademlia.inject_listener_error(id, err)
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &io::Error>) {
+        self.kademlia.inject_listener_closed(id, reason)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.kademlia.inject_new_external_addr(addr)
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.kademlia.inject_expired_external_addr(addr)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        // Kademlia发起连接时使用自己创建的处理器，需包装为可切换模式的处理器
+        let server = &self.server;
+        self.kademlia.poll(cx, params).map(|action| {
+            action.map_handler(|inner| DhtHandlerProto {
+                inner,
+                server: server.clone(),
+            })
+        })
+    }
+}
+
+pub struct DhtHandlerProto {
+    inner: KademliaHandlerProto<QueryId>,
+    server: Arc<AtomicBool>,
+}
+
+impl IntoConnectionHandler for DhtHandlerProto {
+    type Handler = DhtHandler;
+
+    fn into_handler(self, remote_peer_id: &PeerId, endpoint: &ConnectedPoint) -> Self::Handler {
+        DhtHandler {
+            inner: self.inner.into_handler(remote_peer_id, endpoint),
+            server: self.server,
+        }
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ConnectionHandler>::InboundProtocol {
+        if self.server.load(Ordering::Relaxed) {
+            self.inner.inbound_protocol()
+        } else {
+            EitherUpgrade::B(DeniedUpgrade)
+        }
+    }
+}
+
+// 每次协商入站子流时按当前模式决定是否接受Kademlia请求，其余均交给Kademlia的处理器
+pub struct DhtHandler {
+    inner: Inner,
+    server: Arc<AtomicBool>,
+}
+
+impl ConnectionHandler for DhtHandler {
+    type InEvent = <Inner as ConnectionHandler>::InEvent;
+    type OutEvent = HandlerEvent;
+    type Error = <Inner as ConnectionHandler>::Error;
+    type InboundProtocol = <Inner as ConnectionHandler>::InboundProtocol;
+    type OutboundProtocol = <Inner as ConnectionHandler>::OutboundProtocol;
+    type InboundOpenInfo = <Inner as ConnectionHandler>::InboundOpenInfo;
+    type OutboundOpenInfo = <Inner as ConnectionHandler>::OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        if self.server.load(Ordering::Relaxed) {
+            self.inner.listen_protocol()
+        } else {
+            SubstreamProtocol::new(EitherUpgrade::B(DeniedUpgrade), ())
+        }
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        protocol: <Self::InboundProtocol as InboundUpgradeSend>::Output,
+        info: Self::InboundOpenInfo,
+    ) {
+        self.inner.inject_fully_negotiated_inbound(protocol, info)
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        protocol: <Self::OutboundProtocol as OutboundUpgradeSend>::Output,
+        info: Self::OutboundOpenInfo,
+    ) {
+        self.inner.inject_fully_negotiated_outbound(protocol, info)
+    }
+
+    fn inject_event(&mut self, event: Self::InEvent) {
+        self.inner.inject_event(event)
+    }
+
+    fn inject_address_change(&mut self, new_address: &Multiaddr) {
+        self.inner.inject_address_change(new_address)
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: Self::OutboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgradeSend>::Error>,
+    ) {
+        self.inner.inject_dial_upgrade_error(info, error)
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        info: Self::InboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<<Self::InboundProtocol as InboundUpgradeSend>::Error>,
+    ) {
+        self.inner.inject_listen_upgrade_error(info, error)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.inner.connection_keep_alive()
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        self.inner.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::{
+        core::Endpoint,
+        kad::{store::MemoryStore, KademliaConfig},
+    };
+
+    use super::*;
+
+    fn dht() -> Dht {
+        let peer_id = PeerId::random();
+        Dht::new(Kademlia::new(peer_id, BoxedStore::new(MemoryStore::new(peer_id))))
+    }
+
+    fn accepts_requests(handler: &DhtHandler) -> bool {
+        matches!(handler.listen_protocol().upgrade(), EitherUpgrade::A(_))
+    }
+
+    #[test]
+    fn mode_change_applies_to_existing_connections() {
+        let mut dht = dht();
+        let endpoint = ConnectedPoint::Dialer {
+            address: "/ip4/10.0.0.1/tcp/4001".parse().unwrap(),
+            role_override: Endpoint::Dialer,
+        };
+        let handler = dht.new_handler().into_handler(&PeerId::random(), &endpoint);
+        assert!(accepts_requests(&handler));
+
+        dht.set_server(false);
+        assert!(!accepts_requests(&handler));
+        assert!(matches!(dht.new_handler().inbound_protocol(), EitherUpgrade::B(_)));
+
+        dht.set_server(true);
+        assert!(accepts_requests(&handler));
+    }
+
+    #[test]
+    fn handler_uses_the_kademlia_protocol_name() {
+        let peer_id = PeerId::random();
+        let mut config = KademliaConfig::default();
+        config.set_protocol_name(&b"/test/kad/1.0.0"[..]);
+        let mut dht = Dht::new(Kademlia::with_config(peer_id, BoxedStore::new(MemoryStore::new(peer_id)), config));
+        match dht.new_handler().inbound_protocol() {
+            EitherUpgrade::A(protocol) => assert_eq!(protocol.protocol_name(), b"/test/kad/1.0.0"),
+            EitherUpgrade::B(_) => panic!("Server mode to accept requests."),
+        }
+    }
+}
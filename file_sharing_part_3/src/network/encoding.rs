@@ -0,0 +1,19 @@
+// 写入2字节长度前缀的字段
+pub fn put_prefixed(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(field);
+}
+
+pub fn split(bytes: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+    if bytes.len() < at {
+        return None;
+    }
+    Some(bytes.split_at(at))
+}
+
+// 读取2字节长度前缀的字段
+pub fn split_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = split(bytes, 2)?;
+    let len = u16::from_be_bytes(len.try_into().ok()?) as usize;
+    split(rest, len)
+}
@@ -1,31 +1,120 @@
 use std::{
     collections::{HashMap, HashSet},
     error::Error,
+    num::NonZeroUsize,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use futures::{io, StreamExt};
 use libp2p::{
-    core::either::EitherError,
-    kad::{GetProvidersOk, KademliaEvent, QueryId, QueryResult},
+    autonat::{self, NatStatus},
+    dcutr,
+    core::{transport::ListenerId, ConnectedPoint},
+    identify::IdentifyEvent,
+    identity::Keypair,
+    kad::{
+        record::Key, store::RecordStore, GetClosestPeersError, GetClosestPeersOk, GetProvidersOk, GetRecordError, GetRecordOk,
+        KademliaEvent, QueryId, QueryResult, Quorum, Record,
+    },
     multiaddr::Protocol,
-    request_response::{RequestId, RequestResponseEvent, RequestResponseMessage, ResponseChannel},
-    swarm::{ConnectionHandlerUpgrErr, SwarmEvent},
-    PeerId, Swarm,
+    relay::v2::client,
+    request_response::{
+        OutboundFailure, RequestId, RequestResponseEvent, RequestResponseMessage, ResponseChannel,
+    },
+    swarm::{
+        dial_opts::DialOpts, AddAddressResult, AddressScore, DialError, NetworkBehaviour,
+        PendingConnectionError, SwarmEvent,
+    },
+    Multiaddr, PeerId, Swarm,
+};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    time::{self, Instant, MissedTickBehavior},
 };
-use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, info_span, warn, Span};
 
 use crate::client::Command;
 
 use super::{
+    address_book::AddressBook,
+    connection::{close_reason, ConnectionManager},
+    metrics::Metrics,
     behaviour::{ComposedBehaviour, ComposedEvent},
+    access::AccessPolicy,
     protocol::{FileRequest, FileResponse},
+    record::NameRecord,
+    reputation::{Outcome, Reputation},
+    throttle::{UploadLimits, UploadQueue},
+    transfer::{ChunkOutcome, Download, DownloadedFile, TransferId},
+    NAME_RESOLVE_QUORUM, PROVIDER_REPUBLISH_INTERVAL, RELAY_HOP_PROTOCOL,
+    METRICS_UPDATE_INTERVAL, RECONNECT_CHECK_INTERVAL, REPUTATION_SAVE_INTERVAL, UPLOAD_SCHEDULE_INTERVAL,
 };
 
+type ResultSender<T> = oneshot::Sender<Result<T, Box<dyn Error + Send>>>;
+
+// 收到的文件请求，由唯一的请求处理者响应
 #[derive(Debug)]
+pub struct InboundRequest {
+    pub peer: PeerId,
+    pub request_id: RequestId,
+    pub request: FileRequest,
+    pub channel: ResponseChannel<FileResponse>,
+}
+
+// 网络事件，广播给所有订阅者
+#[derive(Debug, Clone)]
 pub enum Event {
-    InboundRequest {
-        request: String,
-        channel: ResponseChannel<FileResponse>,
+    // 开始在新地址上监听，地址包含本节点ID
+    ListeningOn { address: Multiaddr },
+    // 与节点建立第一个连接
+    PeerConnected { peer: PeerId },
+    // 与节点的所有连接均已断开，reason为最后一个连接关闭的原因
+    PeerDisconnected { peer: PeerId, reason: String },
+    // 节点评分低于阈值，已断开连接并封禁
+    PeerBanned { peer: PeerId, score: f64 },
+    // AutoNAT对本节点能否被直接连接的判断发生变化
+    ReachabilityChanged { status: NatStatus },
+    // 找到提供共享文件的节点
+    ProvidersFound {
+        file_name: String,
+        providers: HashSet<PeerId>,
+    },
+    // 获取到文件清单，开始下载分块
+    TransferStarted {
+        transfer: TransferId,
+        file_name: String,
+        size: u64,
+        chunks: usize,
+    },
+    // 从节点收到数据
+    BytesTransferred {
+        transfer: TransferId,
+        peer: PeerId,
+        bytes: usize,
+    },
+    // 分块通过校验
+    ChunkVerified {
+        transfer: TransferId,
+        peer: PeerId,
+        chunk: u64,
+    },
+    // 下载完成，文件通过校验
+    TransferCompleted {
+        transfer: TransferId,
+        file_name: String,
+        size: u64,
+        // 是否有数据经由中继传输
+        relayed: bool,
+    },
+    // 下载失败
+    TransferFailed {
+        transfer: TransferId,
+        file_name: String,
+        error: String,
     },
 }
 
@@ -33,56 +122,532 @@ pub enum Event {
 pub struct EventLoop {
     // P2P网络管理组件
     swarm: Swarm<ComposedBehaviour>,
+    // 本节点密钥对，用于签名名称记录
+    keypair: Keypair,
     // 命令通道接收端
     command_receiver: mpsc::Receiver<Command>,
-    // 事件通道发送端
-    event_sender: mpsc::Sender<Event>,
-    // 缓存等待链接节点的请求
-    pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), Box<dyn Error + Send>>>>,
+    // 事件广播发送端
+    event_sender: broadcast::Sender<Event>,
+    // 文件请求通道发送端
+    request_sender: mpsc::Sender<InboundRequest>,
+    // 缓存等待链接节点的请求及发出请求时的span，本节点拨号成功时返回使用的地址
+    pending_dial: HashMap<PeerId, Vec<(ResultSender<Multiaddr>, Span)>>,
+    // 各节点正在进行的拨号数量，包括其他协议发起的拨号；所有拨号都失败后才算连接失败
+    dials_in_flight: HashMap<PeerId, usize>,
+    // 缓存查找最近节点的请求
+    pending_get_closest_peers: HashMap<QueryId, (oneshot::Sender<Vec<PeerId>>, Span)>,
+    // 已连接节点的地址，优先使用本节点拨号使用的地址
+    connected_addresses: HashMap<PeerId, Multiaddr>,
     // 缓存节点提供共享文件的请求
-    pending_start_providing: HashMap<QueryId, oneshot::Sender<()>>,
+    pending_start_providing: HashMap<QueryId, ResultSender<()>>,
     // 缓存获取提供共享文件节点的请求
-    pending_get_providers: HashMap<QueryId, oneshot::Sender<HashSet<PeerId>>>,
-    // 缓存获取共享文件内容的请求
-    pending_request_file:
-        HashMap<RequestId, oneshot::Sender<Result<String, Box<dyn Error + Send>>>>,
+    pending_get_providers: HashMap<QueryId, (oneshot::Sender<HashSet<PeerId>>, Span)>,
+    // 进行中的下载任务、用于发送文件内容的通道及下载任务的span
+    downloads: HashMap<TransferId, (Download, ResultSender<DownloadedFile>, Span)>,
+    // 缓存下载任务发出的请求：任务ID，提供者，分块序号，发送时间
+    pending_download_requests: HashMap<RequestId, (TransferId, PeerId, Option<u64>, Instant)>,
+    // 下一个下载任务ID
+    next_transfer_id: TransferId,
+    // 缓存发布名称记录的请求及记录序列号
+    pending_publish_name: HashMap<QueryId, (u64, ResultSender<u64>)>,
+    // 缓存解析名称记录的请求及发布者
+    pending_resolve_name: HashMap<QueryId, (PeerId, ResultSender<String>)>,
+    // 本节点正在提供的共享文件，定期重新发布提供者记录
+    providing: HashSet<Key>,
+    // 共享文件的访问策略，未设置的文件为公开
+    access_policies: HashMap<String, AccessPolicy>,
+    // 收到的文件请求及请求节点
+    inbound_requests: HashMap<RequestId, PeerId>,
+    // 等待上传的文件响应
+    uploads: UploadQueue<(RequestId, FileResponse, ResponseChannel<FileResponse>)>,
+    // 已发送、尚未完成的文件响应
+    uploading: HashSet<RequestId>,
+    // 提供者的信誉评分
+    reputation: Reputation,
+    // 已知节点的地址，重启后用于重新加入网络
+    address_book: AddressBook,
+    // 保持与引导节点、中继等节点的连接
+    connections: ConnectionManager,
+    // 在中继上监听的地址，中继连接断开后监听关闭，重新连接中继后再次监听
+    relay_listeners: HashMap<ListenerId, (PeerId, Multiaddr)>,
+    closed_relay_listeners: HashMap<PeerId, Multiaddr>,
+    // 已知的中继及其地址，用于连接地址未知的提供者
+    relays: HashMap<PeerId, Vec<Multiaddr>>,
+    // 各节点经由中继的连接数量，打洞成功后中继连接随即关闭
+    relayed_connections: HashMap<PeerId, usize>,
+    // 运行指标
+    metrics: Metrics,
 }
 
 impl EventLoop {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         swarm: Swarm<ComposedBehaviour>,
+        keypair: Keypair,
         command_receiver: mpsc::Receiver<Command>,
-        event_sender: mpsc::Sender<Event>,
+        event_sender: broadcast::Sender<Event>,
+        request_sender: mpsc::Sender<InboundRequest>,
+        upload_limits: UploadLimits,
+        reputation: Reputation,
+        address_book: AddressBook,
+        connections: ConnectionManager,
+        relays: Vec<(PeerId, Multiaddr, ListenerId, Multiaddr)>,
+        metrics: Metrics,
     ) -> Self {
+        let mut swarm = swarm;
+        // 上次运行时封禁的节点仍在封禁期内
+        for peer in reputation.banned() {
+            swarm.ban_peer_id(peer);
+        }
         Self {
             swarm,
+            keypair,
             command_receiver,
             event_sender,
+            request_sender,
             pending_dial: Default::default(),
+            dials_in_flight: Default::default(),
+            pending_get_closest_peers: Default::default(),
+            connected_addresses: Default::default(),
             pending_start_providing: Default::default(),
             pending_get_providers: Default::default(),
-            pending_request_file: Default::default(),
+            downloads: Default::default(),
+            pending_download_requests: Default::default(),
+            next_transfer_id: 0,
+            pending_publish_name: Default::default(),
+            pending_resolve_name: Default::default(),
+            providing: Default::default(),
+            access_policies: Default::default(),
+            inbound_requests: Default::default(),
+            uploads: UploadQueue::new(upload_limits),
+            uploading: Default::default(),
+            reputation,
+            address_book,
+            connections,
+            relay_listeners: relays
+                .iter()
+                .map(|(peer, _, listener_id, circuit_addr)| (*listener_id, (*peer, circuit_addr.clone())))
+                .collect(),
+            closed_relay_listeners: Default::default(),
+            relays: relays
+                .into_iter()
+                .map(|(peer, addr, ..)| (peer, vec![addr]))
+                .collect(),
+            relayed_connections: Default::default(),
+            metrics,
         }
     }
 
     pub async fn run(mut self) {
+        // 定期重新发布提供者记录，避免记录过期
+        let mut republish = time::interval_at(
+            Instant::now() + PROVIDER_REPUBLISH_INTERVAL,
+            PROVIDER_REPUBLISH_INTERVAL,
+        );
+
+        let mut upload_schedule = time::interval(UPLOAD_SCHEDULE_INTERVAL);
+        upload_schedule.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut reputation_save = time::interval(REPUTATION_SAVE_INTERVAL);
+        let mut reconnect = time::interval(RECONNECT_CHECK_INTERVAL);
+        let mut metrics_update = time::interval(METRICS_UPDATE_INTERVAL);
+
         // 异步轮询事件
         loop {
             tokio::select! {
                 event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite.")).await,
                 command = self.command_receiver.recv() => match command {
                     Some(c) => self.handle_command(c).await,
-                    None=>  return,
+                    None => {
+                        // 所有Client已退出，撤回本节点的提供者记录并保存节点评分和地址簿
+                        self.withdraw_providers();
+                        self.save_reputation();
+                        self.save_address_book();
+                        return;
+                    }
                 },
+                _ = republish.tick() => self.republish_providers(),
+                // 令牌补充后继续发送等待中的响应，没有等待的响应时不需要定时检查
+                _ = upload_schedule.tick(), if self.uploads.waiting() > 0 => self.send_ready_uploads(),
+                _ = reconnect.tick() => self.reconnect_sticky_peers(),
+                _ = metrics_update.tick() => self.update_metrics(),
+                _ = reputation_save.tick() => {
+                    self.expire_bans();
+                    self.save_reputation();
+                    self.save_address_book();
+                }
             }
         }
     }
 
-    // 异步处理网络行为事件
-    async fn handle_event(
+    // 重新发布所有正在提供的共享文件的提供者记录
+    fn republish_providers(&mut self) {
+        for key in self.providing.iter() {
+            if let Err(e) = self
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .start_providing(key.clone())
+            {
+                warn!(key = %String::from_utf8_lossy(key.as_ref()), error = ?e, "Failed to republish provider record");
+            }
+        }
+    }
+
+    // 在并发和速率限制内发送等待中的文件响应
+    fn send_ready_uploads(&mut self) {
+        while let Some((peer, (request_id, response, channel))) = self.uploads.pop_ready() {
+            let bytes = response.payload_len();
+            match self
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_response(channel, response)
+            {
+                Ok(()) => {
+                    debug!(%peer, ?request_id, bytes, "Sending response");
+                    self.metrics.record_upload(bytes);
+                    self.uploading.insert(request_id);
+                }
+                Err(_) => {
+                    warn!(%peer, ?request_id, "Connection closed before upload started");
+                    self.uploads.finish();
+                }
+            }
+        }
+    }
+
+    // 发起拨号并记录正在进行的拨号，无法发起时需要保持连接的节点稍后重试
+    pub(crate) fn dial(&mut self, opts: DialOpts) -> Result<(), DialError> {
+        let peer = opts.get_peer_id();
+        let result = self.swarm.dial(opts);
+        if let Some(peer) = peer {
+            match &result {
+                Ok(()) => *self.dials_in_flight.entry(peer).or_default() += 1,
+                Err(_) => self.connections.dial_failed(&peer),
+            }
+        }
+        result
+    }
+
+    // 一次拨号结束，返回该节点是否已没有正在进行的拨号
+    fn dial_finished(&mut self, peer: &PeerId) -> bool {
+        match self.dials_in_flight.get_mut(peer) {
+            Some(n) if *n > 1 => {
+                *n -= 1;
+                false
+            }
+            _ => {
+                self.dials_in_flight.remove(peer);
+                true
+            }
+        }
+    }
+
+    // 重新连接已断开且到达重连时间的节点
+    fn reconnect_sticky_peers(&mut self) {
+        for (peer, addresses, failures) in self.connections.due(Instant::now()) {
+            if self.swarm.is_connected(&peer) {
+                self.connections.connected(&peer);
+                continue;
+            }
+            info!(%peer, attempt = failures + 1, "Reconnecting");
+            let opts = DialOpts::peer_id(peer)
+                .addresses(addresses)
+                .extend_addresses_through_behaviour()
+                .build();
+            if let Err(e) = self.dial(opts) {
+                warn!(%peer, error = %e, "Failed to dial");
+            }
+        }
+    }
+
+    fn update_metrics(&mut self) {
+        let routing_table_size = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .map(|bucket| bucket.num_entries())
+            .sum();
+        self.metrics.set_state(
+            self.swarm.connected_peers().count(),
+            routing_table_size,
+            self.downloads.len(),
+            self.uploading.len(),
+        );
+    }
+
+    // 解除到期的封禁
+    fn expire_bans(&mut self) {
+        for peer in self.reputation.expire_bans() {
+            self.swarm.unban_peer_id(peer);
+        }
+    }
+
+    fn save_reputation(&mut self) {
+        if let Err(e) = self.reputation.save() {
+            warn!(error = %e, "Failed to save peer scores");
+        }
+    }
+
+    fn save_address_book(&mut self) {
+        if let Err(e) = self.address_book.save() {
+            warn!(error = %e, "Failed to save address book");
+        }
+    }
+
+    // 记录提供者的请求结果，评分低于阈值时封禁该节点
+    fn record_outcome(&mut self, peer: PeerId, outcome: Outcome) {
+        if !self.reputation.record(peer, outcome) {
+            return;
+        }
+        // 封禁后断开连接，并不再使用该节点下载
+        self.swarm.ban_peer_id(peer);
+        self.swarm.behaviour_mut().kademlia.remove_peer(&peer);
+        self.address_book.remove(&peer);
+        for (download, _, _) in self.downloads.values_mut() {
+            download.remove_provider(&peer);
+        }
+        warn!(%peer, score = self.reputation.score(&peer), "Banned peer");
+        self.emit(Event::PeerBanned {
+            peer,
+            score: self.reputation.score(&peer),
+        });
+    }
+
+    // 广播事件，没有订阅者时丢弃事件
+    fn emit(&mut self, event: Event) {
+        let _ = self.event_sender.send(event);
+    }
+
+    // 处理下载任务收到的响应
+    fn handle_download_response(
         &mut self,
-        event: SwarmEvent<ComposedEvent,EitherError<ConnectionHandlerUpgrErr<io::Error>, io::Error>>,
+        request_id: RequestId,
+        transfer: TransferId,
+        peer: PeerId,
+        chunk: Option<u64>,
+        sent: Instant,
+        response: FileResponse,
     ) {
+        let mut events = Vec::new();
+        let mut outcomes = Vec::new();
+        let elapsed = sent.elapsed();
+        // 请求可能经由该节点的任一连接发送，存在中继连接时视为经由中继；
+        // 打洞成功后中继连接被关闭，之后收到的响应只经由直连
+        let relayed = self.relayed_connections.contains_key(&peer);
+        if let Some((download, _, span)) = self.downloads.get_mut(&transfer) {
+            download.relayed |= relayed;
+            match (chunk, response) {
+                (None, FileResponse::Manifest(manifest)) if manifest.is_consistent() => {
+                    span.in_scope(|| {
+                        info!(
+                            %peer,
+                            ?request_id,
+                            size = manifest.size,
+                            chunks = manifest.chunk_digests.len(),
+                            "Received manifest"
+                        )
+                    });
+                    events.push(Event::TransferStarted {
+                        transfer,
+                        file_name: download.file_name.clone(),
+                        size: manifest.size,
+                        chunks: manifest.chunk_digests.len(),
+                    });
+                    download.set_manifest(peer, manifest);
+                    outcomes.push((peer, Outcome::Success { bytes: 0, elapsed }));
+                }
+                (Some(index), FileResponse::Chunk(data)) => {
+                    let bytes = data.len();
+                    span.in_scope(|| debug!(%peer, ?request_id, chunk = index, bytes, relayed, "Received chunk"));
+                    self.metrics.record_download(bytes);
+                    events.push(Event::BytesTransferred {
+                        transfer,
+                        peer,
+                        bytes,
+                    });
+                    match download.on_chunk(peer, index, data) {
+                        ChunkOutcome::Verified { culprits } => {
+                            events.push(Event::ChunkVerified {
+                                transfer,
+                                peer,
+                                chunk: index,
+                            });
+                            outcomes.push((peer, Outcome::Success { bytes, elapsed }));
+                            // 其他节点的同一分块通过校验，说明此前发送错误数据的是这些节点
+                            for culprit in culprits {
+                                span.in_scope(|| warn!(peer = %culprit, chunk = index, "Peer sent a corrupted chunk"));
+                                outcomes.push((culprit, Outcome::VerificationFailed));
+                            }
+                        }
+                        // 暂不影响评分，直到确定是分块还是清单有误
+                        ChunkOutcome::Rejected => {
+                            span.in_scope(|| warn!(%peer, ?request_id, chunk = index, "Chunk failed verification"));
+                        }
+                        ChunkOutcome::ManifestRejected { manifest_peer } => {
+                            span.in_scope(|| {
+                                warn!(
+                                    %peer,
+                                    ?request_id,
+                                    chunk = index,
+                                    %manifest_peer,
+                                    "Chunks disagree with the manifest, fetching it from another provider"
+                                )
+                            });
+                            outcomes.push((manifest_peer, Outcome::VerificationFailed));
+                        }
+                        ChunkOutcome::Stale => {}
+                    }
+                }
+                // 拒绝请求是访问策略的结果，不影响评分
+                (_, FileResponse::Forbidden) => {
+                    span.in_scope(|| warn!(%peer, ?request_id, "Request forbidden"));
+                    download.on_failure(peer, chunk, format!("Peer {} forbade the request.", peer));
+                }
+                (_, _) => {
+                    span.in_scope(|| warn!(%peer, ?request_id, "Invalid response"));
+                    download.on_failure(peer, chunk, format!("Peer {} sent an invalid response.", peer));
+                    outcomes.push((peer, Outcome::Failed));
+                }
+            }
+        }
+
+        for event in events {
+            self.emit(event);
+        }
+        for (peer, outcome) in outcomes {
+            self.record_outcome(peer, outcome);
+        }
+        self.advance_download(transfer);
+    }
+
+    // 推进下载任务：发送新的请求，完成或失败时发送结果
+    fn advance_download(&mut self, transfer: TransferId) {
+        let (download, _, span) = match self.downloads.get_mut(&transfer) {
+            Some(download) => download,
+            None => return,
+        };
+
+        if download.is_complete() {
+            // 分块均与清单一致但整个文件不一致，说明清单有误，从其他提供者重新获取
+            let result = match download.assemble() {
+                Err(_) if download.manifest_peer().is_some() => {
+                    let peer = download.reject_manifest().expect("Manifest peer to be set.");
+                    span.in_scope(|| warn!(%peer, "File disagrees with the manifest, fetching it from another provider"));
+                    self.record_outcome(peer, Outcome::VerificationFailed);
+                    return self.advance_download(transfer);
+                }
+                result => result,
+            };
+            let (download, sender, span) = self.downloads.remove(&transfer).expect("Download to exist.");
+            match result {
+                Ok(file) => {
+                    let size = file.content.len() as u64;
+                    span.in_scope(|| info!(size, relayed = download.relayed, "Download completed"));
+                    self.emit(Event::TransferCompleted {
+                        transfer,
+                        file_name: download.file_name,
+                        size,
+                        relayed: download.relayed,
+                    });
+                    let _ = sender.send(Ok(file));
+                }
+                Err(error) => self.fail_download(transfer, download, sender, span, error),
+            }
+            return;
+        }
+
+        while let Some((peer, chunk)) = download.next_request() {
+            let request_id = self.swarm.behaviour_mut().request_response.send_request(
+                &peer,
+                FileRequest {
+                    file_name: download.file_name.clone(),
+                    token: download.token.clone(),
+                    chunk,
+                },
+            );
+            span.in_scope(|| debug!(%peer, ?request_id, ?chunk, "Sent file request"));
+            self.pending_download_requests
+                .insert(request_id, (transfer, peer, chunk, Instant::now()));
+        }
+
+        if download.is_stalled() {
+            let (download, sender, span) = self.downloads.remove(&transfer).expect("Download to exist.");
+            let error = download.last_error();
+            self.fail_download(transfer, download, sender, span, error);
+        }
+    }
+
+    fn fail_download(
+        &mut self,
+        transfer: TransferId,
+        download: Download,
+        sender: ResultSender<DownloadedFile>,
+        span: Span,
+        error: String,
+    ) {
+        span.in_scope(|| warn!(%error, "Download failed"));
+        self.emit(Event::TransferFailed {
+            transfer,
+            file_name: download.file_name,
+            error: error.clone(),
+        });
+        let _ = sender.send(Err(Box::new(io::Error::other(error))));
+    }
+
+    // 一个文件响应已结束，释放并发名额
+    fn finish_upload(&mut self, request_id: &RequestId) {
+        self.inbound_requests.remove(request_id);
+        if self.uploading.remove(request_id) {
+            self.uploads.finish();
+            self.send_ready_uploads();
+        }
+    }
+
+    // 撤回所有提供者记录，并检查本地存储中不再保留任何提供者记录；
+    // 已发布到其他节点的记录无法撤回，只能等待过期
+    fn withdraw_providers(&mut self) {
+        let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+        let withdrawn = self.providing.len();
+        for key in self.providing.drain() {
+            kademlia.stop_providing(&key);
+        }
+
+        let remaining = kademlia.store_mut().provided().count();
+        if remaining > 0 {
+            warn!(remaining, "Provider records still held after withdrawal");
+        } else if withdrawn > 0 {
+            info!(withdrawn, "Withdrew provider records");
+        }
+    }
+
+    // 为地址未知的提供者添加经由已知中继的地址，直连地址已知时不使用中继
+    fn add_relayed_addresses(&mut self, peer: &PeerId) {
+        if self.swarm.is_connected(peer) {
+            return;
+        }
+        let behaviour = self.swarm.behaviour_mut();
+        if !behaviour.kademlia.addresses_of_peer(peer).is_empty() {
+            return;
+        }
+        for (relay, addrs) in &self.relays {
+            for addr in addrs {
+                let addr = match addr.iter().last() {
+                    Some(Protocol::P2p(_)) => addr.clone(),
+                    _ => addr.clone().with(Protocol::P2p((*relay).into())),
+                };
+                behaviour.request_response.add_address(
+                    peer,
+                    addr.with(Protocol::P2pCircuit).with(Protocol::P2p((*peer).into())),
+                );
+            }
+        }
+    }
+
+    // 异步处理网络行为事件
+    async fn handle_event<E: std::fmt::Debug>(&mut self, event: SwarmEvent<ComposedEvent, E>) {
+        self.metrics.record(&event);
         match event {
             // 节点提供共享文件事件
             SwarmEvent::Behaviour(ComposedEvent::Kademlia(
@@ -92,55 +657,181 @@ impl EventLoop {
                     ..
                 },
             )) => {
-                // 从缓存中节点提供共享文件的请求
-                let sender: oneshot::Sender<()> = self
-                    .pending_start_providing
-                    .remove(&id)
-                    .expect("Completed query to be previously pending.");
-
-                // 发送命令执行成功状态    
-                let _ = sender.send(());
+                // 从缓存中删除节点提供共享文件的请求，重新发布的记录没有等待的请求
+                if let Some(sender) = self.pending_start_providing.remove(&id) {
+                    // 发送命令执行成功状态
+                    let _ = sender.send(Ok(()));
+                }
             }
             // 获取提供共享文件的节点事件
             SwarmEvent::Behaviour(ComposedEvent::Kademlia(
                 KademliaEvent::OutboundQueryCompleted {
                     id,
-                    result: QueryResult::GetProviders(Ok(GetProvidersOk { providers, .. })),
+                    result: QueryResult::GetProviders(Ok(GetProvidersOk { key, providers, .. })),
                     ..
                 },
             )) => {
+                self.emit(Event::ProvidersFound {
+                    file_name: String::from_utf8_lossy(key.as_ref()).into_owned(),
+                    providers: providers.clone(),
+                });
                 // 从缓存中删除获取提供共享文件节点的请求，并发送提供的节点
-                let _ = self
-                    .pending_get_providers
-                    .remove(&id)
-                    .expect("Completed query to be previously pending.")
-                    .send(providers);
+                if let Some((sender, span)) = self.pending_get_providers.remove(&id) {
+                    span.in_scope(|| info!(query_id = ?id, providers = providers.len(), "Found providers"));
+                    let _ = sender.send(providers);
+                }
+            }
+            // 查询超时，没有找到提供共享文件的节点
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(
+                KademliaEvent::OutboundQueryCompleted {
+                    id,
+                    result: QueryResult::GetProviders(Err(e)),
+                    ..
+                },
+            )) => {
+                if let Some((sender, span)) = self.pending_get_providers.remove(&id) {
+                    span.in_scope(|| warn!(query_id = ?id, error = %e, "Provider lookup failed"));
+                    let _ = sender.send(HashSet::new());
+                }
+            }
+            // 发布名称记录事件
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(
+                KademliaEvent::OutboundQueryCompleted {
+                    id,
+                    result: QueryResult::PutRecord(result),
+                    ..
+                },
+            )) => {
+                if let Some((sequence, sender)) = self.pending_publish_name.remove(&id) {
+                    let _ = match result {
+                        Ok(_) => sender.send(Ok(sequence)),
+                        Err(e) => sender.send(Err(Box::new(e))),
+                    };
+                }
+            }
+            // 解析名称记录事件，未达到法定数量时使用已获取的记录
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(
+                KademliaEvent::OutboundQueryCompleted {
+                    id,
+                    result: QueryResult::GetRecord(result),
+                    ..
+                },
+            )) => {
+                if let Some((publisher, sender)) = self.pending_resolve_name.remove(&id) {
+                    let records = match result {
+                        Ok(GetRecordOk { records, .. })
+                        | Err(GetRecordError::QuorumFailed { records, .. })
+                        | Err(GetRecordError::Timeout { records, .. }) => records,
+                        Err(e) => {
+                            let _ = sender.send(Err(Box::new(e)));
+                            return;
+                        }
+                    };
+
+                    let latest = NameRecord::latest(&publisher, records.iter().map(|r| r.record.value.as_slice()));
+                    let _ = match latest {
+                        Some(record) => sender.send(Ok(record.target)),
+                        None => sender.send(Err(Box::new(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "No valid name record found.",
+                        )))),
+                    };
+                }
+            }
+            // 记录新加入路由表的节点地址，被封禁的节点除外
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(KademliaEvent::RoutingUpdated {
+                peer,
+                addresses,
+                ..
+            })) => {
+                if !self.reputation.is_banned(&peer) {
+                    for addr in addresses.iter() {
+                        self.address_book.add_address(peer, addr.clone());
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(
+                KademliaEvent::OutboundQueryCompleted {
+                    id,
+                    result: QueryResult::GetClosestPeers(result),
+                    ..
+                },
+            )) => {
+                // 超时时返回已找到的节点
+                let peers = match result {
+                    Ok(GetClosestPeersOk { peers, .. }) => peers,
+                    Err(GetClosestPeersError::Timeout { peers, .. }) => peers,
+                };
+                if let Some((sender, span)) = self.pending_get_closest_peers.remove(&id) {
+                    span.in_scope(|| info!(query_id = ?id, peers = peers.len(), "Found closest peers"));
+                    let _ = sender.send(peers);
+                }
             }
             SwarmEvent::Behaviour(ComposedEvent::Kademlia(_)) => {}
             // 请求文件内容事件
             SwarmEvent::Behaviour(ComposedEvent::RequestResponse(
-                RequestResponseEvent::Message { message, .. },
+                RequestResponseEvent::Message { peer, message },
             )) => match message {
                 RequestResponseMessage::Request {
-                    request, channel, ..
+                    request_id,
+                    request,
+                    channel,
                 } => {
-                    self.event_sender
-                        .send(Event::InboundRequest {
-                            request: request.0,
-                            channel,
-                        })
-                        .await
-                        .expect("Event receiver not to be dropped.");
+                    // 检查请求节点是否有权获取文件，无权时直接返回拒绝响应
+                    let permitted = self
+                        .access_policies
+                        .get(&request.file_name)
+                        .is_none_or(|policy| {
+                            policy.permits(
+                                &self.keypair.public(),
+                                &request.file_name,
+                                &peer,
+                                request.token.as_deref(),
+                            )
+                        });
+                    debug!(
+                        %peer,
+                        ?request_id,
+                        file = %request.file_name,
+                        chunk = ?request.chunk,
+                        "Received file request"
+                    );
+                    if !permitted {
+                        info!(%peer, ?request_id, file = %request.file_name, "Denied request");
+                        let _ = self
+                            .swarm
+                            .behaviour_mut()
+                            .request_response
+                            .send_response(channel, FileResponse::Forbidden);
+                        return;
+                    }
+
+                    // 请求处理者处理不及时时丢弃请求，不阻塞网络事件处理
+                    let inbound = InboundRequest {
+                        peer,
+                        request_id,
+                        request,
+                        channel,
+                    };
+                    match self.request_sender.try_send(inbound) {
+                        Ok(()) => {
+                            self.inbound_requests.insert(request_id, peer);
+                        }
+                        Err(TrySendError::Full(_)) => {
+                            warn!(%peer, ?request_id, "Request handler is busy, dropped request");
+                        }
+                        Err(TrySendError::Closed(_)) => {}
+                    }
                 }
                 RequestResponseMessage::Response {
                     request_id,
                     response,
                 } => {
-                    let _ = self
-                        .pending_request_file
-                        .remove(&request_id)
-                        .expect("Request to still be pending.")
-                        .send(Ok(response.0));
+                    if let Some((transfer, peer, chunk, sent)) =
+                        self.pending_download_requests.remove(&request_id)
+                    {
+                        self.handle_download_response(request_id, transfer, peer, chunk, sent, response);
+                    }
                 }
             },
             SwarmEvent::Behaviour(ComposedEvent::RequestResponse(
@@ -148,44 +839,292 @@ impl EventLoop {
                     request_id, error, ..
                 },
             )) => {
-                let _ = self
-                    .pending_request_file
-                    .remove(&request_id)
-                    .expect("Request to still be pending.")
-                    .send(Err(Box::new(error)));
+                if let Some((transfer, peer, chunk, _)) =
+                    self.pending_download_requests.remove(&request_id)
+                {
+                    // 打洞成功后关闭中继连接时，经由中继的请求失败，但对方仍可经由直连访问
+                    let interrupted = matches!(error, OutboundFailure::ConnectionClosed)
+                        && self.swarm.is_connected(&peer);
+                    if let Some((download, _, span)) = self.downloads.get_mut(&transfer) {
+                        if interrupted {
+                            span.in_scope(|| debug!(%peer, ?request_id, "File request interrupted, retrying"));
+                            download.on_interrupted(peer, chunk);
+                        } else {
+                            span.in_scope(|| warn!(%peer, ?request_id, %error, "File request failed"));
+                            download.on_failure(peer, chunk, format!("Request to {} failed: {}", peer, error));
+                        }
+                    }
+                    if !interrupted {
+                        let outcome = match error {
+                            OutboundFailure::Timeout => Outcome::Timeout,
+                            _ => Outcome::Failed,
+                        };
+                        self.record_outcome(peer, outcome);
+                    }
+                    self.advance_download(transfer);
+                }
+            }
+            SwarmEvent::Behaviour(ComposedEvent::RequestResponse(
+                RequestResponseEvent::ResponseSent { request_id, .. },
+            )) => {
+                debug!(?request_id, "Response sent");
+                self.finish_upload(&request_id)
+            }
+            // 将节点的监听地址(包括中继地址)加入路由表，并记录支持中继的节点
+            SwarmEvent::Behaviour(ComposedEvent::Identify(event)) => {
+                if let IdentifyEvent::Received { peer_id, info } = *event {
+                    for addr in &info.listen_addrs {
+                        self.swarm
+                            .behaviour_mut()
+                            .kademlia
+                            .add_address(&peer_id, addr.clone());
+                    }
+                    if info.protocols.iter().any(|p| p == RELAY_HOP_PROTOCOL) {
+                        let addrs = info
+                            .listen_addrs
+                            .into_iter()
+                            .filter(|addr| !is_circuit(addr))
+                            .collect();
+                        self.relays.insert(peer_id, addrs);
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Relay(_)) => {}
+            SwarmEvent::Behaviour(ComposedEvent::RelayClient(event)) => match event {
+                client::Event::ReservationReqAccepted {
+                    relay_peer_id,
+                    renewal: false,
+                    ..
+                } => info!(relay = %relay_peer_id, "Reserved a slot on relay"),
+                client::Event::ReservationReqFailed {
+                    relay_peer_id,
+                    error,
+                    ..
+                } => warn!(relay = %relay_peer_id, ?error, "Failed to reserve a slot on relay"),
+                _ => {}
+            },
+            SwarmEvent::Behaviour(ComposedEvent::Autonat(autonat::Event::StatusChanged {
+                new,
+                ..
+            })) => {
+                // 无法被直接连接时其他节点不应通过本节点查询
+                self.swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .set_server(new != NatStatus::Private);
+                match &new {
+                    NatStatus::Public(address) => {
+                        // 已确认可被直接连接的地址，写入提供者记录
+                        let result = self
+                            .swarm
+                            .add_external_address(address.clone(), AddressScore::Infinite);
+                        if matches!(result, AddAddressResult::Inserted { .. }) {
+                            self.republish_providers();
+                        }
+                    }
+                    NatStatus::Private => {
+                        // 直连地址无法访问，只保留中继地址；Identify之后观察到的地址
+                        // 以有限分数重新加入，仅用于打洞
+                        let direct: Vec<_> = self
+                            .swarm
+                            .external_addresses()
+                            .filter(|record| !is_circuit(&record.addr))
+                            .map(|record| record.addr.clone())
+                            .collect();
+                        for address in &direct {
+                            self.swarm.remove_external_address(address);
+                        }
+                        if !direct.is_empty() {
+                            info!(removed = direct.len(), "Removed unreachable external addresses");
+                        }
+                    }
+                    NatStatus::Unknown => {}
+                }
+                self.emit(Event::ReachabilityChanged { status: new });
             }
+            SwarmEvent::Behaviour(ComposedEvent::Autonat(_)) => {}
+            SwarmEvent::Behaviour(ComposedEvent::Dcutr(event)) => match event {
+                dcutr::behaviour::Event::DirectConnectionUpgradeSucceeded { remote_peer_id } => {
+                    info!(peer = %remote_peer_id, "Upgraded relayed connection to a direct one");
+                    // 请求会分散到该节点的所有连接上，关闭中继连接使之后的传输只经由直连
+                    self.swarm
+                        .behaviour_mut()
+                        .keep_alive
+                        .close_relayed(&remote_peer_id);
+                }
+                dcutr::behaviour::Event::DirectConnectionUpgradeFailed {
+                    remote_peer_id,
+                    error,
+                } => warn!(peer = %remote_peer_id, %error, "Failed to upgrade relayed connection"),
+                _ => {}
+            },
             SwarmEvent::Behaviour(ComposedEvent::RequestResponse(
-                RequestResponseEvent::ResponseSent { .. },
-            )) => {}
+                RequestResponseEvent::InboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                },
+            )) => {
+                warn!(%peer, ?request_id, ?error, "Inbound request failed");
+                self.finish_upload(&request_id);
+            }
             // 本地监听事件
             SwarmEvent::NewListenAddr { address, .. } => {
-                let local_peer_id = *self.swarm.local_peer_id();
-                println!(
-                    "Local node is listening on {:?}",
-                    address.with(Protocol::P2p(local_peer_id.into()))
-                );
+                // 中继地址需作为外部地址才会写入提供者记录；直连的监听地址可能在NAT之后，
+                // 由AutoNAT确认或由Identify观察到后才作为外部地址
+                if is_circuit(&address) {
+                    self.swarm
+                        .add_external_address(address.clone(), AddressScore::Infinite);
+                    self.republish_providers();
+                }
+                // 中继地址已以本地节点ID结尾
+                let address = match address.iter().last() {
+                    Some(Protocol::P2p(_)) => address,
+                    _ => address.with(Protocol::P2p((*self.swarm.local_peer_id()).into())),
+                };
+                self.emit(Event::ListeningOn { address });
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                self.swarm.remove_external_address(&address);
             }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                addresses,
+                reason,
+            } => {
+                if let Some((relay, addr)) = self.relay_listeners.remove(&listener_id) {
+                    self.closed_relay_listeners.insert(relay, addr);
+                }
+                for address in &addresses {
+                    self.swarm.remove_external_address(address);
+                }
+                if let Err(e) = reason {
+                    warn!(?addresses, error = %e, "Listener closed");
+                }
+            }
+            SwarmEvent::ListenerError { error, .. } => warn!(%error, "Listener error"),
             SwarmEvent::IncomingConnection { .. } => {}
             SwarmEvent::ConnectionEstablished {
-                peer_id, endpoint, ..
+                peer_id,
+                endpoint,
+                num_established,
+                ..
             } => {
+                let address = endpoint.get_remote_address().clone();
+                debug!(peer = %peer_id, %address, dialer = endpoint.is_dialer(), "Connection established");
+                // 对方拨入的连接的地址无法用于连接对方，拨号仍在进行，直到拨号结束
                 if endpoint.is_dialer() {
-                    if let Some(sender) = self.pending_dial.remove(&peer_id) {
-                        let _ = sender.send(Ok(()));
+                    self.dial_finished(&peer_id);
+                    for (sender, span) in self.pending_dial.remove(&peer_id).unwrap_or_default() {
+                        span.in_scope(|| info!(peer = %peer_id, %address, "Connected"));
+                        let _ = sender.send(Ok(address.clone()));
+                    }
+                    self.connected_addresses.insert(peer_id, address);
+                } else {
+                    self.connected_addresses.entry(peer_id).or_insert(address);
+                }
+                self.connections.connected(&peer_id);
+                if let Some(addr) = self.closed_relay_listeners.remove(&peer_id) {
+                    match self.swarm.listen_on(addr.clone()) {
+                        Ok(listener_id) => {
+                            self.relay_listeners.insert(listener_id, (peer_id, addr));
+                        }
+                        Err(e) => warn!(address = %addr, error = %e, "Failed to listen"),
+                    }
+                }
+                // 中继地址经由中继节点，不作为对方的地址保存
+                let dialed = match &endpoint {
+                    ConnectedPoint::Dialer { address, .. } if !endpoint.is_relayed() => {
+                        Some(address.clone())
                     }
+                    _ => None,
+                };
+                self.address_book.record_connected(peer_id, dialed);
+                if endpoint.is_relayed() {
+                    *self.relayed_connections.entry(peer_id).or_default() += 1;
+                }
+                if num_established.get() == 1 {
+                    self.emit(Event::PeerConnected { peer: peer_id });
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                num_established,
+                cause,
+            } => {
+                if endpoint.is_relayed() {
+                    if let Some(n) = self.relayed_connections.get_mut(&peer_id) {
+                        *n -= 1;
+                        if *n == 0 {
+                            self.relayed_connections.remove(&peer_id);
+                        }
+                    }
+                }
+                debug!(peer = %peer_id, reason = %close_reason(&cause), "Connection closed");
+                if num_established == 0 {
+                    self.connected_addresses.remove(&peer_id);
+                    self.connections.disconnected(&peer_id);
+                    self.emit(Event::PeerDisconnected {
+                        peer: peer_id,
+                        reason: close_reason(&cause),
+                    });
                 }
             }
-            SwarmEvent::ConnectionClosed { .. } => {}
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                if let DialError::ConnectionLimit(limit) = &error {
+                    warn!(peer = ?peer_id, %limit, "Connection limit reached, dial rejected");
+                } else {
+                    debug!(peer = ?peer_id, %error, "Outgoing connection failed");
+                }
                 if let Some(peer_id) = peer_id {
-                    if let Some(sender) = self.pending_dial.remove(&peer_id) {
-                        let _ = sender.send(Err(Box::new(error)));
+                    if !self.swarm.is_connected(&peer_id) {
+                        self.connections.dial_failed(&peer_id);
+                    }
+                    // 只统计由对方导致的失败
+                    if matches!(
+                        error,
+                        DialError::Transport(_) | DialError::WrongPeerId { .. } | DialError::ConnectionIo(_)
+                    ) {
+                        self.address_book.record_dial_failure(&peer_id);
+                    }
+                    // 其他拨号仍在进行时继续等待；对方已拨入时视为连接成功
+                    if self.dial_finished(&peer_id) {
+                        let connected = self.connected_addresses.get(&peer_id).cloned();
+                        for (sender, span) in self.pending_dial.remove(&peer_id).unwrap_or_default() {
+                            match &connected {
+                                Some(address) => {
+                                    span.in_scope(|| info!(peer = %peer_id, %address, "Connected by the peer"));
+                                    let _ = sender.send(Ok(address.clone()));
+                                }
+                                None => {
+                                    span.in_scope(|| warn!(peer = %peer_id, %error, "Dial failed"));
+                                    let _ = sender.send(Err(Box::new(io::Error::other(error.to_string()))));
+                                }
+                            }
+                        }
                     }
                 }
             }
+            // 正在建立的入站连接超过上限时Swarm直接拒绝，只记录在libp2p的日志中
+            SwarmEvent::IncomingConnectionError {
+                send_back_addr,
+                error: PendingConnectionError::ConnectionLimit(limit),
+                ..
+            } => warn!(
+                address = %send_back_addr,
+                %limit,
+                "Connection limit reached, incoming connection rejected"
+            ),
             SwarmEvent::IncomingConnectionError { .. } => {}
-            SwarmEvent::Dialing(peer_id) => println!("Dialing {}", peer_id),
-            e => panic!("{:?}", e),
+            SwarmEvent::BannedPeer { peer_id, .. } => {
+                info!(peer = %peer_id, "Rejected connection from banned peer")
+            }
+            // 其他协议发起的拨号
+            SwarmEvent::Dialing(peer_id) => {
+                debug!(peer = %peer_id, "Dialing");
+                *self.dials_in_flight.entry(peer_id).or_default() += 1;
+            }
         }
     }
 
@@ -202,69 +1141,213 @@ impl EventLoop {
             // 节点加入KAD网络，链接指定节点，插入缓存
             Command::Dial {
                 peer_id,
-                peer_addr,
+                peer_addrs,
                 sender,
+                span,
             } => {
-                if self.pending_dial.contains_key(&peer_id) {
-                    todo!("Already dialing peer.");
-                } else {
+                if let Some(address) = self.connected_addresses.get(&peer_id) {
+                    span.in_scope(|| debug!(peer = %peer_id, %address, "Already connected"));
+                    let _ = sender.send(Ok(address.clone()));
+                    return;
+                }
+                // 已在拨号的节点等待同一次拨号的结果
+                if let Some(senders) = self.pending_dial.get_mut(&peer_id) {
+                    senders.push((sender, span));
+                    return;
+                }
+                for addr in &peer_addrs {
                     self.swarm
                         .behaviour_mut()
                         .kademlia
-                        .add_address(&peer_id, peer_addr.clone());
-                    match self
-                        .swarm
-                        .dial(peer_addr.with(Protocol::P2p(peer_id.into())))
-                    {
-                        Ok(()) => {
-                            self.pending_dial.insert(peer_id, sender);
-                        }
-                        Err(e) => {
-                            let _ = sender.send(Err(Box::new(e)));
-                        }
+                        .add_address(&peer_id, addr.clone());
+                }
+                // 同时尝试给定地址、地址簿和路由表中的地址
+                let mut addresses = peer_addrs;
+                if let Some(entry) = self.address_book.get(&peer_id) {
+                    addresses.extend(entry.addresses.iter().cloned());
+                }
+                let opts = DialOpts::peer_id(peer_id)
+                    .addresses(addresses)
+                    .extend_addresses_through_behaviour()
+                    .build();
+                match self.dial(opts) {
+                    Ok(()) => {
+                        span.in_scope(|| info!(peer = %peer_id, "Dialing"));
+                        self.pending_dial.insert(peer_id, vec![(sender, span)]);
+                    }
+                    Err(e) => {
+                        span.in_scope(|| warn!(peer = %peer_id, error = %e, "Dial failed"));
+                        let _ = sender.send(Err(Box::new(e)));
                     }
                 }
             }
+            Command::GetClosestPeers {
+                peer_id,
+                sender,
+                span,
+            } => {
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_closest_peers(peer_id);
+                span.in_scope(|| info!(peer = %peer_id, ?query_id, "Looking up closest peers"));
+                self.pending_get_closest_peers.insert(query_id, (sender, span));
+            }
             // 节点提供共享文件，插入缓存
+            // 本地存储已满等原因无法保存提供者记录时返回错误
             Command::StartProviding { file_name, sender } => {
-                let query_id = self
+                let key = Key::new(&file_name);
+                match self
                     .swarm
                     .behaviour_mut()
                     .kademlia
-                    .start_providing(file_name.into_bytes().into())
-                    .expect("No store error.");
-                self.pending_start_providing.insert(query_id, sender);
+                    .start_providing(key.clone())
+                {
+                    Ok(query_id) => {
+                        self.providing.insert(key);
+                        self.pending_start_providing.insert(query_id, sender);
+                    }
+                    Err(e) => {
+                        warn!(file = %file_name, error = ?e, "Failed to store provider record");
+                        let _ = sender.send(Err(Box::new(e)));
+                    }
+                }
+            }
+            // 节点停止提供共享文件，删除本地提供者记录和访问策略
+            Command::StopProviding { file_name, sender } => {
+                let key = Key::new(&file_name);
+                self.swarm.behaviour_mut().kademlia.stop_providing(&key);
+                self.providing.remove(&key);
+                self.access_policies.remove(&file_name);
+                let _ = sender.send(());
+            }
+            // 设置共享文件访问策略
+            Command::SetAccessPolicy { file_name, policy } => {
+                self.access_policies.insert(file_name, policy);
             }
             // 获取提供共享文件的节点，插入缓存
-            Command::GetProviders { file_name, sender } => {
+            Command::GetProviders {
+                file_name,
+                sender,
+                span,
+            } => {
                 let query_id = self
                     .swarm
                     .behaviour_mut()
                     .kademlia
                     .get_providers(file_name.into_bytes().into());
-                self.pending_get_providers.insert(query_id, sender);
+                span.in_scope(|| info!(?query_id, "Looking up providers"));
+                self.pending_get_providers.insert(query_id, (sender, span));
             }
-            // 请求共享文件，插入缓存
-            Command::RequestFile {
+            // 从提供者下载共享文件，插入缓存
+            Command::DownloadFile {
                 file_name,
-                peer,
+                token,
+                providers,
+                manifest,
+                chunks,
                 sender,
+                span,
             } => {
-                let request_id = self
+                let transfer = self.next_transfer_id;
+                self.next_transfer_id += 1;
+                // 排除被封禁的提供者，评分高的优先使用
+                let providers = self.reputation.rank(providers);
+                for peer in &providers {
+                    self.add_relayed_addresses(peer);
+                }
+                // 下载任务的请求、响应和结果记录在该span下
+                let span = info_span!(parent: &span, "download", transfer, file = %file_name);
+                span.in_scope(|| info!(providers = providers.len(), "Download started"));
+                let download = match chunks {
+                    Some(chunks) => {
+                        // 已知清单时直接开始下载分块
+                        if let Some(manifest) = &manifest {
+                            self.emit(Event::TransferStarted {
+                                transfer,
+                                file_name: file_name.clone(),
+                                size: manifest.size,
+                                chunks: manifest.chunk_digests.len(),
+                            });
+                        }
+                        Download::partial(file_name, token, providers, manifest, chunks)
+                    }
+                    None => Download::new(file_name, token, providers),
+                };
+                self.downloads.insert(transfer, (download, sender, span));
+                self.advance_download(transfer);
+            }
+            // 发布名称记录，序列号不小于当前时间戳，保证重启后仍然递增
+            Command::PublishName { target, sender } => {
+                let key = NameRecord::key(self.swarm.local_peer_id());
+                let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+                let previous = kademlia
+                    .store_mut()
+                    .get(&key)
+                    .and_then(|r| NameRecord::decode(&r.value))
+                    .map_or(0, |r| r.sequence);
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("System time to be after the epoch.")
+                    .as_millis() as u64;
+                let sequence = (previous + 1).max(now);
+
+                let record = match NameRecord::new(&self.keypair, target, sequence) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        let _ = sender.send(Err(Box::new(e)));
+                        return;
+                    }
+                };
+                match kademlia.put_record(Record::new(key, record.encode()), Quorum::One) {
+                    Ok(query_id) => {
+                        self.pending_publish_name.insert(query_id, (sequence, sender));
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(Box::new(e)));
+                    }
+                }
+            }
+            // 解析名称记录，插入缓存
+            Command::ResolveName { publisher, sender } => {
+                let quorum = NonZeroUsize::new(NAME_RESOLVE_QUORUM).expect("Quorum to be non-zero.");
+                let query_id = self
                     .swarm
                     .behaviour_mut()
-                    .request_response
-                    .send_request(&peer, FileRequest(file_name));
-                self.pending_request_file.insert(request_id, sender);
+                    .kademlia
+                    .get_record(NameRecord::key(&publisher), Quorum::N(quorum));
+                self.pending_resolve_name.insert(query_id, (publisher, sender));
             }
-            // 返回共享文件内容
-            Command::RespondFile { file, channel } => {
-                self.swarm
-                    .behaviour_mut()
-                    .request_response
-                    .send_response(channel, FileResponse(file))
-                    .expect("Connection to peer to be still open.");
+            Command::Reachability { sender } => {
+                let _ = sender.send(self.swarm.behaviour().autonat.nat_status());
+            }
+            // 返回共享文件内容，加入上传队列按限制发送
+            Command::RespondFile {
+                request_id,
+                response,
+                channel,
+            } => {
+                let peer = match self.inbound_requests.get(&request_id) {
+                    Some(peer) => *peer,
+                    None => {
+                        warn!(?request_id, "Request is no longer pending");
+                        return;
+                    }
+                };
+                self.uploads
+                    .push(peer, response.payload_len(), (request_id, response, channel));
+                self.send_ready_uploads();
+                let waiting = self.uploads.waiting();
+                if waiting > 0 {
+                    debug!(waiting, "Uploads waiting for bandwidth");
+                }
             }
         }
     }
 }
+
+// 经由中继的地址
+fn is_circuit(address: &Multiaddr) -> bool {
+    address.iter().any(|p| p == Protocol::P2pCircuit)
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use libp2p::swarm;
+
+// 连接限制配置，None表示不限制
+#[derive(Debug, Clone)]
+pub struct ConnectionLimits {
+    // 已建立的连接总数上限
+    pub max_established: Option<u32>,
+    // 与单个节点的连接数上限，直接连接、中继连接和打洞连接可能同时存在
+    pub max_established_per_peer: Option<u32>,
+    // 正在建立的入站和出站连接数上限
+    pub max_pending_incoming: Option<u32>,
+    pub max_pending_outgoing: Option<u32>,
+    // 每个连接上同时协商中的入站流数量上限
+    pub max_negotiating_inbound_streams: usize,
+    // 连接空闲多久后关闭
+    pub idle_timeout: Duration,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits {
+            max_established: Some(256),
+            max_established_per_peer: Some(4),
+            max_pending_incoming: Some(64),
+            max_pending_outgoing: Some(64),
+            max_negotiating_inbound_streams: 128,
+            idle_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ConnectionLimits {
+    // 转换为Swarm使用的连接限制
+    pub(crate) fn to_swarm(&self) -> swarm::ConnectionLimits {
+        swarm::ConnectionLimits::default()
+            .with_max_established(self.max_established)
+            .with_max_established_per_peer(self.max_established_per_peer)
+            .with_max_pending_incoming(self.max_pending_incoming)
+            .with_max_pending_outgoing(self.max_pending_outgoing)
+    }
+}
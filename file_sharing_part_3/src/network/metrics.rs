@@ -0,0 +1,292 @@
+use std::fmt::Debug;
+
+use libp2p::{
+    kad::{KademliaEvent, QueryResult},
+    request_response::{
+        InboundFailure, OutboundFailure, RequestResponseEvent, RequestResponseMessage,
+    },
+    swarm::{ConnectionError, SwarmEvent},
+};
+use prometheus_client::{
+    encoding::text::Encode,
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+
+use super::behaviour::ComposedEvent;
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Encode)]
+struct DirectionLabels {
+    // dialer或listener
+    direction: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Encode)]
+struct ReasonLabels {
+    reason: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Encode)]
+struct QueryLabels {
+    kind: &'static str,
+    // ok或error
+    outcome: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Encode)]
+struct QueryKindLabels {
+    kind: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Encode)]
+struct OutcomeLabels {
+    outcome: &'static str,
+}
+
+// 节点运行指标，克隆后共享同一组指标；未注册到Registry时只记录不导出
+#[derive(Clone)]
+pub struct Metrics {
+    connections_established: Family<DirectionLabels, Counter>,
+    connections_closed: Family<ReasonLabels, Counter>,
+    connection_errors: Family<DirectionLabels, Counter>,
+    connected_peers: Gauge,
+    kademlia_queries: Family<QueryLabels, Counter>,
+    kademlia_query_duration: Family<QueryKindLabels, Histogram>,
+    routing_table_size: Gauge,
+    outbound_requests: Family<OutcomeLabels, Counter>,
+    inbound_requests: Family<OutcomeLabels, Counter>,
+    bytes_uploaded: Counter,
+    bytes_downloaded: Counter,
+    active_downloads: Gauge,
+    active_uploads: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new(&mut Registry::default())
+    }
+}
+
+impl Metrics {
+    // 创建指标并注册到registry，指标名称带有file_sharing前缀
+    pub fn new(registry: &mut Registry) -> Self {
+        let registry = registry.sub_registry_with_prefix("file_sharing");
+        let metrics = Metrics {
+            connections_established: Family::default(),
+            connections_closed: Family::default(),
+            connection_errors: Family::default(),
+            connected_peers: Gauge::default(),
+            kademlia_queries: Family::default(),
+            // 10毫秒到约40秒
+            kademlia_query_duration: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.01, 2.0, 13))
+            }),
+            routing_table_size: Gauge::default(),
+            outbound_requests: Family::default(),
+            inbound_requests: Family::default(),
+            bytes_uploaded: Counter::default(),
+            bytes_downloaded: Counter::default(),
+            active_downloads: Gauge::default(),
+            active_uploads: Gauge::default(),
+        };
+        registry.register(
+            "connections_established",
+            "Connections established, by direction",
+            Box::new(metrics.connections_established.clone()),
+        );
+        registry.register(
+            "connections_closed",
+            "Connections closed, by reason",
+            Box::new(metrics.connections_closed.clone()),
+        );
+        registry.register(
+            "connection_errors",
+            "Connections that failed before being established, by direction",
+            Box::new(metrics.connection_errors.clone()),
+        );
+        registry.register(
+            "connected_peers",
+            "Peers with at least one connection",
+            Box::new(metrics.connected_peers.clone()),
+        );
+        registry.register(
+            "kademlia_queries",
+            "Completed Kademlia queries, by kind and outcome",
+            Box::new(metrics.kademlia_queries.clone()),
+        );
+        registry.register(
+            "kademlia_query_duration_seconds",
+            "Duration of completed Kademlia queries, by kind",
+            Box::new(metrics.kademlia_query_duration.clone()),
+        );
+        registry.register(
+            "kademlia_routing_table_size",
+            "Peers in the Kademlia routing table",
+            Box::new(metrics.routing_table_size.clone()),
+        );
+        registry.register(
+            "outbound_requests",
+            "File requests sent to other peers, by outcome",
+            Box::new(metrics.outbound_requests.clone()),
+        );
+        registry.register(
+            "inbound_requests",
+            "File requests received from other peers, by outcome",
+            Box::new(metrics.inbound_requests.clone()),
+        );
+        registry.register(
+            "uploaded_bytes",
+            "File bytes sent in responses",
+            Box::new(metrics.bytes_uploaded.clone()),
+        );
+        registry.register(
+            "downloaded_bytes",
+            "File chunk bytes received",
+            Box::new(metrics.bytes_downloaded.clone()),
+        );
+        registry.register(
+            "active_downloads",
+            "Downloads in progress",
+            Box::new(metrics.active_downloads.clone()),
+        );
+        registry.register(
+            "active_uploads",
+            "Responses being sent",
+            Box::new(metrics.active_uploads.clone()),
+        );
+        metrics
+    }
+
+    // 记录EventLoop收到的连接、Kademlia和请求-响应事件
+    pub(crate) fn record<E: Debug>(&self, event: &SwarmEvent<ComposedEvent, E>) {
+        match event {
+            SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                self.connections_established
+                    .get_or_create(&DirectionLabels {
+                        direction: direction(endpoint.is_dialer()),
+                    })
+                    .inc();
+            }
+            SwarmEvent::ConnectionClosed { cause, .. } => {
+                let reason = match cause {
+                    None => "closed",
+                    Some(ConnectionError::IO(_)) => "io",
+                    Some(ConnectionError::KeepAliveTimeout) => "idle_timeout",
+                    Some(ConnectionError::Handler(_)) => "protocol",
+                };
+                self.connections_closed
+                    .get_or_create(&ReasonLabels { reason })
+                    .inc();
+            }
+            SwarmEvent::OutgoingConnectionError { .. } => {
+                self.connection_errors
+                    .get_or_create(&DirectionLabels {
+                        direction: direction(true),
+                    })
+                    .inc();
+            }
+            SwarmEvent::IncomingConnectionError { .. } => {
+                self.connection_errors
+                    .get_or_create(&DirectionLabels {
+                        direction: direction(false),
+                    })
+                    .inc();
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(
+                KademliaEvent::OutboundQueryCompleted { result, stats, .. },
+            )) => {
+                let (kind, ok) = query_kind(result);
+                self.kademlia_queries
+                    .get_or_create(&QueryLabels {
+                        kind,
+                        outcome: if ok { "ok" } else { "error" },
+                    })
+                    .inc();
+                if let Some(duration) = stats.duration() {
+                    self.kademlia_query_duration
+                        .get_or_create(&QueryKindLabels { kind })
+                        .observe(duration.as_secs_f64());
+                }
+            }
+            SwarmEvent::Behaviour(ComposedEvent::RequestResponse(event)) => {
+                let (family, outcome) = match event {
+                    RequestResponseEvent::Message {
+                        message: RequestResponseMessage::Response { .. },
+                        ..
+                    } => (&self.outbound_requests, "success"),
+                    RequestResponseEvent::OutboundFailure { error, .. } => (
+                        &self.outbound_requests,
+                        match error {
+                            OutboundFailure::DialFailure => "dial_failure",
+                            OutboundFailure::Timeout => "timeout",
+                            OutboundFailure::ConnectionClosed => "connection_closed",
+                            OutboundFailure::UnsupportedProtocols => "unsupported_protocols",
+                        },
+                    ),
+                    RequestResponseEvent::ResponseSent { .. } => (&self.inbound_requests, "success"),
+                    RequestResponseEvent::InboundFailure { error, .. } => (
+                        &self.inbound_requests,
+                        match error {
+                            InboundFailure::Timeout => "timeout",
+                            InboundFailure::ConnectionClosed => "connection_closed",
+                            InboundFailure::UnsupportedProtocols => "unsupported_protocols",
+                            InboundFailure::ResponseOmission => "response_omission",
+                        },
+                    ),
+                    RequestResponseEvent::Message { .. } => return,
+                };
+                family.get_or_create(&OutcomeLabels { outcome }).inc();
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn record_upload(&self, bytes: usize) {
+        self.bytes_uploaded.inc_by(bytes as u64);
+    }
+
+    pub(crate) fn record_download(&self, bytes: usize) {
+        self.bytes_downloaded.inc_by(bytes as u64);
+    }
+
+    // 更新当前状态的指标
+    pub(crate) fn set_state(
+        &self,
+        connected_peers: usize,
+        routing_table_size: usize,
+        active_downloads: usize,
+        active_uploads: usize,
+    ) {
+        self.connected_peers.set(connected_peers as u64);
+        self.routing_table_size.set(routing_table_size as u64);
+        self.active_downloads.set(active_downloads as u64);
+        self.active_uploads.set(active_uploads as u64);
+    }
+}
+
+fn direction(dialer: bool) -> &'static str {
+    if dialer {
+        "dialer"
+    } else {
+        "listener"
+    }
+}
+
+// 查询类型及是否成功
+fn query_kind(result: &QueryResult) -> (&'static str, bool) {
+    match result {
+        QueryResult::Bootstrap(r) => ("bootstrap", r.is_ok()),
+        QueryResult::GetClosestPeers(r) => ("get_closest_peers", r.is_ok()),
+        QueryResult::GetProviders(r) => ("get_providers", r.is_ok()),
+        QueryResult::StartProviding(r) => ("start_providing", r.is_ok()),
+        QueryResult::RepublishProvider(r) => ("republish_provider", r.is_ok()),
+        QueryResult::GetRecord(r) => ("get_record", r.is_ok()),
+        QueryResult::PutRecord(r) => ("put_record", r.is_ok()),
+        QueryResult::RepublishRecord(r) => ("republish_record", r.is_ok()),
+    }
+}
@@ -1,22 +1,63 @@
+pub mod access;
+pub mod address_book;
 pub mod behaviour;
+pub mod builder;
+pub mod connection;
+pub mod dht;
+mod encoding;
 pub mod event;
+pub mod limits;
+pub mod metrics;
+mod persist;
 pub mod protocol;
+pub mod record;
+pub mod reputation;
+pub mod store;
+pub mod subscription;
+pub mod throttle;
+pub mod transfer;
 
-use std::{error::Error, iter};
+use std::time::Duration;
 
-use libp2p::{identity::{ed25519, self}, swarm::SwarmBuilder, kad::{Kademlia, store::MemoryStore}, request_response::{RequestResponse, ProtocolSupport}};
+use libp2p::identity::{ed25519, self};
 pub use protocol::*;
-use tokio::sync::mpsc::{Receiver, self};
 
-use crate::client::Client;
+// 提供者记录的有效期，节点离开后其他节点上的记录在此时间后过期
+pub const PROVIDER_RECORD_TTL: Duration = Duration::from_secs(30 * 60);
+// 重新发布提供者记录的间隔，需小于记录有效期
+pub const PROVIDER_REPUBLISH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// 解析名称记录时需要获取的记录数量，从中选择序列号最大的记录
+pub const NAME_RESOLVE_QUORUM: usize = 3;
+// 检查等待中的上传是否可以发送的间隔
+pub const UPLOAD_SCHEDULE_INTERVAL: Duration = Duration::from_millis(50);
+// Identify协议中声明的协议版本
+pub const IDENTIFY_PROTOCOL_VERSION: &str = "/file-exchange/1.0.0";
+// 中继服务器支持的协议，通过Identify识别中继
+pub const RELAY_HOP_PROTOCOL: &str = "/libp2p/circuit/relay/0.2.0/hop";
+// 作为中继服务器时，每条中继连接的时长和流量上限
+pub const RELAY_MAX_CIRCUIT_DURATION: Duration = Duration::from_secs(30 * 60);
+pub const RELAY_MAX_CIRCUIT_BYTES: u64 = 1 << 30;
+// 按节点ID连接时的尝试次数和首次重试前的等待时间，之后每次重试等待时间加倍
+pub const CONNECT_ATTEMPTS: u32 = 3;
+pub const CONNECT_BACKOFF: Duration = Duration::from_secs(1);
+// 保持连接的节点断开后重连的初始等待时间和等待时间上限，以及检查重连的间隔
+pub const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+pub const RECONNECT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// 启动时从地址簿中选择连接的节点数量
+pub const ADDRESS_BOOK_DIAL_SAMPLE: usize = 8;
+// 保存节点评分和地址簿、检查封禁到期的间隔
+pub const REPUTATION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+// 更新连接数、路由表大小等状态指标的间隔
+pub const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+// 每个事件订阅者的缓冲区容量，超过后订阅者会丢失最早的事件
+pub const EVENT_BUFFER_SIZE: usize = 256;
+// 等待处理的文件请求数量上限，超过后丢弃新的请求
+pub const REQUEST_BUFFER_SIZE: usize = 64;
 
-use self::{event::{EventLoop, Event}, behaviour::ComposedBehaviour};
-
-pub async fn new(
-    secret_key_seed: Option<u8>,
-) -> Result<(Client, Receiver<Event>, EventLoop), Box<dyn Error>> {
-    // 创建密钥对
-    let id_keys = match secret_key_seed {
+// 创建密钥对，给定种子时生成固定的密钥对
+pub fn keypair(secret_key_seed: Option<u8>) -> identity::Keypair {
+    match secret_key_seed {
         Some(seed) => {
             let mut bytes = [0u8; 32];
             bytes[0] = seed;
@@ -26,31 +67,5 @@ pub async fn new(
             identity::Keypair::Ed25519(secret_key.into())
         }
         None => identity::Keypair::generate_ed25519(),
-    };
-    // 根据公钥生成节点ID
-    let peer_id = id_keys.public().to_peer_id();
-
-    // 构建网络层管理组件Swarm
-    let swarm = SwarmBuilder::new(
-        libp2p::development_transport(id_keys).await?,
-        ComposedBehaviour {
-            kademlia: Kademlia::new(peer_id, MemoryStore::new(peer_id)),
-            request_response: RequestResponse::new(
-                FileExchangeCodec(),
-                iter::once((FileExchangeProtocol(), ProtocolSupport::Full)),
-                Default::default(),
-            ),
-        },
-        peer_id,
-    )
-    .build();
-
-    let (command_sender, command_receiver) = mpsc::channel(1);
-    let (event_sender, event_receiver) = mpsc::channel(1);
-
-    Ok((
-        Client::new(command_sender),
-        event_receiver,
-        EventLoop::new(swarm, command_receiver, event_sender),
-    ))
-}
\ No newline at end of file
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+// 读取以节点ID为键的JSON文件，文件不存在时返回空表，忽略无法解析的节点ID
+pub fn load<T: DeserializeOwned>(path: &Path) -> io::Result<HashMap<PeerId, T>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let stored: HashMap<String, T> =
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(stored
+        .into_iter()
+        .filter_map(|(peer, value)| Some((peer.parse().ok()?, value)))
+        .collect())
+}
+
+// 写入临时文件并同步到磁盘后重命名，避免中断或断电时损坏已有文件
+pub fn save<T: Serialize>(path: &Path, peers: &HashMap<PeerId, T>) -> io::Result<()> {
+    let stored: HashMap<String, &T> = peers
+        .iter()
+        .map(|(peer, value)| (peer.to_base58(), value))
+        .collect();
+    let json = serde_json::to_vec_pretty(&stored).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(&json)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+    // 同步目录，确保重命名已写入磁盘
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// 当前Unix时间戳(秒)
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time to be after the epoch.")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn round_trip_skips_invalid_peer_ids() {
+        let path = env::temp_dir().join(format!("persist-test-{}.json", std::process::id()));
+        let peer = PeerId::random();
+        save(&path, &HashMap::from([(peer, 7u64)])).unwrap();
+        assert!(!Path::new(&format!("{}.tmp", path.display())).exists());
+        assert_eq!(load::<u64>(&path).unwrap(), HashMap::from([(peer, 7)]));
+
+        fs::write(&path, format!(r#"{{"{}": 1, "not a peer": 2}}"#, peer)).unwrap();
+        assert_eq!(load::<u64>(&path).unwrap(), HashMap::from([(peer, 1)]));
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(load::<u64>(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = env::temp_dir().join("persist-test-missing.json");
+        assert!(load::<u64>(&path).unwrap().is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use futures::{io, AsyncRead, AsyncWrite, AsyncWriteExt};
 use libp2p::{
@@ -8,14 +10,88 @@ use libp2p::{
     request_response::RequestResponseCodec,
 };
 
+use super::transfer::Manifest;
+
+// 单个消息的默认最大长度
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1_000_000;
+// 请求的默认超时时间
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// 响应状态：返回文件分块
+const RESPONSE_CHUNK: u8 = 0;
+// 响应状态：无权获取文件
+const RESPONSE_FORBIDDEN: u8 = 1;
+// 响应状态：返回文件清单
+const RESPONSE_MANIFEST: u8 = 2;
+// 响应状态：没有该文件或分块
+const RESPONSE_NOT_FOUND: u8 = 3;
+
+// 文件交换协议的限制
+#[derive(Debug, Clone)]
+pub struct ProtocolLimits {
+    // 单个消息的最大长度，需能容纳一个文件分块
+    pub max_message_size: usize,
+    // 请求的超时时间
+    pub request_timeout: Duration,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileExchangeProtocol();
 #[derive(Clone)]
-pub struct FileExchangeCodec();
+pub struct FileExchangeCodec {
+    max_message_size: usize,
+}
+
+impl FileExchangeCodec {
+    pub fn new(max_message_size: usize) -> Self {
+        FileExchangeCodec { max_message_size }
+    }
+}
+
+impl Default for FileExchangeCodec {
+    fn default() -> Self {
+        FileExchangeCodec::new(DEFAULT_MAX_MESSAGE_SIZE)
+    }
+}
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FileRequest(pub String);
+pub struct FileRequest {
+    // 文件名称
+    pub file_name: String,
+    // 访问令牌
+    pub token: Option<Vec<u8>>,
+    // 请求的分块序号，为空时请求文件清单
+    pub chunk: Option<u64>,
+}
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FileResponse(pub String);
+pub enum FileResponse {
+    // 文件清单
+    Manifest(Manifest),
+    // 文件分块内容
+    Chunk(Vec<u8>),
+    // 请求节点无权获取文件
+    Forbidden,
+    // 没有该文件或分块
+    NotFound,
+}
+
+impl FileResponse {
+    // 响应携带的数据长度，用于上传限速
+    pub fn payload_len(&self) -> usize {
+        match self {
+            FileResponse::Manifest(manifest) => manifest.encoded_len(),
+            FileResponse::Chunk(data) => data.len(),
+            FileResponse::Forbidden | FileResponse::NotFound => 0,
+        }
+    }
+}
 
 // 定义协议名称
 impl ProtocolName for FileExchangeProtocol {
@@ -31,7 +107,7 @@ impl RequestResponseCodec for FileExchangeCodec {
     type Request = FileRequest;
     type Response = FileResponse;
 
-    // 读请求
+    // 读请求：文件名称，访问令牌(为空表示没有令牌)，分块序号(为空表示请求文件清单)
     async fn read_request<T>(
         &mut self,
         _: &FileExchangeProtocol,
@@ -41,16 +117,30 @@ impl RequestResponseCodec for FileExchangeCodec {
         T: AsyncRead + Unpin + Send,
     {
         // 读取固定长度的字节
-        let vec = read_length_prefixed(io, 1_000_000).await?;
+        let vec = read_length_prefixed(io, self.max_message_size).await?;
 
         if vec.is_empty() {
             return Err(io::ErrorKind::UnexpectedEof.into());
         }
 
-        Ok(FileRequest(String::from_utf8(vec).unwrap()))
+        let file_name = String::from_utf8(vec)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let token = read_length_prefixed(io, self.max_message_size).await?;
+        let chunk = match read_length_prefixed(io, 8).await?.as_slice() {
+            [] => None,
+            bytes => Some(u64::from_be_bytes(
+                bytes.try_into().map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?,
+            )),
+        };
+
+        Ok(FileRequest {
+            file_name,
+            token: Some(token).filter(|t| !t.is_empty()),
+            chunk,
+        })
     }
 
-    // 读取响应
+    // 读取响应：状态字节，响应内容
     async fn read_response<T>(
         &mut self,
         _: &FileExchangeProtocol,
@@ -60,13 +150,18 @@ impl RequestResponseCodec for FileExchangeCodec {
         T: AsyncRead + Unpin + Send,
     {
         // 读取固定长度的字节
-        let vec = read_length_prefixed(io, 1_000_000).await?;
+        let vec = read_length_prefixed(io, self.max_message_size).await?;
 
-        if vec.is_empty() {
-            return Err(io::ErrorKind::UnexpectedEof.into());
+        match vec.split_first() {
+            Some((&RESPONSE_CHUNK, data)) => Ok(FileResponse::Chunk(data.to_vec())),
+            Some((&RESPONSE_MANIFEST, data)) => Manifest::decode(data)
+                .map(FileResponse::Manifest)
+                .ok_or_else(|| io::ErrorKind::InvalidData.into()),
+            Some((&RESPONSE_FORBIDDEN, _)) => Ok(FileResponse::Forbidden),
+            Some((&RESPONSE_NOT_FOUND, _)) => Ok(FileResponse::NotFound),
+            Some(_) => Err(io::ErrorKind::InvalidData.into()),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
         }
-
-        Ok(FileResponse(String::from_utf8(vec).unwrap()))
     }
 
     // 写请求
@@ -74,12 +169,18 @@ impl RequestResponseCodec for FileExchangeCodec {
         &mut self,
         _: &FileExchangeProtocol,
         io: &mut T,
-        FileRequest(data): FileRequest,
+        FileRequest {
+            file_name,
+            token,
+            chunk,
+        }: FileRequest,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        write_length_prefixed(io, data).await?;
+        write_length_prefixed(io, file_name).await?;
+        write_length_prefixed(io, token.unwrap_or_default()).await?;
+        write_length_prefixed(io, chunk.map(|c| c.to_be_bytes().to_vec()).unwrap_or_default()).await?;
         io.close().await?;
 
         Ok(())
@@ -89,14 +190,111 @@ impl RequestResponseCodec for FileExchangeCodec {
         &mut self,
         _: &FileExchangeProtocol,
         io: &mut T,
-        FileResponse(data): FileResponse,
+        response: FileResponse,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
+        let data = match response {
+            FileResponse::Manifest(manifest) => [&[RESPONSE_MANIFEST], manifest.encode().as_slice()].concat(),
+            FileResponse::Chunk(data) => [&[RESPONSE_CHUNK], data.as_slice()].concat(),
+            FileResponse::Forbidden => vec![RESPONSE_FORBIDDEN],
+            FileResponse::NotFound => vec![RESPONSE_NOT_FOUND],
+        };
         write_length_prefixed(io, data).await?;
         io.close().await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+
+    use super::*;
+
+    async fn request_round_trip(request: FileRequest) -> FileRequest {
+        let mut codec = FileExchangeCodec::default();
+        let mut io = Cursor::new(Vec::new());
+        codec.write_request(&FileExchangeProtocol(), &mut io, request).await.unwrap();
+        io.set_position(0);
+        codec.read_request(&FileExchangeProtocol(), &mut io).await.unwrap()
+    }
+
+    async fn write_response(codec: &mut FileExchangeCodec, response: FileResponse) -> Cursor<Vec<u8>> {
+        let mut io = Cursor::new(Vec::new());
+        codec.write_response(&FileExchangeProtocol(), &mut io, response).await.unwrap();
+        io.set_position(0);
+        io
+    }
+
+    #[tokio::test]
+    async fn requests_round_trip() {
+        let requests = [
+            FileRequest {
+                file_name: "file".to_string(),
+                token: None,
+                chunk: None,
+            },
+            FileRequest {
+                file_name: "dir/file".to_string(),
+                token: Some(vec![1, 2, 3]),
+                chunk: Some(7),
+            },
+        ];
+        for request in requests {
+            assert_eq!(request_round_trip(request.clone()).await, request);
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_round_trip() {
+        let mut codec = FileExchangeCodec::default();
+        let responses = [
+            FileResponse::Manifest(Manifest::new(b"content", true)),
+            FileResponse::Chunk(b"chunk".to_vec()),
+            FileResponse::Chunk(Vec::new()),
+            FileResponse::Forbidden,
+            FileResponse::NotFound,
+        ];
+        for response in responses {
+            let mut io = write_response(&mut codec, response.clone()).await;
+            assert_eq!(codec.read_response(&FileExchangeProtocol(), &mut io).await.unwrap(), response);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_responses_are_rejected() {
+        let mut codec = FileExchangeCodec::new(16);
+        // 超过最大长度的消息
+        let mut io = write_response(&mut codec, FileResponse::Chunk(vec![0; 32])).await;
+        assert!(codec.read_response(&FileExchangeProtocol(), &mut io).await.is_err());
+
+        // 未知的响应状态
+        let mut io = Cursor::new(Vec::new());
+        write_length_prefixed(&mut io, [9u8]).await.unwrap();
+        io.set_position(0);
+        let error = codec.read_response(&FileExchangeProtocol(), &mut io).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // 无法解析的清单
+        let mut io = Cursor::new(Vec::new());
+        write_length_prefixed(&mut io, [RESPONSE_MANIFEST, 0]).await.unwrap();
+        io.set_position(0);
+        let error = codec.read_response(&FileExchangeProtocol(), &mut io).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn invalid_chunk_index_is_rejected() {
+        let mut codec = FileExchangeCodec::default();
+        let mut io = Cursor::new(Vec::new());
+        write_length_prefixed(&mut io, "file").await.unwrap();
+        write_length_prefixed(&mut io, []).await.unwrap();
+        write_length_prefixed(&mut io, [0u8; 4]).await.unwrap();
+        io.set_position(0);
+        let error = codec.read_request(&FileExchangeProtocol(), &mut io).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,167 @@
+use libp2p::{
+    identity::{error::SigningError, Keypair, PublicKey},
+    kad::record::Key,
+    PeerId,
+};
+
+use super::encoding::{put_prefixed, split, split_prefixed};
+
+// 签名内容的前缀，避免签名被用于其他用途
+const SIGNING_DOMAIN: &[u8] = b"file-sharing-name:";
+
+// 可变名称记录，由发布者签名，指向共享文件的键，序列号越大越新
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameRecord {
+    // 指向的共享文件键
+    pub target: String,
+    // 序列号
+    pub sequence: u64,
+    // 发布者公钥
+    pub public_key: PublicKey,
+    // 发布者对序列号和目标的签名
+    pub signature: Vec<u8>,
+}
+
+impl NameRecord {
+    // 使用发布者密钥对签名生成名称记录
+    pub fn new(keypair: &Keypair, target: String, sequence: u64) -> Result<Self, SigningError> {
+        let signature = keypair.sign(&signing_bytes(&target, sequence))?;
+        Ok(NameRecord {
+            target,
+            sequence,
+            public_key: keypair.public(),
+            signature,
+        })
+    }
+
+    // 名称记录在DHT中的键，由发布者的PeerId生成
+    pub fn key(publisher: &PeerId) -> Key {
+        let mut key = b"/name/".to_vec();
+        key.extend_from_slice(&publisher.to_bytes());
+        Key::new(&key)
+    }
+
+    // 检查记录由给定节点发布且签名有效
+    pub fn verify(&self, publisher: &PeerId) -> bool {
+        self.public_key.to_peer_id() == *publisher
+            && self
+                .public_key
+                .verify(&signing_bytes(&self.target, self.sequence), &self.signature)
+    }
+
+    // 只接受签名有效的记录，选择序列号最大的记录
+    pub fn latest<'a>(publisher: &PeerId, records: impl IntoIterator<Item = &'a [u8]>) -> Option<Self> {
+        records
+            .into_iter()
+            .filter_map(NameRecord::decode)
+            .filter(|r| r.verify(publisher))
+            .max_by_key(|r| r.sequence)
+    }
+
+    // 编码格式：序列号(8) | 公钥长度(2) | 公钥 | 签名长度(2) | 签名 | 目标
+    pub fn encode(&self) -> Vec<u8> {
+        let public_key = self.public_key.to_protobuf_encoding();
+        let mut bytes = Vec::with_capacity(
+            12 + public_key.len() + self.signature.len() + self.target.len(),
+        );
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        put_prefixed(&mut bytes, &public_key);
+        put_prefixed(&mut bytes, &self.signature);
+        bytes.extend_from_slice(self.target.as_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (sequence, rest) = split(bytes, 8)?;
+        let sequence = u64::from_be_bytes(sequence.try_into().ok()?);
+        let (public_key, rest) = split_prefixed(rest)?;
+        let public_key = PublicKey::from_protobuf_encoding(public_key).ok()?;
+        let (signature, rest) = split_prefixed(rest)?;
+        let target = String::from_utf8(rest.to_vec()).ok()?;
+
+        Some(NameRecord {
+            target,
+            sequence,
+            public_key,
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+fn signing_bytes(target: &str, sequence: u64) -> Vec<u8> {
+    let mut bytes = SIGNING_DOMAIN.to_vec();
+    bytes.extend_from_slice(&sequence.to_be_bytes());
+    bytes.extend_from_slice(target.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::ed25519;
+
+    use super::*;
+
+    fn keypair(seed: u8) -> Keypair {
+        let mut bytes = [seed; 32];
+        Keypair::Ed25519(ed25519::SecretKey::from_bytes(&mut bytes).unwrap().into())
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let publisher = keypair(1);
+        let record = NameRecord::new(&publisher, "file-v1".to_string(), 3).unwrap();
+        let decoded = NameRecord::decode(&record.encode()).unwrap();
+        assert_eq!(decoded, record);
+        assert!(decoded.verify(&publisher.public().to_peer_id()));
+
+        let bytes = record.encode();
+        assert_eq!(NameRecord::decode(&bytes[..7]), None);
+        assert_eq!(NameRecord::decode(&bytes[..12]), None);
+    }
+
+    #[test]
+    fn tampered_records_fail_verification() {
+        let publisher = keypair(1);
+        let peer_id = publisher.public().to_peer_id();
+        let record = NameRecord::new(&publisher, "file-v1".to_string(), 3).unwrap();
+
+        let mut tampered = record.clone();
+        tampered.target = "file-v2".to_string();
+        assert!(!tampered.verify(&peer_id));
+        let mut tampered = record.clone();
+        tampered.sequence += 1;
+        assert!(!tampered.verify(&peer_id));
+        let mut tampered = record;
+        tampered.signature[0] ^= 1;
+        assert!(!tampered.verify(&peer_id));
+    }
+
+    #[test]
+    fn records_from_other_keys_are_rejected() {
+        let publisher = keypair(1).public().to_peer_id();
+        let other = keypair(2);
+        // 签名有效，但公钥与发布者不一致
+        let record = NameRecord::new(&other, "file".to_string(), 1).unwrap();
+        assert!(record.verify(&other.public().to_peer_id()));
+        assert!(!record.verify(&publisher));
+        assert_eq!(NameRecord::latest(&publisher, [record.encode().as_slice()]), None);
+    }
+
+    #[test]
+    fn highest_valid_sequence_wins() {
+        let publisher = keypair(1);
+        let peer_id = publisher.public().to_peer_id();
+        let old = NameRecord::new(&publisher, "v1".to_string(), 1).unwrap().encode();
+        let new = NameRecord::new(&publisher, "v2".to_string(), 2).unwrap().encode();
+        let forged = NameRecord::new(&keypair(2), "v9".to_string(), 9).unwrap().encode();
+        let mut invalid = NameRecord::new(&publisher, "v8".to_string(), 8).unwrap();
+        invalid.target = "v3".to_string();
+        let invalid = invalid.encode();
+
+        let records = [old.as_slice(), &forged, &new, &invalid, b"garbage"];
+        let latest = NameRecord::latest(&peer_id, records).unwrap();
+        assert_eq!((latest.target.as_str(), latest.sequence), ("v2", 2));
+        assert_eq!(NameRecord::latest(&peer_id, [old.as_slice()]).unwrap().target, "v1");
+        assert_eq!(NameRecord::latest(&peer_id, []), None);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::PathBuf,
+    time::Duration,
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::persist::{self, unix_time};
+
+// 默认的封禁阈值，评分低于该值的节点被封禁
+pub const DEFAULT_BAN_THRESHOLD: f64 = -50.0;
+// 默认的封禁时长
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+// 各种结果对评分的影响，评分上限避免长期表现良好的节点作恶后难以被封禁
+const SUCCESS_REWARD: f64 = 1.0;
+const MAX_SCORE: f64 = 100.0;
+const VERIFICATION_PENALTY: f64 = 25.0;
+const TIMEOUT_PENALTY: f64 = 5.0;
+const FAILURE_PENALTY: f64 = 2.0;
+// 吞吐量移动平均中新样本的权重
+const THROUGHPUT_WEIGHT: f64 = 0.2;
+
+// 节点信誉配置
+#[derive(Debug, Clone)]
+pub struct ReputationConfig {
+    pub ban_threshold: f64,
+    pub ban_duration: Duration,
+    // 保存评分的文件，None表示不保存
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        ReputationConfig {
+            ban_threshold: DEFAULT_BAN_THRESHOLD,
+            ban_duration: DEFAULT_BAN_DURATION,
+            path: None,
+        }
+    }
+}
+
+// 一次请求的结果
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    // 收到有效响应，bytes为分块大小，清单响应为0
+    Success { bytes: usize, elapsed: Duration },
+    // 内容未通过校验
+    VerificationFailed,
+    Timeout,
+    // 连接失败或响应无效
+    Failed,
+}
+
+// 单个节点的评分和统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerScore {
+    pub score: f64,
+    pub successes: u64,
+    pub verification_failures: u64,
+    pub timeouts: u64,
+    pub failures: u64,
+    // 分块下载吞吐量的移动平均(字节/秒)
+    pub throughput: f64,
+    // 封禁截止时间(Unix时间戳，秒)
+    pub banned_until: Option<u64>,
+}
+
+// 所有节点的信誉，下载时优先使用评分高的提供者
+pub struct Reputation {
+    config: ReputationConfig,
+    peers: HashMap<PeerId, PeerScore>,
+    // 有未保存的变化
+    dirty: bool,
+}
+
+impl Reputation {
+    // 从文件读取评分，文件不存在时从空白开始
+    pub fn load(config: ReputationConfig) -> io::Result<Self> {
+        let peers = match &config.path {
+            Some(path) => persist::load(path)?,
+            None => HashMap::new(),
+        };
+        Ok(Reputation {
+            config,
+            peers,
+            dirty: false,
+        })
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        match &self.config.path {
+            Some(path) if self.dirty => persist::save(path, &self.peers)?,
+            _ => return Ok(()),
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // 记录请求结果，节点因此被封禁时返回true
+    pub fn record(&mut self, peer: PeerId, outcome: Outcome) -> bool {
+        let peer_score = self.peers.entry(peer).or_default();
+        match outcome {
+            Outcome::Success { bytes, elapsed } => {
+                peer_score.successes += 1;
+                peer_score.score = (peer_score.score + SUCCESS_REWARD).min(MAX_SCORE);
+                if bytes > 0 {
+                    let sample = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+                    peer_score.throughput = if peer_score.throughput == 0.0 {
+                        sample
+                    } else {
+                        peer_score.throughput * (1.0 - THROUGHPUT_WEIGHT) + sample * THROUGHPUT_WEIGHT
+                    };
+                }
+            }
+            Outcome::VerificationFailed => {
+                peer_score.verification_failures += 1;
+                peer_score.score -= VERIFICATION_PENALTY;
+            }
+            Outcome::Timeout => {
+                peer_score.timeouts += 1;
+                peer_score.score -= TIMEOUT_PENALTY;
+            }
+            Outcome::Failed => {
+                peer_score.failures += 1;
+                peer_score.score -= FAILURE_PENALTY;
+            }
+        }
+        self.dirty = true;
+
+        let now = unix_time();
+        if peer_score.score < self.config.ban_threshold
+            && peer_score.banned_until.is_none_or(|until| until <= now)
+        {
+            peer_score.banned_until = Some(now + self.config.ban_duration.as_secs());
+            return true;
+        }
+        false
+    }
+
+    pub fn score(&self, peer: &PeerId) -> f64 {
+        self.peers.get(peer).map_or(0.0, |s| s.score)
+    }
+
+    pub fn is_banned(&self, peer: &PeerId) -> bool {
+        let now = unix_time();
+        self.peers
+            .get(peer)
+            .and_then(|s| s.banned_until)
+            .is_some_and(|until| until > now)
+    }
+
+    // 当前被封禁的节点
+    pub fn banned(&self) -> Vec<PeerId> {
+        self.peers
+            .keys()
+            .filter(|peer| self.is_banned(peer))
+            .copied()
+            .collect()
+    }
+
+    // 解除已到期的封禁，评分重置为0，返回解除封禁的节点
+    pub fn expire_bans(&mut self) -> Vec<PeerId> {
+        let now = unix_time();
+        let mut expired = Vec::new();
+        for (peer, peer_score) in self.peers.iter_mut() {
+            if peer_score.banned_until.is_some_and(|until| until <= now) {
+                peer_score.banned_until = None;
+                peer_score.score = 0.0;
+                expired.push(*peer);
+            }
+        }
+        if !expired.is_empty() {
+            self.dirty = true;
+        }
+        expired
+    }
+
+    // 排除被封禁的节点，按评分和吞吐量从高到低排列提供者
+    pub fn rank(&self, providers: HashSet<PeerId>) -> Vec<PeerId> {
+        let mut providers: Vec<_> = providers
+            .into_iter()
+            .filter(|peer| !self.is_banned(peer))
+            .collect();
+        let key = |peer: &PeerId| {
+            let peer_score = self.peers.get(peer).cloned().unwrap_or_default();
+            (peer_score.score, peer_score.throughput)
+        };
+        providers.sort_by(|a, b| key(b).partial_cmp(&key(a)).unwrap_or(std::cmp::Ordering::Equal));
+        providers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reputation(ban_duration: Duration) -> Reputation {
+        Reputation::load(ReputationConfig {
+            ban_duration,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn record_updates_score_and_throughput() {
+        let mut reputation = reputation(DEFAULT_BAN_DURATION);
+        let peer = PeerId::random();
+        let success = Outcome::Success {
+            bytes: 1000,
+            elapsed: Duration::from_secs(1),
+        };
+
+        assert!(!reputation.record(peer, success));
+        assert_eq!(reputation.score(&peer), SUCCESS_REWARD);
+        assert_eq!(reputation.peers[&peer].throughput, 1000.0);
+        reputation.record(
+            peer,
+            Outcome::Success {
+                bytes: 2000,
+                elapsed: Duration::from_secs(1),
+            },
+        );
+        assert_eq!(reputation.peers[&peer].throughput, 1000.0 * 0.8 + 2000.0 * 0.2);
+        // 清单响应不影响吞吐量
+        reputation.record(
+            peer,
+            Outcome::Success {
+                bytes: 0,
+                elapsed: Duration::from_secs(1),
+            },
+        );
+        assert_eq!(reputation.peers[&peer].throughput, 1000.0 * 0.8 + 2000.0 * 0.2);
+
+        reputation.record(peer, Outcome::Timeout);
+        reputation.record(peer, Outcome::Failed);
+        assert_eq!(reputation.score(&peer), 3.0 - TIMEOUT_PENALTY - FAILURE_PENALTY);
+        assert_eq!(reputation.score(&PeerId::random()), 0.0);
+    }
+
+    #[test]
+    fn score_is_capped() {
+        let mut reputation = reputation(DEFAULT_BAN_DURATION);
+        let peer = PeerId::random();
+        for _ in 0..200 {
+            reputation.record(
+                peer,
+                Outcome::Success {
+                    bytes: 0,
+                    elapsed: Duration::ZERO,
+                },
+            );
+        }
+        assert_eq!(reputation.score(&peer), MAX_SCORE);
+    }
+
+    #[test]
+    fn peer_is_banned_once_below_threshold() {
+        let mut reputation = reputation(DEFAULT_BAN_DURATION);
+        let peer = PeerId::random();
+
+        // -25、-50时未低于阈值，-75时被封禁
+        assert!(!reputation.record(peer, Outcome::VerificationFailed));
+        assert!(!reputation.record(peer, Outcome::VerificationFailed));
+        assert!(!reputation.is_banned(&peer));
+        assert!(reputation.record(peer, Outcome::VerificationFailed));
+        assert!(reputation.is_banned(&peer));
+        assert_eq!(reputation.banned(), vec![peer]);
+        // 已被封禁时不再报告
+        assert!(!reputation.record(peer, Outcome::VerificationFailed));
+        // 封禁尚未到期
+        assert!(reputation.expire_bans().is_empty());
+    }
+
+    #[test]
+    fn expired_bans_reset_the_score() {
+        let mut reputation = reputation(Duration::ZERO);
+        let peer = PeerId::random();
+        for _ in 0..3 {
+            reputation.record(peer, Outcome::VerificationFailed);
+        }
+
+        assert_eq!(reputation.expire_bans(), vec![peer]);
+        assert_eq!(reputation.score(&peer), 0.0);
+        assert!(!reputation.is_banned(&peer));
+        assert!(reputation.expire_bans().is_empty());
+    }
+
+    #[test]
+    fn rank_orders_by_score_then_throughput_and_skips_banned() {
+        let mut reputation = reputation(DEFAULT_BAN_DURATION);
+        let [good, fast, slow, unknown, banned] = [(); 5].map(|_| PeerId::random());
+        let success = |bytes| Outcome::Success {
+            bytes,
+            elapsed: Duration::from_secs(1),
+        };
+        reputation.record(good, success(10));
+        reputation.record(good, success(10));
+        reputation.record(fast, success(1000));
+        reputation.record(slow, success(10));
+        for _ in 0..3 {
+            reputation.record(banned, Outcome::VerificationFailed);
+        }
+
+        let providers = [good, fast, slow, unknown, banned].into_iter().collect();
+        assert_eq!(reputation.rank(providers), vec![good, fast, slow, unknown]);
+    }
+}
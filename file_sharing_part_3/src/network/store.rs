@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+
+use libp2p::{
+    kad::{
+        record::Key,
+        store::{RecordStore, Result},
+        ProviderRecord, Record,
+    },
+    PeerId,
+};
+
+// 对象安全的记录存储，迭代器装箱后返回；实现了RecordStore的存储都实现该trait
+trait Store: Send + 'static {
+    fn get(&self, k: &Key) -> Option<Cow<'_, Record>>;
+    fn put(&mut self, r: Record) -> Result<()>;
+    fn remove(&mut self, k: &Key);
+    fn records(&self) -> Box<dyn Iterator<Item = Cow<'_, Record>> + '_>;
+    fn add_provider(&mut self, record: ProviderRecord) -> Result<()>;
+    fn providers(&self, key: &Key) -> Vec<ProviderRecord>;
+    fn provided(&self) -> Box<dyn Iterator<Item = Cow<'_, ProviderRecord>> + '_>;
+    fn remove_provider(&mut self, k: &Key, p: &PeerId);
+}
+
+impl<S> Store for S
+where
+    S: for<'a> RecordStore<'a> + Send + 'static,
+{
+    fn get(&self, k: &Key) -> Option<Cow<'_, Record>> {
+        RecordStore::get(self, k)
+    }
+
+    fn put(&mut self, r: Record) -> Result<()> {
+        RecordStore::put(self, r)
+    }
+
+    fn remove(&mut self, k: &Key) {
+        RecordStore::remove(self, k)
+    }
+
+    fn records(&self) -> Box<dyn Iterator<Item = Cow<'_, Record>> + '_> {
+        Box::new(RecordStore::records(self))
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+        RecordStore::add_provider(self, record)
+    }
+
+    fn providers(&self, key: &Key) -> Vec<ProviderRecord> {
+        RecordStore::providers(self, key)
+    }
+
+    fn provided(&self) -> Box<dyn Iterator<Item = Cow<'_, ProviderRecord>> + '_> {
+        Box::new(RecordStore::provided(self))
+    }
+
+    fn remove_provider(&mut self, k: &Key, p: &PeerId) {
+        RecordStore::remove_provider(self, k, p)
+    }
+}
+
+// Kademlia使用的记录存储，可以是任意实现了RecordStore的存储
+pub struct BoxedStore(Box<dyn Store>);
+
+impl BoxedStore {
+    pub fn new<S>(store: S) -> Self
+    where
+        S: for<'a> RecordStore<'a> + Send + 'static,
+    {
+        BoxedStore(Box::new(store))
+    }
+}
+
+impl<'a> RecordStore<'a> for BoxedStore {
+    type RecordsIter = Box<dyn Iterator<Item = Cow<'a, Record>> + 'a>;
+    type ProvidedIter = Box<dyn Iterator<Item = Cow<'a, ProviderRecord>> + 'a>;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'a, Record>> {
+        self.0.get(k)
+    }
+
+    fn put(&'a mut self, r: Record) -> Result<()> {
+        self.0.put(r)
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        self.0.remove(k)
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        self.0.records()
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> Result<()> {
+        self.0.add_provider(record)
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        self.0.providers(key)
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        self.0.provided()
+    }
+
+    fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+        self.0.remove_provider(k, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::kad::store::{MemoryStore, MemoryStoreConfig};
+
+    use super::{BoxedStore, Key, PeerId, ProviderRecord, Record, RecordStore};
+
+    #[test]
+    fn boxed_store_forwards_to_the_inner_store() {
+        let local = PeerId::random();
+        let config = MemoryStoreConfig {
+            max_records: 1,
+            ..Default::default()
+        };
+        let mut store = BoxedStore::new(MemoryStore::with_config(local, config));
+
+        let record = Record::new(Key::new(&"a"), b"value".to_vec());
+        store.put(record.clone()).unwrap();
+        assert_eq!(store.get(&record.key).as_deref(), Some(&record));
+        // 内部存储的限制仍然生效
+        assert!(store.put(Record::new(Key::new(&"b"), Vec::new())).is_err());
+        store.remove(&record.key);
+        assert_eq!(store.records().count(), 0);
+
+        let key = Key::new(&"file");
+        store
+            .add_provider(ProviderRecord::new(key.clone(), local, Vec::new()))
+            .unwrap();
+        assert_eq!(store.providers(&key).len(), 1);
+        assert_eq!(store.provided().count(), 1);
+        store.remove_provider(&key, &local);
+        assert_eq!(store.provided().count(), 0);
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+
+use super::{event::Event, transfer::TransferId};
+
+// 事件类别，用于过滤订阅的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    // 本地监听地址
+    Listener,
+    // 节点连接和断开
+    Connection,
+    // 查找提供者
+    Discovery,
+    // 文件下载
+    Transfer,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::ListeningOn { .. } => EventKind::Listener,
+            Event::PeerConnected { .. }
+            | Event::PeerDisconnected { .. }
+            | Event::PeerBanned { .. }
+            | Event::ReachabilityChanged { .. } => EventKind::Connection,
+            Event::ProvidersFound { .. } => EventKind::Discovery,
+            Event::TransferStarted { .. }
+            | Event::BytesTransferred { .. }
+            | Event::ChunkVerified { .. }
+            | Event::TransferCompleted { .. }
+            | Event::TransferFailed { .. } => EventKind::Transfer,
+        }
+    }
+
+    // 下载相关事件所属的下载任务
+    pub fn transfer(&self) -> Option<TransferId> {
+        match self {
+            Event::TransferStarted { transfer, .. }
+            | Event::BytesTransferred { transfer, .. }
+            | Event::ChunkVerified { transfer, .. }
+            | Event::TransferCompleted { transfer, .. }
+            | Event::TransferFailed { transfer, .. } => Some(*transfer),
+            _ => None,
+        }
+    }
+}
+
+// 事件过滤条件，未指定的条件不做过滤
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    kinds: Option<HashSet<EventKind>>,
+    transfer: Option<TransferId>,
+}
+
+impl EventFilter {
+    // 接收所有事件
+    pub fn all() -> Self {
+        Default::default()
+    }
+
+    // 只接收给定类别的事件
+    pub fn kinds(kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        EventFilter {
+            kinds: Some(kinds.into_iter().collect()),
+            transfer: None,
+        }
+    }
+
+    // 只接收给定下载任务的下载事件
+    pub fn with_transfer(mut self, transfer: TransferId) -> Self {
+        self.transfer = Some(transfer);
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&event.kind()))
+            && self
+                .transfer
+                .is_none_or(|transfer| event.transfer().is_none_or(|t| t == transfer))
+    }
+}
+
+// 事件订阅，每个订阅者有独立的缓冲区，处理不及时的订阅者会丢失事件而不会阻塞网络事件处理
+pub struct Subscription {
+    receiver: broadcast::Receiver<Event>,
+    filter: EventFilter,
+}
+
+impl Subscription {
+    pub fn new(receiver: broadcast::Receiver<Event>, filter: EventFilter) -> Self {
+        Subscription { receiver, filter }
+    }
+
+    // 接收下一个符合过滤条件的事件
+    // 订阅者落后太多时返回RecvError::Lagged及丢失的事件数量，之后可以继续接收
+    pub async fn recv(&mut self) -> Result<Event, RecvError> {
+        loop {
+            let event = self.receiver.recv().await?;
+            if self.filter.matches(&event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    // 不等待，取出已缓冲的下一个符合过滤条件的事件
+    pub fn try_recv(&mut self) -> Result<Event, TryRecvError> {
+        loop {
+            let event = self.receiver.try_recv()?;
+            if self.filter.matches(&event) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use super::*;
+
+    // 每个类别各一个事件，下载事件属于给定的下载任务
+    fn events(transfer: TransferId) -> Vec<Event> {
+        vec![
+            Event::ListeningOn {
+                address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            },
+            Event::PeerConnected { peer: PeerId::random() },
+            Event::ProvidersFound {
+                file_name: "file".to_string(),
+                providers: HashSet::new(),
+            },
+            Event::ChunkVerified {
+                transfer,
+                peer: PeerId::random(),
+                chunk: 0,
+            },
+        ]
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = EventFilter::all();
+        assert!(events(1).iter().all(|event| filter.matches(event)));
+    }
+
+    #[test]
+    fn kinds_filter_matches_only_its_kinds() {
+        let kinds = [EventKind::Listener, EventKind::Connection, EventKind::Discovery, EventKind::Transfer];
+        for (event, kind) in events(1).iter().zip(kinds) {
+            assert!(EventFilter::kinds([kind]).matches(event), "{:?}", event);
+            for other in kinds.iter().filter(|k| **k != kind) {
+                assert!(!EventFilter::kinds([*other]).matches(event), "{:?} {:?}", event, other);
+            }
+        }
+        let filter = EventFilter::kinds([EventKind::Connection, EventKind::Transfer]);
+        let matched: Vec<_> = events(1).iter().map(|event| filter.matches(event)).collect();
+        assert_eq!(matched, [false, true, false, true]);
+        assert!(!EventFilter::kinds([]).matches(&events(1)[0]));
+    }
+
+    #[test]
+    fn transfer_filter_matches_only_its_transfer() {
+        let filter = EventFilter::kinds([EventKind::Transfer]).with_transfer(1);
+        assert!(filter.matches(&events(1)[3]));
+        assert!(!filter.matches(&events(2)[3]));
+
+        // 不属于任何下载任务的事件只按类别过滤
+        let filter = EventFilter::all().with_transfer(1);
+        let matched: Vec<_> = events(2).iter().map(|event| filter.matches(event)).collect();
+        assert_eq!(matched, [true, true, true, false]);
+    }
+
+    #[test]
+    fn subscriptions_skip_filtered_events() {
+        let (sender, receiver) = broadcast::channel(16);
+        let mut subscription = Subscription::new(receiver, EventFilter::kinds([EventKind::Transfer]).with_transfer(1));
+        for event in events(2).into_iter().chain(events(1)) {
+            sender.send(event).unwrap();
+        }
+        assert!(matches!(
+            subscription.try_recv(),
+            Ok(Event::ChunkVerified { transfer: 1, .. })
+        ));
+        assert!(matches!(subscription.try_recv(), Err(TryRecvError::Empty)));
+    }
+}
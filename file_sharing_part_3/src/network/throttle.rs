@@ -0,0 +1,227 @@
+use std::collections::{HashMap, VecDeque};
+
+use libp2p::PeerId;
+use tokio::time::Instant;
+
+// 上传限制配置。速率限制作用于响应的准入：响应整体发送并一次性扣除令牌，
+// 令牌透支后推迟发送后续响应，长期平均速率不超过限制，但单个响应的发送速度不受限制
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    // 全局上传速率(字节/秒)，None表示不限制
+    pub global_rate: Option<u64>,
+    // 每个节点的上传速率(字节/秒)，None表示不限制
+    pub peer_rate: Option<u64>,
+    // 同时进行的上传数量上限
+    pub max_concurrent: usize,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        UploadLimits {
+            global_rate: None,
+            peer_rate: None,
+            max_concurrent: 8,
+        }
+    }
+}
+
+// 令牌桶，允许透支，透支后需等待令牌补充到非负才能继续发送
+struct TokenBucket {
+    // 每秒补充的令牌数，同时也是桶的容量
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    fn ready(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.last = now;
+        self.tokens >= 0.0
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+// 等待上传的响应队列，按节点轮流调度，保证各请求节点公平获取带宽
+pub struct UploadQueue<T> {
+    limits: UploadLimits,
+    global_bucket: Option<TokenBucket>,
+    peer_buckets: HashMap<PeerId, TokenBucket>,
+    // 每个节点等待上传的响应及其大小
+    queues: HashMap<PeerId, VecDeque<(usize, T)>>,
+    // 有等待响应的节点，按轮询顺序排列
+    order: VecDeque<PeerId>,
+    // 正在进行的上传数量
+    active: usize,
+}
+
+impl<T> UploadQueue<T> {
+    pub fn new(limits: UploadLimits) -> Self {
+        UploadQueue {
+            global_bucket: limits
+                .global_rate
+                .map(|rate| TokenBucket::new(rate, Instant::now())),
+            limits,
+            peer_buckets: Default::default(),
+            queues: Default::default(),
+            order: Default::default(),
+            active: 0,
+        }
+    }
+
+    // 将响应加入节点的等待队列
+    pub fn push(&mut self, peer: PeerId, size: usize, item: T) {
+        let queue = self.queues.entry(peer).or_default();
+        if queue.is_empty() {
+            self.order.push_back(peer);
+        }
+        queue.push_back((size, item));
+    }
+
+    // 等待上传的响应数量
+    pub fn waiting(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    // 取出下一个可以发送的响应，未达到并发和速率限制时返回
+    pub fn pop_ready(&mut self) -> Option<(PeerId, T)> {
+        self.pop_ready_at(Instant::now())
+    }
+
+    fn pop_ready_at(&mut self, now: Instant) -> Option<(PeerId, T)> {
+        if self.active >= self.limits.max_concurrent {
+            return None;
+        }
+        if let Some(bucket) = self.global_bucket.as_mut() {
+            if !bucket.ready(now) {
+                return None;
+            }
+        }
+
+        // 从轮询顺序中找到第一个未超过速率限制的节点
+        for _ in 0..self.order.len() {
+            let peer = self.order.pop_front().expect("Order to be non-empty.");
+            let ready = match self.limits.peer_rate {
+                Some(rate) => self
+                    .peer_buckets
+                    .entry(peer)
+                    .or_insert_with(|| TokenBucket::new(rate, now))
+                    .ready(now),
+                None => true,
+            };
+            if !ready {
+                self.order.push_back(peer);
+                continue;
+            }
+
+            let queue = self.queues.get_mut(&peer).expect("Queued peer to have a queue.");
+            let (size, item) = queue.pop_front().expect("Queued peer to have a response.");
+            if queue.is_empty() {
+                self.queues.remove(&peer);
+            } else {
+                self.order.push_back(peer);
+            }
+
+            if let Some(bucket) = self.global_bucket.as_mut() {
+                bucket.consume(size);
+            }
+            if let Some(bucket) = self.peer_buckets.get_mut(&peer) {
+                bucket.consume(size);
+            }
+            // 没有等待响应且未透支的节点不再需要保留令牌桶
+            if !self.queues.contains_key(&peer) {
+                self.peer_buckets.retain(|p, b| p != &peer || b.tokens < 0.0);
+            }
+
+            self.active += 1;
+            return Some((peer, item));
+        }
+
+        None
+    }
+
+    // 一个上传已结束
+    pub fn finish(&mut self) {
+        self.active = self.active.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn token_bucket_refills_up_to_its_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100, start);
+        assert!(bucket.ready(start));
+        // 允许透支，透支后需等待补充
+        bucket.consume(150);
+        assert!(!bucket.ready(start));
+        assert!(!bucket.ready(start + Duration::from_millis(400)));
+        assert!(bucket.ready(start + Duration::from_millis(500)));
+
+        // 补充的令牌不超过桶的容量
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.ready(later));
+        assert_eq!(bucket.tokens, 100.0);
+    }
+
+    #[test]
+    fn peers_are_served_in_turn() {
+        let now = Instant::now();
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let mut queue = UploadQueue::new(UploadLimits::default());
+        for i in 0..3 {
+            queue.push(a, 1, ("a", i));
+        }
+        queue.push(b, 1, ("b", 0));
+        queue.push(b, 1, ("b", 1));
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop_ready_at(now).map(|(_, item)| item)).collect();
+        assert_eq!(order, [("a", 0), ("b", 0), ("a", 1), ("b", 1), ("a", 2)]);
+        assert_eq!(queue.waiting(), 0);
+    }
+
+    #[test]
+    fn limits_delay_responses() {
+        let now = Instant::now();
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let mut queue = UploadQueue::new(UploadLimits {
+            global_rate: None,
+            peer_rate: Some(100),
+            max_concurrent: 2,
+        });
+        queue.push(a, 200, 0);
+        queue.push(a, 10, 1);
+        queue.push(b, 10, 2);
+        queue.push(b, 10, 3);
+
+        // 节点a透支后跳过，轮到节点b
+        assert_eq!(queue.pop_ready_at(now), Some((a, 0)));
+        assert_eq!(queue.pop_ready_at(now), Some((b, 2)));
+        // 达到并发上限
+        assert_eq!(queue.pop_ready_at(now), None);
+        queue.finish();
+        assert_eq!(queue.pop_ready_at(now), Some((b, 3)));
+        queue.finish();
+        assert_eq!(queue.pop_ready_at(now), None);
+        assert_eq!(queue.pop_ready_at(now + Duration::from_secs(1)), Some((a, 1)));
+    }
+}
@@ -0,0 +1,593 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::Range,
+};
+
+use libp2p::PeerId;
+use sha2::{Digest, Sha256};
+
+use super::protocol::FileResponse;
+
+// 文件分块大小
+pub const CHUNK_SIZE: usize = 256 * 1024;
+// 每个提供者同时请求的分块数量上限
+pub const MAX_CHUNKS_IN_FLIGHT_PER_PEER: usize = 4;
+
+// 下载任务ID
+pub type TransferId = u64;
+
+// 文件清单，包含文件大小、文件摘要和每个分块的摘要
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub size: u64,
+    // 内容是否为接收者加密，由提供者声明，不根据内容判断
+    pub encrypted: bool,
+    pub digest: [u8; 32],
+    pub chunk_digests: Vec<[u8; 32]>,
+}
+
+// 清单中标志字节的各位
+const FLAG_ENCRYPTED: u8 = 1;
+
+impl Manifest {
+    pub fn new(content: &[u8], encrypted: bool) -> Self {
+        Manifest {
+            size: content.len() as u64,
+            encrypted,
+            digest: Sha256::digest(content).into(),
+            chunk_digests: content
+                .chunks(CHUNK_SIZE)
+                .map(|chunk| Sha256::digest(chunk).into())
+                .collect(),
+        }
+    }
+
+    // 分块数量与文件大小一致
+    pub fn is_consistent(&self) -> bool {
+        self.chunk_digests.len() as u64 == self.size.div_ceil(CHUNK_SIZE as u64)
+    }
+
+    // 检查分块长度和摘要
+    pub fn verify_chunk(&self, index: u64, data: &[u8]) -> bool {
+        match self.chunk_digests.get(index as usize) {
+            Some(digest) => {
+                let expected_len = (self.size - index * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64);
+                data.len() as u64 == expected_len && Sha256::digest(data).as_slice() == digest
+            }
+            None => false,
+        }
+    }
+
+    // 检查完整文件的摘要
+    pub fn verify(&self, content: &[u8]) -> bool {
+        content.len() as u64 == self.size && Sha256::digest(content).as_slice() == self.digest
+    }
+
+    // 编码后的长度
+    pub fn encoded_len(&self) -> usize {
+        41 + self.chunk_digests.len() * 32
+    }
+
+    // 编码格式：文件大小(8) | 标志(1) | 文件摘要(32) | 分块摘要(32 * n)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&self.size.to_be_bytes());
+        bytes.push(if self.encrypted { FLAG_ENCRYPTED } else { 0 });
+        bytes.extend_from_slice(&self.digest);
+        for digest in &self.chunk_digests {
+            bytes.extend_from_slice(digest);
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        // 分块摘要部分的长度必须是32的整数倍
+        let digests = bytes.get(41..)?.chunks_exact(32);
+        if !digests.remainder().is_empty() {
+            return None;
+        }
+        let size = u64::from_be_bytes(bytes[..8].try_into().ok()?);
+        // 拒绝未知的标志，避免误解较新版本的清单
+        let flags = bytes[8];
+        if flags & !FLAG_ENCRYPTED != 0 {
+            return None;
+        }
+        let digest = bytes[9..41].try_into().ok()?;
+        let chunk_digests = digests
+            .map(|chunk| chunk.try_into().expect("Chunk length to be 32."))
+            .collect();
+        Some(Manifest {
+            size,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            digest,
+            chunk_digests,
+        })
+    }
+}
+
+// 本节点提供的共享文件
+pub struct SharedFile {
+    content: Vec<u8>,
+    manifest: Manifest,
+}
+
+impl SharedFile {
+    // encrypted表示内容已为接收者加密
+    pub fn new(content: Vec<u8>, encrypted: bool) -> Self {
+        let manifest = Manifest::new(&content, encrypted);
+        SharedFile { content, manifest }
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    // 根据请求的分块序号生成响应，为空时返回文件清单
+    pub fn respond(&self, chunk: Option<u64>) -> FileResponse {
+        match chunk {
+            None => FileResponse::Manifest(self.manifest.clone()),
+            Some(index) => match self.content.chunks(CHUNK_SIZE).nth(index as usize) {
+                Some(data) => FileResponse::Chunk(data.to_vec()),
+                None => FileResponse::NotFound,
+            },
+        }
+    }
+}
+
+// 收到分块的处理结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    // 通过校验；culprits为此前发送该分块但未通过校验的节点，确认是这些节点发送了错误的数据
+    Verified { culprits: Vec<PeerId> },
+    // 未通过校验，尚不确定是分块还是清单有误，暂不再从该节点下载
+    Rejected,
+    // 提供清单的节点自己的分块与清单不一致，或多个节点的分块都未通过校验，
+    // 说明清单有误，已丢弃该清单，需从其他提供者重新获取
+    ManifestRejected { manifest_peer: PeerId },
+    // 丢弃清单之前发出的请求，忽略其结果
+    Stale,
+}
+
+// 下载完成的文件，只下载部分分块时content为这些分块的内容
+#[derive(Debug)]
+pub struct DownloadedFile {
+    pub manifest: Manifest,
+    pub content: Vec<u8>,
+}
+
+// 下载任务状态，按清单从多个提供者并行获取分块
+pub struct Download {
+    pub file_name: String,
+    pub token: Option<Vec<u8>>,
+    // 是否有响应来自经由中继连接的提供者
+    pub relayed: bool,
+    // 仍可使用的提供者，靠前的优先使用，由调用者按信誉排序
+    providers: Vec<PeerId>,
+    manifest: Option<Manifest>,
+    // 提供文件清单的节点
+    manifest_peer: Option<PeerId>,
+    // 只下载该范围内的分块，不校验整个文件；None表示下载整个文件
+    partial: Option<Range<u64>>,
+    chunks: Vec<Option<Vec<u8>>>,
+    // 尚未请求的分块
+    missing: VecDeque<u64>,
+    // 是否正在请求文件清单
+    manifest_pending: bool,
+    // 每个提供者正在请求的分块数量
+    in_flight: HashMap<PeerId, usize>,
+    // 按当前清单发出、尚未完成的分块请求
+    requested: HashSet<(PeerId, u64)>,
+    // 发送的分块未通过校验的节点及该分块序号，按失败顺序排列，清单被丢弃后恢复使用
+    suspects: Vec<(PeerId, u64)>,
+    // 最近一次失败的原因
+    last_error: Option<String>,
+}
+
+impl Download {
+    pub fn new(file_name: String, token: Option<Vec<u8>>, providers: Vec<PeerId>) -> Self {
+        Download {
+            file_name,
+            token,
+            relayed: false,
+            providers,
+            manifest: None,
+            manifest_peer: None,
+            partial: None,
+            chunks: Vec::new(),
+            missing: VecDeque::new(),
+            manifest_pending: false,
+            in_flight: HashMap::new(),
+            requested: HashSet::new(),
+            suspects: Vec::new(),
+            last_error: None,
+        }
+    }
+
+    // 只下载指定范围内的分块，已知清单时不再请求清单
+    pub fn partial(
+        file_name: String,
+        token: Option<Vec<u8>>,
+        providers: Vec<PeerId>,
+        manifest: Option<Manifest>,
+        chunks: Range<u64>,
+    ) -> Self {
+        let mut download = Download::new(file_name, token, providers);
+        download.partial = Some(chunks);
+        if let Some(manifest) = manifest {
+            download.use_manifest(manifest);
+        }
+        download
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    pub fn manifest_peer(&self) -> Option<PeerId> {
+        self.manifest_peer
+    }
+
+    pub fn set_manifest(&mut self, peer: PeerId, manifest: Manifest) {
+        self.manifest_pending = false;
+        self.manifest_peer = Some(peer);
+        self.use_manifest(manifest);
+    }
+
+    fn use_manifest(&mut self, manifest: Manifest) {
+        self.chunks = vec![None; manifest.chunk_digests.len()];
+        self.manifest = Some(manifest);
+        self.missing = self.range().collect();
+    }
+
+    // 需要下载的分块，超出清单的部分被忽略
+    fn range(&self) -> Range<u64> {
+        let count = self.chunks.len() as u64;
+        match &self.partial {
+            Some(range) => range.start.min(count)..range.end.min(count),
+            None => 0..count,
+        }
+    }
+
+    // 取出下一个请求：没有清单时向第一个提供者请求清单，
+    // 否则将分块分配给正在请求分块最少的提供者
+    pub fn next_request(&mut self) -> Option<(PeerId, Option<u64>)> {
+        if self.manifest.is_none() {
+            if self.manifest_pending {
+                return None;
+            }
+            let peer = *self.providers.first()?;
+            self.manifest_pending = true;
+            return Some((peer, None));
+        }
+
+        let peer = self
+            .providers
+            .iter()
+            .map(|p| (*p, self.in_flight.get(p).copied().unwrap_or(0)))
+            .filter(|(_, n)| *n < MAX_CHUNKS_IN_FLIGHT_PER_PEER)
+            .min_by_key(|(_, n)| *n)
+            .map(|(p, _)| p)?;
+        let index = self.missing.pop_front()?;
+        *self.in_flight.entry(peer).or_default() += 1;
+        self.requested.insert((peer, index));
+        Some((peer, Some(index)))
+    }
+
+    // 收到分块，校验通过时保存；未通过时根据失败的节点判断是分块还是清单有误
+    pub fn on_chunk(&mut self, peer: PeerId, index: u64, data: Vec<u8>) -> ChunkOutcome {
+        self.release(&peer);
+        if !self.requested.remove(&(peer, index)) {
+            return ChunkOutcome::Stale;
+        }
+        let manifest = self.manifest.as_ref().expect("Manifest to be set while chunks are requested.");
+        if manifest.verify_chunk(index, &data) {
+            self.chunks[index as usize] = Some(data);
+            let culprits = self
+                .suspects
+                .iter()
+                .filter(|(_, failed)| *failed == index)
+                .map(|(peer, _)| *peer)
+                .collect();
+            self.suspects.retain(|(_, failed)| *failed != index);
+            return ChunkOutcome::Verified { culprits };
+        }
+
+        self.missing.push_back(index);
+        self.providers.retain(|p| *p != peer);
+        self.last_error = Some(format!("Chunk {} from {} failed verification.", index, peer));
+        self.suspects.push((peer, index));
+        match self.manifest_peer {
+            Some(manifest_peer) if manifest_peer == peer || self.suspects.len() >= 2 => {
+                self.reject_manifest();
+                ChunkOutcome::ManifestRejected { manifest_peer }
+            }
+            _ => ChunkOutcome::Rejected,
+        }
+    }
+
+    // 丢弃从网络获取的清单，不再使用提供该清单的节点，并恢复使用因此被怀疑的节点，之后重新获取清单
+    pub fn reject_manifest(&mut self) -> Option<PeerId> {
+        let manifest_peer = self.manifest_peer.take()?;
+        self.manifest = None;
+        self.chunks.clear();
+        self.missing.clear();
+        self.requested.clear();
+        for (peer, _) in self.suspects.drain(..) {
+            if peer != manifest_peer && !self.providers.contains(&peer) {
+                self.providers.push(peer);
+            }
+        }
+        self.providers.retain(|p| *p != manifest_peer);
+        self.last_error = Some(format!("Manifest from {} is inconsistent.", manifest_peer));
+        Some(manifest_peer)
+    }
+
+    // 请求失败，放弃该提供者，未完成的分块重新排队
+    pub fn on_failure(&mut self, peer: PeerId, index: Option<u64>, error: String) {
+        self.on_interrupted(peer, index);
+        self.providers.retain(|p| *p != peer);
+        self.last_error = Some(error);
+    }
+
+    // 请求所经由的连接被关闭，而与提供者之间仍有其他连接，重新排队但保留提供者
+    pub fn on_interrupted(&mut self, peer: PeerId, index: Option<u64>) {
+        match index {
+            Some(index) => {
+                self.release(&peer);
+                if self.requested.remove(&(peer, index)) {
+                    self.missing.push_back(index);
+                }
+            }
+            None => self.manifest_pending = false,
+        }
+    }
+
+    // 不再使用该提供者，进行中的请求失败后重新排队
+    pub fn remove_provider(&mut self, peer: &PeerId) {
+        self.providers.retain(|p| p != peer);
+    }
+
+    fn release(&mut self, peer: &PeerId) {
+        if let Some(n) = self.in_flight.get_mut(peer) {
+            *n = n.saturating_sub(1);
+        }
+    }
+
+    // 需要的分块均已收到
+    pub fn is_complete(&self) -> bool {
+        let range = self.range();
+        self.manifest.is_some() && self.chunks[range.start as usize..range.end as usize].iter().all(Option::is_some)
+    }
+
+    // 没有可用的提供者，且没有正在进行的请求
+    pub fn is_stalled(&self) -> bool {
+        self.providers.is_empty()
+            && !self.manifest_pending
+            && self.in_flight.values().all(|n| *n == 0)
+    }
+
+    pub fn last_error(&self) -> String {
+        self.last_error
+            .clone()
+            .unwrap_or_else(|| "No providers left.".to_string())
+    }
+
+    // 拼接需要的分块，下载整个文件时校验完整文件的摘要
+    pub fn assemble(&mut self) -> Result<DownloadedFile, String> {
+        let range = self.range();
+        let content: Vec<u8> = self.chunks[range.start as usize..range.end as usize]
+            .iter_mut()
+            .flat_map(|chunk| chunk.take().unwrap_or_default())
+            .collect();
+        match &self.manifest {
+            Some(manifest) if self.partial.is_some() || manifest.verify(&content) => Ok(DownloadedFile {
+                manifest: manifest.clone(),
+                content,
+            }),
+            _ => Err(format!("File {} failed verification.", self.file_name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 三个分块的文件，最后一个分块较短
+    fn content() -> Vec<u8> {
+        (0..2 * CHUNK_SIZE + 10).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn chunk(content: &[u8], index: u64) -> Vec<u8> {
+        content.chunks(CHUNK_SIZE).nth(index as usize).unwrap().to_vec()
+    }
+
+    // 从第一个提供者获取清单，并把三个分块依次分配给三个提供者
+    fn started(content: &[u8]) -> (Download, [PeerId; 3]) {
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let mut download = Download::new("file".to_string(), None, peers.to_vec());
+        assert_eq!(download.next_request(), Some((peers[0], None)));
+        download.set_manifest(peers[0], Manifest::new(content, false));
+        for (i, peer) in peers.iter().enumerate() {
+            assert_eq!(download.next_request(), Some((*peer, Some(i as u64))));
+        }
+        (download, peers)
+    }
+
+    #[test]
+    fn one_bad_chunk_is_not_blamed_until_confirmed() {
+        let content = content();
+        let (mut download, peers) = started(&content);
+
+        assert_eq!(download.on_chunk(peers[1], 1, vec![0; CHUNK_SIZE]), ChunkOutcome::Rejected);
+        // 其他节点发送的同一分块通过校验，确认是peers[1]发送了错误的数据
+        assert_eq!(download.next_request(), Some((peers[0], Some(1))));
+        assert_eq!(
+            download.on_chunk(peers[0], 1, chunk(&content, 1)),
+            ChunkOutcome::Verified {
+                culprits: vec![peers[1]]
+            }
+        );
+    }
+
+    #[test]
+    fn chunks_failing_from_two_peers_reject_the_manifest() {
+        let content = content();
+        let (mut download, peers) = started(&content);
+
+        assert_eq!(download.on_chunk(peers[1], 1, vec![0; CHUNK_SIZE]), ChunkOutcome::Rejected);
+        assert_eq!(
+            download.on_chunk(peers[2], 2, vec![0; 10]),
+            ChunkOutcome::ManifestRejected {
+                manifest_peer: peers[0]
+            }
+        );
+        assert!(download.manifest().is_none());
+        // 按旧清单发出的请求被忽略
+        assert_eq!(download.on_chunk(peers[0], 0, chunk(&content, 0)), ChunkOutcome::Stale);
+        // 被怀疑的节点恢复使用，从其中之一重新获取清单
+        assert_eq!(download.next_request(), Some((peers[1], None)));
+        download.set_manifest(peers[1], Manifest::new(&content, false));
+        assert!(!download.is_stalled());
+    }
+
+    #[test]
+    fn bad_chunk_from_manifest_peer_rejects_the_manifest() {
+        let content = content();
+        let (mut download, peers) = started(&content);
+
+        assert_eq!(
+            download.on_chunk(peers[0], 0, vec![0; CHUNK_SIZE]),
+            ChunkOutcome::ManifestRejected {
+                manifest_peer: peers[0]
+            }
+        );
+        assert_eq!(download.next_request(), Some((peers[1], None)));
+    }
+
+    #[test]
+    fn interrupted_request_keeps_the_provider() {
+        let content = content();
+        let (mut download, peers) = started(&content);
+
+        download.on_interrupted(peers[2], Some(2));
+        assert_eq!(download.next_request(), Some((peers[2], Some(2))));
+
+        download.on_failure(peers[2], Some(2), "failed".to_string());
+        assert_eq!(download.next_request(), Some((peers[0], Some(2))));
+    }
+
+    #[test]
+    fn inconsistent_file_digest_rejects_the_manifest() {
+        let content = content();
+        let (mut download, peers) = started(&content);
+        let mut manifest = Manifest::new(&content, false);
+        manifest.digest = [0; 32];
+        download.manifest = Some(manifest);
+        for (i, peer) in peers.iter().enumerate() {
+            let outcome = download.on_chunk(*peer, i as u64, chunk(&content, i as u64));
+            assert_eq!(outcome, ChunkOutcome::Verified { culprits: vec![] });
+        }
+
+        assert!(download.is_complete());
+        assert!(download.assemble().is_err());
+        assert_eq!(download.reject_manifest(), Some(peers[0]));
+        assert!(!download.is_complete());
+        assert_eq!(download.next_request(), Some((peers[1], None)));
+    }
+
+    #[test]
+    fn manifest_round_trips_with_flags() {
+        let content = content();
+        for encrypted in [false, true] {
+            let manifest = Manifest::new(&content, encrypted);
+            let bytes = manifest.encode();
+            assert_eq!(bytes.len(), manifest.encoded_len());
+            assert_eq!(bytes[8], encrypted as u8);
+            assert_eq!(Manifest::decode(&bytes), Some(manifest));
+        }
+
+        let mut bytes = Manifest::new(&content, false).encode();
+        bytes[8] = FLAG_ENCRYPTED | 2;
+        assert_eq!(Manifest::decode(&bytes), None);
+        bytes[8] = 0;
+        // 长度不完整的清单
+        assert_eq!(Manifest::decode(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(Manifest::decode(&bytes[..40]), None);
+    }
+
+    #[test]
+    fn verify_chunk_checks_length_and_digest() {
+        let content = content();
+        let manifest = Manifest::new(&content, false);
+        assert!(manifest.verify_chunk(0, &chunk(&content, 0)));
+        // 最后一个分块较短，补齐到完整长度后不能通过校验
+        let last = chunk(&content, 2);
+        assert_eq!(last.len(), 10);
+        assert!(manifest.verify_chunk(2, &last));
+        let mut padded = last.clone();
+        padded.resize(CHUNK_SIZE, 0);
+        assert!(!manifest.verify_chunk(2, &padded));
+
+        assert!(!manifest.verify_chunk(1, &chunk(&content, 0)));
+        assert!(!manifest.verify_chunk(3, &last));
+    }
+
+    #[test]
+    fn chunk_count_must_match_the_size() {
+        let content = content();
+        assert!(Manifest::new(&content, false).is_consistent());
+        assert!(Manifest::new(&[], false).is_consistent());
+
+        let mut manifest = Manifest::new(&content, false);
+        manifest.chunk_digests.pop();
+        assert!(!manifest.is_consistent());
+        let mut manifest = Manifest::new(&content, false);
+        manifest.size = 2 * CHUNK_SIZE as u64;
+        assert!(!manifest.is_consistent());
+    }
+
+    #[test]
+    fn failed_requests_are_requeued() {
+        let content = content();
+        let peers = [PeerId::random(), PeerId::random()];
+        let mut download = Download::new("file".to_string(), None, peers.to_vec());
+
+        // 获取清单失败后向下一个提供者请求
+        assert_eq!(download.next_request(), Some((peers[0], None)));
+        assert_eq!(download.next_request(), None);
+        download.on_failure(peers[0], None, "failed".to_string());
+        assert_eq!(download.next_request(), Some((peers[1], None)));
+
+        download.set_manifest(peers[1], Manifest::new(&content, false));
+        for i in 0..3 {
+            assert_eq!(download.next_request(), Some((peers[1], Some(i))));
+        }
+        download.on_interrupted(peers[1], Some(1));
+        assert_eq!(download.next_request(), Some((peers[1], Some(1))));
+        assert_eq!(download.next_request(), None);
+    }
+
+    #[test]
+    fn download_stalls_when_no_provider_is_left() {
+        let content = content();
+        let peer = PeerId::random();
+        let mut download = Download::new("file".to_string(), None, vec![peer]);
+        assert_eq!(download.next_request(), Some((peer, None)));
+        download.set_manifest(peer, Manifest::new(&content, false));
+        assert_eq!(download.next_request(), Some((peer, Some(0))));
+        assert_eq!(download.next_request(), Some((peer, Some(1))));
+
+        // 放弃提供者后仍需等待进行中的请求结束
+        download.on_failure(peer, Some(0), "timeout".to_string());
+        assert!(!download.is_stalled());
+        download.on_failure(peer, Some(1), "timeout".to_string());
+        assert!(download.is_stalled());
+        assert_eq!(download.last_error(), "timeout");
+        assert_eq!(download.next_request(), None);
+    }
+}
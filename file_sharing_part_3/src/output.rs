@@ -0,0 +1,500 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use file_sharing::Event;
+use libp2p::autonat::NatStatus;
+use serde::{Deserialize, Serialize};
+
+// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    // 便于阅读的文本
+    Text,
+    // 每行一个JSON对象
+    Json,
+}
+
+// 命令行输出的一条记录，JSON格式下使用type字段区分记录类型
+// 记录输出后即被丢弃，Config记录较大不影响性能
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    ListenAddress {
+        address: String,
+    },
+    PeerConnected {
+        peer: String,
+    },
+    PeerDisconnected {
+        peer: String,
+        reason: String,
+    },
+    PeerBanned {
+        peer: String,
+        score: f64,
+    },
+    // 按节点ID连接成功，address为连接使用的地址
+    Connected {
+        peer: String,
+        address: String,
+    },
+    Reachability {
+        // public、private或unknown
+        status: String,
+        address: Option<String>,
+    },
+    Providers {
+        file_name: String,
+        providers: Vec<String>,
+    },
+    TransferStarted {
+        transfer: u64,
+        file_name: String,
+        size: u64,
+        chunks: usize,
+    },
+    BytesTransferred {
+        transfer: u64,
+        peer: String,
+        bytes: usize,
+    },
+    ChunkVerified {
+        transfer: u64,
+        peer: String,
+        chunk: u64,
+    },
+    TransferCompleted {
+        transfer: u64,
+        file_name: String,
+        size: u64,
+        relayed: bool,
+    },
+    TransferFailed {
+        transfer: u64,
+        file_name: String,
+        error: String,
+    },
+    Sharing {
+        file_name: String,
+        size: u64,
+        sha256: String,
+    },
+    Serving {
+        file_name: String,
+        peer: String,
+    },
+    Watching {
+        path: String,
+    },
+    Withdrawn {
+        file_name: String,
+    },
+    Gateway {
+        address: String,
+    },
+    MetricsEndpoint {
+        address: String,
+    },
+    StoppedProviding {
+        files: usize,
+    },
+    FileContent {
+        file_name: String,
+        size: usize,
+        content: String,
+    },
+    Saved {
+        file_name: String,
+        path: String,
+        size: usize,
+    },
+    Summary {
+        succeeded: Vec<String>,
+        failed: Vec<FailedFile>,
+    },
+    Token {
+        file_name: String,
+        peer: String,
+        token: String,
+    },
+    Published {
+        target: String,
+        sequence: u64,
+    },
+    Resolved {
+        publisher: String,
+        target: String,
+    },
+    Config {
+        peer_id: String,
+        listen_addresses: Vec<String>,
+        bootstrap_peers: Vec<String>,
+        relay_server: bool,
+        relays: Vec<String>,
+        sticky_peers: Vec<String>,
+        // 预共享密钥的指纹
+        swarm_key: Option<String>,
+        upload_rate: Option<u64>,
+        peer_upload_rate: Option<u64>,
+        max_concurrent_uploads: usize,
+        max_connections: Option<u32>,
+        max_connections_per_peer: Option<u32>,
+        max_pending_incoming: Option<u32>,
+        max_pending_outgoing: Option<u32>,
+        // 秒
+        idle_timeout: u64,
+        reputation_file: Option<String>,
+        ban_threshold: f64,
+        address_book: Option<String>,
+        gateway: Option<String>,
+        share_roots: Vec<String>,
+        metrics: Option<String>,
+        log_level: Option<String>,
+        log_file: Option<String>,
+        shares: Vec<SharedFileConfig>,
+    },
+    EventsMissed {
+        count: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct SharedFileConfig {
+    pub file_name: String,
+    pub path: String,
+    pub policy: String,
+    pub recipients: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedFile {
+    pub file_name: String,
+    pub error: String,
+}
+
+impl From<&Event> for Record {
+    fn from(event: &Event) -> Self {
+        match event {
+            Event::ListeningOn { address } => Record::ListenAddress {
+                address: address.to_string(),
+            },
+            Event::PeerConnected { peer } => Record::PeerConnected {
+                peer: peer.to_string(),
+            },
+            Event::PeerDisconnected { peer, reason } => Record::PeerDisconnected {
+                peer: peer.to_string(),
+                reason: reason.clone(),
+            },
+            Event::PeerBanned { peer, score } => Record::PeerBanned {
+                peer: peer.to_string(),
+                score: *score,
+            },
+            Event::ReachabilityChanged { status } => {
+                let (status, address) = match status {
+                    NatStatus::Public(address) => ("public", Some(address.to_string())),
+                    NatStatus::Private => ("private", None),
+                    NatStatus::Unknown => ("unknown", None),
+                };
+                Record::Reachability {
+                    status: status.to_string(),
+                    address,
+                }
+            }
+            Event::ProvidersFound {
+                file_name,
+                providers,
+            } => Record::Providers {
+                file_name: file_name.clone(),
+                providers: providers.iter().map(ToString::to_string).collect(),
+            },
+            Event::TransferStarted {
+                transfer,
+                file_name,
+                size,
+                chunks,
+            } => Record::TransferStarted {
+                transfer: *transfer,
+                file_name: file_name.clone(),
+                size: *size,
+                chunks: *chunks,
+            },
+            Event::BytesTransferred {
+                transfer,
+                peer,
+                bytes,
+            } => Record::BytesTransferred {
+                transfer: *transfer,
+                peer: peer.to_string(),
+                bytes: *bytes,
+            },
+            Event::ChunkVerified {
+                transfer,
+                peer,
+                chunk,
+            } => Record::ChunkVerified {
+                transfer: *transfer,
+                peer: peer.to_string(),
+                chunk: *chunk,
+            },
+            Event::TransferCompleted {
+                transfer,
+                file_name,
+                size,
+                relayed,
+            } => Record::TransferCompleted {
+                transfer: *transfer,
+                file_name: file_name.clone(),
+                size: *size,
+                relayed: *relayed,
+            },
+            Event::TransferFailed {
+                transfer,
+                file_name,
+                error,
+            } => Record::TransferFailed {
+                transfer: *transfer,
+                file_name: file_name.clone(),
+                error: error.clone(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::ListenAddress { address } => {
+                write!(f, "Local node is listening on {}", address)
+            }
+            Record::PeerConnected { peer } => write!(f, "Connected to {}", peer),
+            Record::PeerDisconnected { peer, reason } => {
+                write!(f, "Disconnected from {} ({})", peer, reason)
+            }
+            Record::PeerBanned { peer, score } => {
+                write!(f, "Banned {} (score {:.1})", peer, score)
+            }
+            Record::Connected { peer, address } => {
+                write!(f, "Connected to {} at {}", peer, address)
+            }
+            Record::Reachability { status, address } => match address {
+                Some(address) => write!(f, "Reachability: {} at {}", status, address),
+                None => write!(f, "Reachability: {}", status),
+            },
+            Record::Providers {
+                file_name,
+                providers,
+            } => write!(f, "Found {} providers for {}", providers.len(), file_name),
+            Record::TransferStarted {
+                file_name,
+                size,
+                chunks,
+                ..
+            } => write!(f, "Downloading {} ({} bytes, {} chunks)", file_name, size, chunks),
+            Record::BytesTransferred { peer, bytes, .. } => {
+                write!(f, "Received {} bytes from {}", bytes, peer)
+            }
+            Record::ChunkVerified { peer, chunk, .. } => {
+                write!(f, "Verified chunk {} from {}", chunk, peer)
+            }
+            Record::TransferCompleted {
+                file_name,
+                size,
+                relayed,
+                ..
+            } => write!(
+                f,
+                "Downloaded {} ({} bytes, {})",
+                file_name,
+                size,
+                if *relayed { "relayed" } else { "direct" }
+            ),
+            Record::TransferFailed {
+                file_name, error, ..
+            } => write!(f, "Failed to download {}: {}", file_name, error),
+            Record::Sharing {
+                file_name,
+                size,
+                sha256,
+            } => write!(
+                f,
+                "Sharing file {} ({} bytes, sha256 {})",
+                file_name, size, sha256
+            ),
+            Record::Serving { file_name, peer } => {
+                write!(f, "Serving file {} to {}", file_name, peer)
+            }
+            Record::Watching { path } => write!(f, "Watching {} for files to share", path),
+            Record::Withdrawn { file_name } => write!(f, "Stopped sharing deleted file {}", file_name),
+            Record::Gateway { address } => write!(f, "HTTP gateway listening on {}", address),
+            Record::MetricsEndpoint { address } => write!(f, "Metrics available at {}", address),
+            Record::StoppedProviding { .. } => write!(f, "Stopped providing files."),
+            Record::FileContent {
+                file_name, content, ..
+            } => write!(f, "Content of file {}: {}", file_name, content),
+            Record::Saved {
+                file_name,
+                path,
+                size,
+            } => write!(f, "Saved {} to {} ({} bytes)", file_name, path, size),
+            Record::Summary { succeeded, failed } => {
+                write!(
+                    f,
+                    "Got {} of {} files",
+                    succeeded.len(),
+                    succeeded.len() + failed.len()
+                )?;
+                for file in failed {
+                    write!(f, "\nFailed to get {}: {}", file.file_name, file.error)?;
+                }
+                Ok(())
+            }
+            Record::Token {
+                file_name,
+                peer,
+                token,
+            } => write!(
+                f,
+                "Access token for {} granted to {}: {}",
+                file_name, peer, token
+            ),
+            Record::Published { target, sequence } => {
+                write!(f, "Published name -> {} (sequence {})", target, sequence)
+            }
+            Record::Resolved { publisher, target } => {
+                write!(f, "Name of {} resolves to {}", publisher, target)
+            }
+            Record::Config {
+                peer_id,
+                listen_addresses,
+                bootstrap_peers,
+                relay_server,
+                relays,
+                sticky_peers,
+                swarm_key,
+                upload_rate,
+                peer_upload_rate,
+                max_concurrent_uploads,
+                max_connections,
+                max_connections_per_peer,
+                max_pending_incoming,
+                max_pending_outgoing,
+                idle_timeout,
+                reputation_file,
+                ban_threshold,
+                address_book,
+                gateway,
+                share_roots,
+                metrics,
+                log_level,
+                log_file,
+                shares,
+            } => {
+                let rate = |rate: &Option<u64>| {
+                    rate.map_or("unlimited".to_string(), |r| format!("{} B/s", r))
+                };
+                writeln!(f, "Configuration is valid.")?;
+                write!(f, "Peer ID: {}", peer_id)?;
+                for addr in listen_addresses {
+                    write!(f, "\nListen address: {}", addr)?;
+                }
+                for addr in bootstrap_peers {
+                    write!(f, "\nBootstrap peer: {}", addr)?;
+                }
+                if *relay_server {
+                    write!(f, "\nRelay server: enabled")?;
+                }
+                for addr in relays {
+                    write!(f, "\nRelay: {}", addr)?;
+                }
+                for addr in sticky_peers {
+                    write!(f, "\nSticky peer: {}", addr)?;
+                }
+                if let Some(fingerprint) = swarm_key {
+                    write!(f, "\nPrivate network: swarm key {}", fingerprint)?;
+                }
+                write!(
+                    f,
+                    "\nUpload limits: {} global, {} per peer, {} concurrent",
+                    rate(upload_rate),
+                    rate(peer_upload_rate),
+                    max_concurrent_uploads
+                )?;
+                let limit = |limit: &Option<u32>| limit.map_or("unlimited".to_string(), |l| l.to_string());
+                write!(
+                    f,
+                    "\nConnection limits: {} total, {} per peer, {} pending incoming, {} pending outgoing, idle timeout {}s",
+                    limit(max_connections),
+                    limit(max_connections_per_peer),
+                    limit(max_pending_incoming),
+                    limit(max_pending_outgoing),
+                    idle_timeout
+                )?;
+                write!(
+                    f,
+                    "\nPeer scores: {}, ban below {}",
+                    reputation_file.as_deref().unwrap_or("not saved"),
+                    ban_threshold
+                )?;
+                if let Some(path) = address_book {
+                    write!(f, "\nAddress book: {}", path)?;
+                }
+                if let Some(gateway) = gateway {
+                    write!(f, "\nHTTP gateway: {}", gateway)?;
+                    for root in share_roots {
+                        write!(f, "\nGateway share root: {}", root)?;
+                    }
+                }
+                if let Some(metrics) = metrics {
+                    write!(f, "\nMetrics endpoint: {}", metrics)?;
+                }
+                if let Some(level) = log_level {
+                    write!(f, "\nLog level: {}", level)?;
+                }
+                if let Some(path) = log_file {
+                    write!(f, "\nLog file: {}", path)?;
+                }
+                for share in shares {
+                    write!(
+                        f,
+                        "\nShared file: {} from {} ({}, {} recipients)",
+                        share.file_name, share.path, share.policy, share.recipients
+                    )?;
+                }
+                Ok(())
+            }
+            Record::EventsMissed { count } => write!(f, "Missed {} events", count),
+            Record::Error { message } => write!(f, "Error: {}", message),
+        }
+    }
+}
+
+// 按输出格式输出记录
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    format: OutputFormat,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Output { format }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    pub fn print(&self, record: Record) {
+        match self.format {
+            OutputFormat::Text => println!("{}", record),
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string(&record).expect("Record to serialize.")
+            ),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::Instant,
+};
+
+use libp2p::PeerId;
+
+use file_sharing::Event;
+
+// 进度条宽度
+const BAR_WIDTH: usize = 30;
+
+// 根据网络事件在终端显示下载进度及每个提供者的吞吐量，同时下载多个文件时显示总进度
+#[derive(Default)]
+pub struct Progress {
+    size: u64,
+    received: u64,
+    chunks: usize,
+    verified: usize,
+    // 已开始和已结束的下载数量
+    files: usize,
+    finished: usize,
+    // 开始下载分块的时间
+    started: Option<Instant>,
+    // 每个提供者已传输的字节数
+    peers: HashMap<PeerId, u64>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match event {
+            // 监听地址和可达性由调用者输出
+            Event::ListeningOn { .. } | Event::ReachabilityChanged { .. } => {}
+            Event::PeerConnected { peer } => self.print_line(&format!("Connected to {}", peer)),
+            Event::PeerDisconnected { peer, reason } => {
+                self.print_line(&format!("Disconnected from {} ({})", peer, reason))
+            }
+            Event::PeerBanned { peer, score } => {
+                self.print_line(&format!("Banned {} (score {:.1})", peer, score))
+            }
+            Event::ProvidersFound {
+                file_name,
+                providers,
+            } => self.print_line(&format!(
+                "Found {} providers for {}",
+                providers.len(),
+                file_name
+            )),
+            Event::TransferStarted { size, chunks, .. } => {
+                self.size += *size;
+                self.chunks += *chunks;
+                self.files += 1;
+                self.started.get_or_insert_with(Instant::now);
+                self.render();
+            }
+            Event::BytesTransferred { peer, bytes, .. } => {
+                self.received += *bytes as u64;
+                *self.peers.entry(*peer).or_default() += *bytes as u64;
+                self.render();
+            }
+            Event::ChunkVerified { .. } => {
+                self.verified += 1;
+                self.render();
+            }
+            Event::TransferCompleted {
+                file_name,
+                size,
+                relayed,
+                ..
+            } => {
+                self.finished += 1;
+                let line = format!(
+                    "Downloaded {} ({}, {})",
+                    file_name,
+                    format_bytes(*size),
+                    if *relayed { "relayed" } else { "direct" }
+                );
+                if self.finished < self.files {
+                    self.print_line(&line);
+                } else {
+                    self.render();
+                    eprintln!("\n{}", line);
+                }
+            }
+            Event::TransferFailed {
+                file_name, error, ..
+            } => {
+                self.finished += 1;
+                let line = format!("Failed to download {}: {}", file_name, error);
+                if self.finished < self.files {
+                    self.print_line(&line);
+                } else {
+                    eprintln!("\r\x1b[K{}", line);
+                }
+            }
+        }
+    }
+
+    // 订阅者落后，丢失了部分事件
+    pub fn lagged(&self, missed: u64) {
+        self.print_line(&format!("Progress display missed {} events", missed));
+    }
+
+    // 在进度条上方输出一行信息
+    fn print_line(&self, line: &str) {
+        eprint!("\r\x1b[K{}\n", line);
+        if self.chunks > 0 {
+            self.render();
+        }
+    }
+
+    fn render(&self) {
+        let ratio = if self.size == 0 {
+            1.0
+        } else {
+            (self.received as f64 / self.size as f64).min(1.0)
+        };
+        let filled = (ratio * BAR_WIDTH as f64) as usize;
+        let mut line = format!(
+            "[{}{}] {:>3}% {}/{} {}/{} chunks",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            (ratio * 100.0) as u32,
+            format_bytes(self.received),
+            format_bytes(self.size),
+            self.verified,
+            self.chunks,
+        );
+        if self.files > 1 {
+            line.push_str(&format!(" {}/{} files", self.finished, self.files));
+        }
+        let elapsed = self
+            .started
+            .map_or(0.0, |started| started.elapsed().as_secs_f64())
+            .max(0.001);
+        for (peer, bytes) in &self.peers {
+            let peer = peer.to_base58();
+            line.push_str(&format!(
+                " | ..{} {}/s",
+                &peer[peer.len() - 6..],
+                format_bytes((*bytes as f64 / elapsed) as u64)
+            ));
+        }
+        eprint!("\r\x1b[K{}", line);
+        let _ = io::stderr().flush();
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    error::Error,
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+use sha2::{Digest, Sha256};
+
+// 下载文件的保存位置，先写入同一目录下的临时文件，校验并同步到磁盘后再重命名
+pub struct Destination {
+    path: PathBuf,
+    overwrite: bool,
+}
+
+impl Destination {
+    // 指定的路径为已有目录时，保存为目录中的同名文件
+    pub fn new(output: &Path, file_name: &str, overwrite: bool) -> Result<Self, String> {
+        let path = if output.is_dir() {
+            let name = Path::new(file_name)
+                .file_name()
+                .ok_or_else(|| format!("Cannot derive a file name from {:?}.", file_name))?;
+            output.join(name)
+        } else {
+            output.to_path_buf()
+        };
+        let destination = Destination { path, overwrite };
+        // 下载前检查，避免下载完成后才发现无法保存
+        destination.check_overwrite()?;
+        Ok(destination)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn check_overwrite(&self) -> Result<(), String> {
+        if !self.overwrite && fs::symlink_metadata(&self.path).is_ok() {
+            return Err(format!(
+                "{} already exists, pass --force to overwrite it.",
+                self.path.display()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let available = available_space(dir)
+            .map_err(|e| format!("Failed to check free space in {}: {}", dir.display(), e))?;
+        if available < content.len() as u64 {
+            return Err(format!(
+                "Not enough free space in {}: {} bytes needed, {} bytes available.",
+                dir.display(),
+                content.len(),
+                available
+            )
+            .into());
+        }
+
+        let mut temp = TempFile::create(dir, &self.path)?;
+        temp.file.write_all(content)?;
+        temp.file.sync_all()?;
+
+        // 读回临时文件，确认写入的内容完整
+        if Sha256::digest(fs::read(&temp.path)?) != Sha256::digest(content) {
+            return Err(format!("Verification of {} failed.", temp.path.display()).into());
+        }
+
+        temp.persist(&self.path, self.overwrite).map_err(|e| match e.kind() {
+            // 检查之后目标文件才被创建
+            io::ErrorKind::AlreadyExists => self.check_overwrite().unwrap_err(),
+            _ => format!("Failed to save {}: {}", self.path.display(), e),
+        })?;
+        // 同步目录，确保重命名已写入磁盘
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+// 未成功重命名时自动删除的临时文件
+struct TempFile {
+    path: PathBuf,
+    file: File,
+    persisted: bool,
+}
+
+impl TempFile {
+    fn create(dir: &Path, target: &Path) -> io::Result<Self> {
+        let name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let path = dir.join(format!(".{}.{}.part", name, process::id()));
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok(TempFile {
+            path,
+            file,
+            persisted: false,
+        })
+    }
+
+    // 不允许覆盖时使用硬链接，目标已存在时失败，不会替换期间创建的文件
+    fn persist(&mut self, target: &Path, overwrite: bool) -> io::Result<()> {
+        if overwrite {
+            fs::rename(&self.path, target)?;
+            self.persisted = true;
+        } else {
+            fs::hard_link(&self.path, target)?;
+            // 临时文件由Drop删除
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// 非特权用户可用的磁盘空间
+fn available_space(dir: &Path) -> io::Result<u64> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // 字段类型因平台而异
+    #[allow(clippy::unnecessary_cast)]
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+    Ok(available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用单独的临时目录
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("save-test-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        // 目录中的文件名称
+        fn entries(&self) -> Vec<String> {
+            let mut names: Vec<_> = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn saves_into_a_directory() {
+        let dir = TempDir::new("directory");
+        let destination = Destination::new(&dir.0, "docs/file.txt", false).unwrap();
+        destination.write(b"content").unwrap();
+        assert_eq!(fs::read(dir.0.join("file.txt")).unwrap(), b"content");
+        assert_eq!(dir.entries(), ["file.txt"]);
+        assert!(Destination::new(&dir.0, "file.txt", false).is_err());
+    }
+
+    #[test]
+    fn target_created_after_the_check_is_not_replaced() {
+        let dir = TempDir::new("race");
+        let path = dir.0.join("file");
+        let destination = Destination::new(&path, "file", false).unwrap();
+        fs::write(&path, b"existing").unwrap();
+
+        let error = destination.write(b"downloaded").unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert_eq!(fs::read(&path).unwrap(), b"existing");
+        assert_eq!(dir.entries(), ["file"]);
+    }
+
+    #[test]
+    fn force_replaces_the_target() {
+        let dir = TempDir::new("force");
+        let path = dir.0.join("file");
+        fs::write(&path, b"existing").unwrap();
+        Destination::new(&path, "file", true).unwrap().write(b"downloaded").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"downloaded");
+        assert_eq!(dir.entries(), ["file"]);
+    }
+}
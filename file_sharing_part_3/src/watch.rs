@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use file_sharing::Client;
+use futures::StreamExt;
+use inotify::{EventMask, EventStream, Inotify, WatchDescriptor, WatchMask, Watches};
+use sha2::{Digest, Sha256};
+use tokio::time::{self, Instant};
+use tracing::warn;
+
+use crate::{
+    config::{Share, WatchDir},
+    library::Library,
+    output::{Output, Record},
+};
+
+// 文件在这段时间内没有变化才认为已写完
+const DEBOUNCE: Duration = Duration::from_secs(1);
+// 检查待处理文件的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// 监视目录中文件的变化，共享新增和修改的文件，撤回已删除的文件
+pub struct Watcher {
+    dir: WatchDir,
+    library: Library,
+    output: Output,
+    events: EventStream<Vec<u8>>,
+    watches: Watches,
+    // 被监视的目录，子目录也单独监视
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    root: Option<WatchDescriptor>,
+    // 等待处理的路径及其处理时间，期间再次变化时推迟处理
+    pending: HashMap<PathBuf, Instant>,
+    // 已共享文件的内容摘要，内容未变化时不再重新发布
+    digests: HashMap<PathBuf, [u8; 32]>,
+}
+
+impl Watcher {
+    pub fn new(dir: WatchDir, library: Library, output: Output) -> io::Result<Self> {
+        let inotify = Inotify::init()?;
+        let watches = inotify.watches();
+        let events = inotify.into_event_stream(vec![0; 4096])?;
+        Ok(Watcher {
+            dir,
+            library,
+            output,
+            events,
+            watches,
+            dirs: HashMap::new(),
+            root: None,
+            pending: HashMap::new(),
+            digests: HashMap::new(),
+        })
+    }
+
+    // 共享目录中已有的文件，之后持续处理文件变化，直到被监视的目录被删除
+    pub async fn run(mut self, mut client: Client) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let root = self.dir.path.clone();
+        self.scan(&root, Instant::now())
+            .map_err(|e| format!("Failed to watch {}: {}", root.display(), e))?;
+        self.output.print(Record::Watching {
+            path: root.display().to_string(),
+        });
+
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                event = self.events.next() => {
+                    let event = match event {
+                        Some(event) => event?,
+                        None => return Err("inotify event stream ended.".into()),
+                    };
+                    if !self.handle_event(event.wd, event.mask, event.name) {
+                        // 目录已被删除，撤回其中的所有文件
+                        let paths: Vec<_> = self.digests.keys().cloned().collect();
+                        for path in paths {
+                            self.withdraw(&mut client, &path).await;
+                        }
+                        return Err(format!("Watched directory {} was removed.", root.display()).into());
+                    }
+                }
+                _ = interval.tick() => self.flush(&mut client).await,
+            }
+        }
+    }
+
+    // 记录发生变化的路径，被监视的目录被删除时返回false
+    fn handle_event(&mut self, wd: WatchDescriptor, mask: EventMask, name: Option<OsString>) -> bool {
+        if mask.contains(EventMask::IGNORED) {
+            self.dirs.remove(&wd);
+            return self.root.as_ref() != Some(&wd);
+        }
+        // 事件队列溢出时丢失了部分事件，重新检查所有文件
+        if mask.contains(EventMask::Q_OVERFLOW) {
+            let deadline = Instant::now() + DEBOUNCE;
+            for path in self.digests.keys() {
+                self.pending.insert(path.clone(), deadline);
+            }
+            let root = self.dir.path.clone();
+            if let Err(e) = self.scan(&root, deadline) {
+                warn!(path = %root.display(), error = %e, "Failed to rescan");
+            }
+            return true;
+        }
+        let (dir, name) = match (self.dirs.get(&wd), name) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => return true,
+        };
+        let path = dir.join(&name);
+        let deadline = Instant::now() + DEBOUNCE;
+
+        if mask.contains(EventMask::ISDIR) {
+            if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                // 移入的目录中已有文件，需要扫描
+                if let Err(e) = self.scan(&path, deadline) {
+                    warn!(path = %path.display(), error = %e, "Failed to watch");
+                }
+            } else if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+                // 移出的目录仍会产生事件，停止监视
+                let removed: Vec<_> = self
+                    .dirs
+                    .iter()
+                    .filter(|(_, dir)| dir.starts_with(&path))
+                    .map(|(wd, _)| wd.clone())
+                    .collect();
+                for wd in removed {
+                    self.dirs.remove(&wd);
+                    let _ = self.watches.remove(wd);
+                }
+                self.pending.insert(path, deadline);
+            }
+        } else if !is_temporary(&name) {
+            self.pending.insert(path, deadline);
+        }
+        true
+    }
+
+    // 监视目录及其子目录，其中的文件在deadline之后处理
+    fn scan(&mut self, dir: &Path, deadline: Instant) -> io::Result<()> {
+        let mask = WatchMask::CREATE
+            | WatchMask::MODIFY
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::MOVED_TO
+            | WatchMask::MOVED_FROM
+            | WatchMask::DELETE
+            | WatchMask::ONLYDIR;
+        let wd = self.watches.add(dir, mask)?;
+        if dir == self.dir.path {
+            self.root = Some(wd.clone());
+        }
+        self.dirs.insert(wd, dir.to_path_buf());
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                self.scan(&path, deadline)?;
+            } else if !is_temporary(&entry.file_name()) {
+                self.pending.insert(path, deadline);
+            }
+        }
+        Ok(())
+    }
+
+    // 处理已经停止变化的路径
+    async fn flush(&mut self, client: &mut Client) {
+        for path in self.ready(Instant::now()) {
+            match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => self.share(client, path).await,
+                Ok(metadata) if metadata.is_dir() => {}
+                // 文件或目录已不存在
+                _ => self.withdraw(client, &path).await,
+            }
+        }
+    }
+
+    // 取出到期的待处理路径，按路径排序
+    fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in &ready {
+            self.pending.remove(path);
+        }
+        ready
+    }
+
+    // 共享新文件，或重新发布内容已变化的文件，文件只读取一次
+    async fn share(&mut self, client: &mut Client, path: PathBuf) {
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Failed to read");
+                return;
+            }
+        };
+        let digest: [u8; 32] = Sha256::digest(&content).into();
+        if self.digests.get(&path) == Some(&digest) {
+            return;
+        }
+        let share = Share {
+            path: path.clone(),
+            name: self.file_name(&path),
+            policy: self.dir.policy.clone(),
+            recipients: self.dir.recipients.clone(),
+        };
+        match self.library.share_content(client, share, content, self.output).await {
+            Ok(_) => {
+                self.digests.insert(path, digest);
+            }
+            Err(e) => warn!(path = %path.display(), error = %e, "Failed to share"),
+        }
+    }
+
+    // 撤回该路径下所有已共享的文件
+    async fn withdraw(&mut self, client: &mut Client, path: &Path) {
+        let mut removed: Vec<_> = self
+            .digests
+            .keys()
+            .filter(|shared| shared.starts_with(path))
+            .cloned()
+            .collect();
+        removed.sort();
+        for shared in removed {
+            self.digests.remove(&shared);
+            let file_name = self.file_name(&shared);
+            self.library.remove(&file_name);
+            client.stop_providing(file_name.clone()).await;
+            self.output.print(Record::Withdrawn { file_name });
+        }
+    }
+
+    // 文件名称为相对于监视目录的路径，指定名称时加上前缀
+    fn file_name(&self, path: &Path) -> String {
+        let relative = path
+            .strip_prefix(&self.dir.path)
+            .expect("File to be inside the watched directory.")
+            .to_string_lossy()
+            .into_owned();
+        match &self.dir.prefix {
+            Some(prefix) => format!("{}/{}", prefix, relative),
+            None => relative,
+        }
+    }
+}
+
+// 隐藏文件以及编辑器和下载工具的临时文件
+fn is_temporary(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.')
+        || name.ends_with('~')
+        || [".part", ".tmp", ".swp"].iter().any(|ext| name.ends_with(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_sharing::network::access::AccessPolicy;
+
+    use crate::output::OutputFormat;
+
+    // 每个测试使用单独的临时目录
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("watch-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn watcher(root: &Path) -> Watcher {
+        let dir = WatchDir {
+            path: root.to_path_buf(),
+            prefix: None,
+            policy: AccessPolicy::Public,
+            recipients: Vec::new(),
+        };
+        let mut watcher = Watcher::new(dir, Library::new(), Output::new(OutputFormat::Text)).unwrap();
+        watcher.scan(root, Instant::now()).unwrap();
+        watcher
+    }
+
+    // 读取已产生的inotify事件并交给handle_event处理
+    async fn drain(watcher: &mut Watcher) {
+        while let Ok(Some(event)) = time::timeout(Duration::from_millis(200), watcher.events.next()).await {
+            let event = event.unwrap();
+            assert!(watcher.handle_event(event.wd, event.mask, event.name));
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_events_push_the_deadline_back() {
+        let dir = TempDir::new("debounce");
+        let mut watcher = watcher(&dir.0);
+        let root = watcher.root.clone().unwrap();
+        let path = dir.0.join("file");
+
+        watcher.handle_event(root.clone(), EventMask::MODIFY, Some("file".into()));
+        let first = watcher.pending[&path];
+        time::sleep(Duration::from_millis(10)).await;
+        watcher.handle_event(root, EventMask::MODIFY, Some("file".into()));
+        let second = watcher.pending[&path];
+        assert!(second > first);
+
+        // 第一次变化的处理时间已过，但文件仍在变化
+        assert!(watcher.ready(first).is_empty());
+        assert_eq!(watcher.ready(second), vec![path]);
+        assert!(watcher.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn existing_files_are_pending_after_scan() {
+        let dir = TempDir::new("scan");
+        fs::create_dir(dir.0.join("sub")).unwrap();
+        fs::write(dir.0.join("sub/a"), b"a").unwrap();
+        fs::write(dir.0.join("b"), b"b").unwrap();
+        fs::write(dir.0.join(".hidden"), b"c").unwrap();
+
+        let mut watcher = watcher(&dir.0);
+        assert_eq!(watcher.dirs.len(), 2);
+        let ready = watcher.ready(Instant::now() + DEBOUNCE);
+        assert_eq!(ready, vec![dir.0.join("b"), dir.0.join("sub/a")]);
+    }
+
+    #[tokio::test]
+    async fn renamed_file_marks_both_paths() {
+        let dir = TempDir::new("rename");
+        fs::write(dir.0.join("old"), b"content").unwrap();
+        let mut watcher = watcher(&dir.0);
+        watcher.pending.clear();
+
+        fs::rename(dir.0.join("old"), dir.0.join("new")).unwrap();
+        drain(&mut watcher).await;
+
+        // 旧路径在处理时已不存在，因而被撤回；新路径被共享
+        let ready = watcher.ready(Instant::now() + DEBOUNCE);
+        assert_eq!(ready, vec![dir.0.join("new"), dir.0.join("old")]);
+    }
+
+    #[tokio::test]
+    async fn moved_in_directory_is_scanned() {
+        let dir = TempDir::new("move-in");
+        let outside = TempDir::new("move-in-outside");
+        fs::create_dir(outside.0.join("sub")).unwrap();
+        fs::write(outside.0.join("sub/file"), b"content").unwrap();
+        let mut watcher = watcher(&dir.0);
+
+        fs::rename(outside.0.join("sub"), dir.0.join("sub")).unwrap();
+        drain(&mut watcher).await;
+
+        assert!(watcher.dirs.values().any(|path| path == &dir.0.join("sub")));
+        let ready = watcher.ready(Instant::now() + DEBOUNCE);
+        assert_eq!(ready, vec![dir.0.join("sub/file")]);
+    }
+
+    #[tokio::test]
+    async fn moved_out_directory_is_no_longer_watched() {
+        let dir = TempDir::new("move-out");
+        let outside = TempDir::new("move-out-outside");
+        fs::create_dir_all(dir.0.join("sub/nested")).unwrap();
+        let mut watcher = watcher(&dir.0);
+        assert_eq!(watcher.dirs.len(), 3);
+
+        fs::rename(dir.0.join("sub"), outside.0.join("sub")).unwrap();
+        drain(&mut watcher).await;
+        assert_eq!(watcher.dirs.len(), 1);
+        assert_eq!(watcher.ready(Instant::now() + DEBOUNCE), vec![dir.0.join("sub")]);
+
+        // 移出后的变化不再产生事件
+        fs::write(outside.0.join("sub/nested/file"), b"content").unwrap();
+        drain(&mut watcher).await;
+        assert!(watcher.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn removing_the_root_stops_the_watcher() {
+        let dir = TempDir::new("remove-root");
+        let mut watcher = watcher(&dir.0);
+        let root = watcher.root.clone().unwrap();
+        assert!(!watcher.handle_event(root, EventMask::IGNORED, None));
+    }
+
+    #[test]
+    fn temporary_files_are_ignored() {
+        for name in [".hidden", "file~", "video.part", "data.tmp", ".file.swp"] {
+            assert!(is_temporary(OsStr::new(name)), "{}", name);
+        }
+        for name in ["file", "notes.txt", "part", "tmp.dat"] {
+            assert!(!is_temporary(OsStr::new(name)), "{}", name);
+        }
+    }
+}
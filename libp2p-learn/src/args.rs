@@ -0,0 +1,19 @@
+use clap::Args;
+
+use crate::output::Output;
+
+// 各程序共用的输出和日志参数，通过 `#[clap(flatten)]` 加入各程序的参数
+#[derive(Debug, Args)]
+pub struct CommonArgs {
+    // 输出格式，json格式下每行输出一个JSON对象
+    #[clap(long, value_enum, default_value = "text")]
+    pub output: Output,
+
+    // 日志过滤规则，优先于RUST_LOG环境变量
+    #[clap(long)]
+    pub log_level: Option<String>,
+
+    // 每行输出一个JSON格式的日志
+    #[clap(long)]
+    pub log_json: bool,
+}
@@ -1,4 +1,7 @@
+use std::process;
+
 use anyhow::{Ok, Result};
+use clap::Parser;
 use futures::StreamExt;
 use libp2p::{
     core::upgrade,
@@ -10,6 +13,9 @@ use libp2p::{
     tcp::{GenTcpConfig, TokioTcpTransport},
     yamux, Multiaddr, NetworkBehaviour, PeerId, Transport,
 };
+use libp2p_learn::{args::CommonArgs, logging, output::Output};
+use tracing::debug;
+use serde_json::json;
 use tokio::io::{self, AsyncBufReadExt};
 
 // 自定义网络行为，组合floodsub和mDNS。
@@ -18,16 +24,20 @@ use tokio::io::{self, AsyncBufReadExt};
 struct MyBehaviour {
     floodsub: Floodsub,
     mdns: Mdns,
+    // 输出格式
+    #[behaviour(ignore)]
+    output: Output,
 }
 
 impl MyBehaviour {
     // 传入peerId，构建MyBehaviour
-    async fn new(id: PeerId) -> Result<Self> {
+    async fn new(id: PeerId, output: Output) -> Result<Self> {
         Ok(Self {
             // floodsub协议初始化
             floodsub: Floodsub::new(id),
             // mDNS协议初始化
             mdns: Mdns::new(Default::default()).await?,
+            output,
         })
     }
 }
@@ -38,10 +48,12 @@ impl NetworkBehaviourEventProcess<FloodsubEvent> for MyBehaviour {
     fn inject_event(&mut self, message: FloodsubEvent) {
         // 显示接收到的消息及来源
         if let FloodsubEvent::Message(message) = message {
-            println!(
-                "收到消息: '{:?}' 来自 {:?}",
-                String::from_utf8_lossy(&message.data),
-                message.source
+            let data = String::from_utf8_lossy(&message.data);
+            debug!(source = %message.source, topics = ?message.topics, bytes = message.data.len(), "Received message");
+            self.output.emit(
+                "message",
+                json!({ "source": message.source.to_string(), "data": data }),
+                format!("收到消息: '{:?}' 来自 {:?}", data, message.source),
             );
         }
     }
@@ -54,9 +66,14 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for MyBehaviour {
         match event {
             // 发现新节点时，将节点添加到传播消息的节点列表中。
             MdnsEvent::Discovered(list) => {
-                for (peer, _) in list {
+                for (peer, address) in list {
+                    debug!(%peer, %address, "Discovered peer through mDNS");
                     self.floodsub.add_node_to_partial_view(peer);
-                    println!("在网络中加入节点: {peer} ");
+                    self.output.emit(
+                        "peer_discovered",
+                        json!({ "peer": peer.to_string() }),
+                        format!("在网络中加入节点: {peer} "),
+                    );
                 }
             }
             // 当节点失效时，从传播消息的节点列表中删除一个节点。
@@ -64,7 +81,11 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for MyBehaviour {
                 for (peer, _) in list {
                     if !self.mdns.has_node(&peer) {
                         self.floodsub.remove_node_from_partial_view(&peer);
-                        println!("从网络中删除节点: {peer} ");
+                        self.output.emit(
+                            "peer_expired",
+                            json!({ "peer": peer.to_string() }),
+                            format!("从网络中删除节点: {peer} "),
+                        );
                     }
                 }
             }
@@ -72,14 +93,36 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for MyBehaviour {
     }
 }
 
+#[derive(Debug, Parser)]
+struct Opt {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    // 远程节点地址，省略时只等待其他节点连接
+    to_dial: Option<Multiaddr>,
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    let opt = Opt::parse();
+    let output = opt.common.output;
+    if let Err(e) = logging::init(&opt.common) {
+        output.error(e);
+        process::exit(2);
+    }
+    if let Err(e) = run(output, opt.to_dial).await {
+        output.error(e);
+        process::exit(1);
+    }
+}
+
+async fn run(output: Output, to_dial: Option<Multiaddr>) -> Result<()> {
     // 生成密钥对
     let id_keys = identity::Keypair::generate_ed25519();
 
     // 基于密钥对的公钥，生成节点唯一标识peerId
     let peer_id = PeerId::from(id_keys.public());
-    println!("节点ID: {peer_id}");
+    output.emit("local_peer", json!({ "peer_id": peer_id.to_string() }), format!("节点ID: {peer_id}"));
 
     // 创建noise密钥对
     let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&id_keys)?;
@@ -97,7 +140,7 @@ async fn main() -> Result<()> {
 
     // 创建Swarm来管理节点网络及事件。
     let mut swarm = {
-        let mut behaviour = MyBehaviour::new(peer_id).await?;
+        let mut behaviour = MyBehaviour::new(peer_id, output).await?;
 
         // 订阅floodsub topic
         behaviour.floodsub.subscribe(floodsub_topic.clone());
@@ -110,10 +153,9 @@ async fn main() -> Result<()> {
     };
 
     // 指定一个远程节点，进行手动链接。
-    if let Some(to_dial) = std::env::args().nth(1) {
-        let addr: Multiaddr = to_dial.parse()?;
-        swarm.dial(addr)?;
-        println!("链接远程节点: {to_dial}");
+    if let Some(to_dial) = to_dial {
+        swarm.dial(to_dial.clone())?;
+        output.emit("dialing", json!({ "address": to_dial.to_string() }), format!("链接远程节点: {to_dial}"));
     }
 
     // 从标准输入中读取消息
@@ -130,8 +172,9 @@ async fn main() -> Result<()> {
                 swarm.behaviour_mut().floodsub.publish(floodsub_topic.clone(), line.as_bytes());
             }
             event = swarm.select_next_some() => {
+                logging::swarm_event(&event);
                 if let SwarmEvent::NewListenAddr { address, .. } = event {
-                    println!("本地监听地址: {address}");
+                    output.emit("listen_address", json!({ "address": address.to_string() }), format!("本地监听地址: {address}"));
                 }
             }
         }
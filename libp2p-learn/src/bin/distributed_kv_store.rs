@@ -1,4 +1,7 @@
+use std::process;
+
 use anyhow::Result;
+use clap::Parser;
 use futures::StreamExt;
 use libp2p::{
     kad::{
@@ -9,6 +12,9 @@ use libp2p::{
     swarm::{NetworkBehaviourEventProcess, SwarmBuilder, SwarmEvent},
     NetworkBehaviour, identity, PeerId,
 };
+use libp2p_learn::{args::CommonArgs, logging, output::Output};
+use tracing::{debug, info, info_span};
+use serde_json::json;
 use tokio::io::{self, AsyncBufReadExt};
 
 // 自定义网络行为，组合Kademlia和mDNS.
@@ -17,11 +23,14 @@ use tokio::io::{self, AsyncBufReadExt};
 struct MyBehaviour {
     kademlia: Kademlia<MemoryStore>,
     mdns: Mdns,
+    // 输出格式
+    #[behaviour(ignore)]
+    output: Output,
 }
 
 impl MyBehaviour {
     // 传入peerId，构建MyBehaviour
-    async fn new(peer_id: PeerId) -> Result<Self> {
+    async fn new(peer_id: PeerId, output: Output) -> Result<Self> {
         let store = MemoryStore::new(peer_id);
         let kademlia = Kademlia::new(peer_id, store);
 
@@ -30,6 +39,7 @@ impl MyBehaviour {
             kademlia,
             // mDNS协议初始化
             mdns: Mdns::new(Default::default()).await?,
+            output,
         })
     }
 }
@@ -41,6 +51,7 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for MyBehaviour {
         // 发现新节点时，将节点加入到Kademlia网络中。
         if let MdnsEvent::Discovered(list) = event {
             for (peer_id, multiaddr) in list {
+                debug!(peer = %peer_id, address = %multiaddr, "Discovered peer through mDNS");
                 self.kademlia.add_address(&peer_id, multiaddr);
             }
         }
@@ -51,20 +62,29 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for MyBehaviour {
 impl NetworkBehaviourEventProcess<KademliaEvent> for MyBehaviour {
     // 当产生一个kademlia事件时，该方法被调用。
     fn inject_event(&mut self, message: KademliaEvent) {
-        if let KademliaEvent::OutboundQueryCompleted { result, .. } = message {
+        if let KademliaEvent::OutboundQueryCompleted { id, result, stats } = message {
+            // 查询结果的日志带有查询ID，可与发起查询时的日志对应
+            let _span = info_span!("query", query_id = ?id).entered();
+            info!(
+                duration = ?stats.duration(),
+                succeeded = stats.num_successes(),
+                failed = stats.num_failures(),
+                "Query completed"
+            );
             match result {
                 // 查询提供key的节点事件
                 QueryResult::GetProviders(Ok(ok)) => {
+                    let key = String::from_utf8_lossy(ok.key.as_ref());
                     for peer in ok.providers {
-                        println!(
-                            "节点 {:?} 提供了key {:?}",
-                            peer,
-                            std::str::from_utf8(ok.key.as_ref()).unwrap()
+                        self.output.emit(
+                            "provider",
+                            json!({ "key": key, "peer": peer.to_string() }),
+                            format!("节点 {:?} 提供了key {:?}", peer, key),
                         );
                     }
                 }
                 QueryResult::GetProviders(Err(err)) => {
-                    eprintln!("Failed to get providers: {:?}", err);
+                    self.output.error(format!("Failed to get providers: {:?}", err));
                 }
                 // 查询存储记录事件
                 QueryResult::GetRecord(Ok(ok)) => {
@@ -73,35 +93,41 @@ impl NetworkBehaviourEventProcess<KademliaEvent> for MyBehaviour {
                         ..
                     } in ok.records
                     {
-                        println!(
-                            "获取存储记录 {:?} {:?}",
-                            std::str::from_utf8(key.as_ref()).unwrap(),
-                            std::str::from_utf8(&value).unwrap(),
+                        let key = String::from_utf8_lossy(key.as_ref());
+                        let value = String::from_utf8_lossy(&value);
+                        self.output.emit(
+                            "record",
+                            json!({ "key": key, "value": value }),
+                            format!("获取存储记录 {:?} {:?}", key, value),
                         );
                     }
                 }
                 QueryResult::GetRecord(Err(err)) => {
-                    eprintln!("Failed to get record: {:?}", err);
+                    self.output.error(format!("Failed to get record: {:?}", err));
                 }
                 // 记录存储成功事件
                 QueryResult::PutRecord(Ok(PutRecordOk { key })) => {
-                    println!(
-                        "成功存储记录  {:?}",
-                        std::str::from_utf8(key.as_ref()).unwrap()
+                    let key = String::from_utf8_lossy(key.as_ref());
+                    self.output.emit(
+                        "record_stored",
+                        json!({ "key": key }),
+                        format!("成功存储记录  {:?}", key),
                     );
                 }
                 QueryResult::PutRecord(Err(err)) => {
-                    eprintln!("Failed to put record: {:?}", err);
+                    self.output.error(format!("Failed to put record: {:?}", err));
                 }
                 // 成功存储记录提供者事件
                 QueryResult::StartProviding(Ok(AddProviderOk { key })) => {
-                    println!(
-                        "成功存储记录提供者 {:?}",
-                        std::str::from_utf8(key.as_ref()).unwrap()
+                    let key = String::from_utf8_lossy(key.as_ref());
+                    self.output.emit(
+                        "provider_stored",
+                        json!({ "key": key }),
+                        format!("成功存储记录提供者 {:?}", key),
                     );
                 }
                 QueryResult::StartProviding(Err(err)) => {
-                    eprintln!("Failed to put provider record: {:?}", err);
+                    self.output.error(format!("Failed to put provider record: {:?}", err));
                 }
                 _ => {}
             }
@@ -109,21 +135,40 @@ impl NetworkBehaviourEventProcess<KademliaEvent> for MyBehaviour {
     }
 }
 
+#[derive(Debug, Parser)]
+struct Opt {
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    let opt = Opt::parse();
+    let output = opt.common.output;
+    if let Err(e) = logging::init(&opt.common) {
+        output.error(e);
+        process::exit(2);
+    }
+    if let Err(e) = run(output).await {
+        output.error(e);
+        process::exit(1);
+    }
+}
+
+async fn run(output: Output) -> Result<()> {
     // 生成密钥对
     let key_pair = identity::Keypair::generate_ed25519();
 
     // 基于密钥对的公钥，生成节点唯一标识peerId
     let peer_id = PeerId::from(key_pair.public());
-    println!("节点ID: {peer_id}");
+    output.emit("local_peer", json!({ "peer_id": peer_id.to_string() }), format!("节点ID: {peer_id}"));
 
     // 在Mplex协议上建立一个加密的，启用dns的TCP传输
     let transport = libp2p::development_transport(key_pair).await?;
 
     // 创建Swarm网络管理器，来管理节点网络及事件。
     let mut swarm = {
-        let behaviour = MyBehaviour::new(peer_id).await?;
+        let behaviour = MyBehaviour::new(peer_id, output).await?;
         
         SwarmBuilder::new(transport, behaviour, peer_id)
             .executor(Box::new(|fut| {
@@ -142,11 +187,12 @@ async fn main() -> Result<()> {
         tokio::select! {
             line = stdin.next_line() => {
                 let line = line?.expect("stdin closed");
-                handle_input_line(&mut swarm.behaviour_mut().kademlia, line);
+                handle_input_line(&mut swarm.behaviour_mut().kademlia, output, line);
             },
             event = swarm.select_next_some() => {
+                logging::swarm_event(&event);
                 if let SwarmEvent::NewListenAddr { address, .. } = event {
-                    println!("本地监听地址: {address}");
+                    output.emit("listen_address", json!({ "address": address.to_string() }), format!("本地监听地址: {address}"));
                 }
             }
         }
@@ -154,7 +200,7 @@ async fn main() -> Result<()> {
 }
 
 // 处理输入命令
-fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
+fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, output: Output, line: String) {
     let mut args = line.split(' ');
 
     match args.next() {
@@ -164,13 +210,14 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 match args.next() {
                     Some(key) => Key::new(&key),
                     None => {
-                        eprintln!("Expected key");
+                        output.error("Expected key");
                         return;
                     }
                 }
             };
             // 获取v记录
-            kademlia.get_record(key, Quorum::One);
+            let query_id = kademlia.get_record(key.clone(), Quorum::One);
+            info!(?query_id, key = %String::from_utf8_lossy(key.as_ref()), "Started GET query");
         }
         // 处理 GET_PROVIDERS 命令，获取存储kv记录的节点PeerId
         Some("GET_PROVIDERS") => {
@@ -178,13 +225,14 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 match args.next() {
                     Some(key) => Key::new(&key),
                     None => {
-                        eprintln!("Expected key");
+                        output.error("Expected key");
                         return;
                     }
                 }
             };
             // 获取存储kv记录的节点
-            kademlia.get_providers(key);
+            let query_id = kademlia.get_providers(key.clone());
+            info!(?query_id, key = %String::from_utf8_lossy(key.as_ref()), "Started GET_PROVIDERS query");
         }
         // 处理 PUT 命令，存储kv记录
         Some("PUT") => {
@@ -192,7 +240,7 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 match args.next() {
                     Some(key) => Key::new(&key),
                     None => {
-                        eprintln!("Expected key");
+                        output.error("Expected key");
                         return;
                     }
                 }
@@ -202,11 +250,12 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 match args.next() {
                     Some(value) => value.as_bytes().to_vec(),
                     None => {
-                        eprintln!("Expected value");
+                        output.error("Expected value");
                         return;
                     }
                 }
             };
+            let name = String::from_utf8_lossy(key.as_ref()).into_owned();
             let record = Record {
                 key,
                 value,
@@ -214,9 +263,10 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 expires: None,
             };
             // 存储kv记录
-            kademlia
+            let query_id = kademlia
                 .put_record(record, Quorum::One)
                 .expect("Failed to store record locally.");
+            info!(?query_id, key = %name, "Started PUT query");
         }
         // 处理 PUT_PROVIDER 命令，保存kv记录的提供者(节点)
         Some("PUT_PROVIDER") => {
@@ -224,18 +274,19 @@ fn handle_input_line(kademlia: &mut Kademlia<MemoryStore>, line: String) {
                 match args.next() {
                     Some(key) => Key::new(&key),
                     None => {
-                        eprintln!("Expected key");
+                        output.error("Expected key");
                         return;
                     }
                 }
             };
 
-            kademlia
-                .start_providing(key)
+            let query_id = kademlia
+                .start_providing(key.clone())
                 .expect("Failed to start providing key");
+            info!(?query_id, key = %String::from_utf8_lossy(key.as_ref()), "Started PUT_PROVIDER query");
         }
         _ => {
-            eprintln!("expected GET, GET_PROVIDERS, PUT or PUT_PROVIDER");
+            output.error("expected GET, GET_PROVIDERS, PUT or PUT_PROVIDER");
         }
     }
 }
\ No newline at end of file
@@ -1,21 +1,46 @@
-use std::error::Error;
+use std::{error::Error, process};
 
+use clap::Parser;
 use libp2p::{
     futures::StreamExt,
     identity,
-    ping::{Ping, PingConfig},
+    ping::{Ping, PingConfig, PingEvent, PingSuccess},
     swarm::SwarmEvent,
     Multiaddr, PeerId, Swarm,
 };
+use libp2p_learn::{args::CommonArgs, logging, output::Output};
+use serde_json::json;
+
+#[derive(Debug, Parser)]
+struct Opt {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    // 远程节点地址，省略时只等待其他节点连接
+    remote_peer: Option<Multiaddr>,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    let opt = Opt::parse();
+    let output = opt.common.output;
+    if let Err(e) = logging::init(&opt.common) {
+        output.error(e);
+        process::exit(2);
+    }
+    if let Err(e) = run(output, opt.remote_peer).await {
+        output.error(e);
+        process::exit(1);
+    }
+}
+
+async fn run(output: Output, remote_peer: Option<Multiaddr>) -> Result<(), Box<dyn Error>> {
     // 生成密钥对
     let key_pair = identity::Keypair::generate_ed25519();
 
     // 基于密钥对的公钥，生成节点唯一标识peerId
     let peer_id = PeerId::from(key_pair.public());
-    println!("节点ID: {peer_id}");
+    output.emit("local_peer", json!({ "peer_id": peer_id.to_string() }), format!("节点ID: {peer_id}"));
 
     // 声明Ping网络行为
     let behaviour = Ping::new(PingConfig::new().with_keep_alive(true));
@@ -30,22 +55,41 @@ async fn main() -> Result<(), Box<dyn Error>> {
     swarm.listen_on("/ip4/127.0.0.1/tcp/0".parse()?)?;
 
     // 从命令行参数获取远程节点地址，进行链接。
-    if let Some(remote_peer) = std::env::args().nth(1) {
-        let remote_peer_multiaddr: Multiaddr = remote_peer.parse()?;
-        swarm.dial(remote_peer_multiaddr)?;
-        println!("链接远程节点: {remote_peer}");
+    if let Some(remote_peer) = remote_peer {
+        swarm.dial(remote_peer.clone())?;
+        output.emit("dialing", json!({ "address": remote_peer.to_string() }), format!("链接远程节点: {remote_peer}"));
     }
 
     loop {
         // 匹配网络事件
-        match swarm.select_next_some().await {
+        let event = swarm.select_next_some().await;
+        logging::swarm_event(&event);
+        match event {
             // 监听事件
             SwarmEvent::NewListenAddr { address, .. } => {
-                println!("本地监听地址: {address}");
+                output.emit("listen_address", json!({ "address": address.to_string() }), format!("本地监听地址: {address}"));
             }
             // 网络行为事件
-            SwarmEvent::Behaviour(event) => println!("{:?}", event),
+            SwarmEvent::Behaviour(event) => print_ping(output, event),
             _ => {}
         }
     }
 }
+
+// 输出Ping结果
+fn print_ping(output: Output, event: PingEvent) {
+    let peer = event.peer.to_string();
+    match &event.result {
+        Ok(PingSuccess::Ping { rtt }) => output.emit(
+            "ping",
+            json!({ "peer": peer, "rtt_ms": rtt.as_secs_f64() * 1000.0 }),
+            format!("{:?}", event),
+        ),
+        Ok(PingSuccess::Pong) => output.emit("pong", json!({ "peer": peer }), format!("{:?}", event)),
+        Err(failure) => output.emit(
+            "ping_failure",
+            json!({ "peer": peer, "error": failure.to_string() }),
+            format!("{:?}", event),
+        ),
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fmt::Debug, io};
+
+use libp2p::swarm::SwarmEvent;
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::args::CommonArgs;
+
+// 未指定日志级别且未设置RUST_LOG时只输出警告
+const DEFAULT_FILTER: &str = "warn";
+
+// 按 `--log-level` 和 `--log-json` 初始化输出到标准错误的日志
+// --log-level 优先于RUST_LOG环境变量，--log-json 时每行输出一个JSON对象
+pub fn init(args: &CommonArgs) -> Result<(), String> {
+    let filter = match &args.log_level {
+        Some(level) => {
+            EnvFilter::try_new(level).map_err(|e| format!("invalid `--log-level {level}`: {e}"))?
+        }
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER))
+        }
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    let result = if args.log_json {
+        builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init()
+    } else {
+        builder.try_init()
+    };
+    result.map_err(|e| e.to_string())
+}
+
+// 记录连接建立、关闭和拨号失败等Swarm事件，网络行为事件由各程序自行处理
+pub fn swarm_event<B, E: Debug>(event: &SwarmEvent<B, E>) {
+    match event {
+        SwarmEvent::ConnectionEstablished {
+            peer_id,
+            endpoint,
+            num_established,
+            ..
+        } => info!(
+            peer = %peer_id,
+            address = %endpoint.get_remote_address(),
+            dialer = endpoint.is_dialer(),
+            connections = num_established.get(),
+            "Connection established"
+        ),
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            num_established,
+            cause,
+            ..
+        } => info!(
+            peer = %peer_id,
+            connections = num_established,
+            cause = ?cause,
+            "Connection closed"
+        ),
+        SwarmEvent::OutgoingConnectionError { peer_id, error } => {
+            warn!(peer = ?peer_id, %error, "Outgoing connection failed")
+        }
+        SwarmEvent::IncomingConnectionError {
+            send_back_addr,
+            error,
+            ..
+        } => {
+            warn!(address = %send_back_addr, %error, "Incoming connection failed")
+        }
+        SwarmEvent::Dialing(peer_id) => debug!(peer = %peer_id, "Dialing"),
+        SwarmEvent::ListenerError { error, .. } => warn!(%error, "Listener error"),
+        _ => {}
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+// 输出格式，通过 `--output json` 选择每行输出一个JSON对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl Output {
+    // 文本格式下输出text，json格式下输出带type字段的fields
+    pub fn emit(&self, kind: &str, fields: Value, text: impl Display) {
+        match self {
+            Output::Text => println!("{text}"),
+            Output::Json => {
+                let mut object = match fields {
+                    Value::Object(object) => object,
+                    _ => Default::default(),
+                };
+                object.insert("type".to_string(), json!(kind));
+                println!("{}", Value::Object(object));
+            }
+        }
+    }
+
+    // 文本格式下输出到标准错误，json格式下输出error记录
+    pub fn error(&self, message: impl Display) {
+        match self {
+            Output::Text => eprintln!("{message}"),
+            Output::Json => println!("{}", json!({ "type": "error", "message": message.to_string() })),
+        }
+    }
+}